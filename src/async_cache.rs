@@ -0,0 +1,173 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use tokio::sync::OnceCell;
+
+use crate::cache::Cache;
+use crate::statistics::StatsSnapshot;
+
+
+/// A load in flight with the timestamp at which its loader started
+type PendingLoad<V> = Arc<OnceCell<(V, i64)>>;
+
+/// A cache for async code that deduplicates concurrent loads of the same missing key.
+///
+/// Callers awaiting `get_with` for a key that is not cached share a single load future, so a
+/// popular key that just expired causes one request to the backend instead of a stampede. The
+/// loaded value is stored in the underlying slab-backed `Cache` once the load completes.
+///
+/// # Examples
+/// ```rust
+/// use slabcache::AsyncCache;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let cache = AsyncCache::new(128);
+///
+/// let value = cache.get_with("foo", || async { "bar" }).await;
+/// assert_eq!(value, "bar");
+/// assert_eq!(cache.get(&"foo"), Some("bar"));
+/// # }
+/// ```
+pub struct AsyncCache<K, V> {
+    /// The underlying cache
+    cache: Mutex<Cache<K, V>>,
+    /// The loads currently in flight, shared by every caller waiting for the same key
+    pending: Mutex<HashMap<K, PendingLoad<V>>>,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> AsyncCache<K, V> {
+    /// Create a cache that can hold `capacity` elements
+    ///
+    /// # Panics
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        Self::from_cache(Cache::new(capacity))
+    }
+
+    /// Wrap a preconfigured cache, e.g. to set expiration policies or listeners
+    pub fn from_cache(cache: Cache<K, V>) -> Self {
+        AsyncCache {
+            cache: Mutex::new(cache),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get a copy of a value from the cache and update its access time and frequency
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        lock(&self.cache).get(key).cloned()
+    }
+
+    /// Return the cached value for `key`, loading it with `init` on a miss
+    ///
+    /// Only one `init` future runs per missing key at a time, every other caller waits for its
+    /// result. If the loading caller is cancelled, one of the waiting callers takes over with its
+    /// own `init`.
+    pub async fn get_with<F, Fut>(&self, key: K, init: F) -> V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        if let Some(value) = self.get(&key) {
+            return value;
+        }
+        let cell = {
+            let mut pending = lock(&self.pending);
+            // Another caller may have completed the load since the miss above
+            if let Some(value) = lock(&self.cache).peek(&key) {
+                return value.clone();
+            }
+            pending.entry(key.clone()).or_default().clone()
+        };
+        let (value, started) = cell
+            .get_or_init(|| async {
+                let started = lock(&self.cache).now();
+                (init().await, started)
+            })
+            .await
+            .clone();
+
+        // The first caller to get here publishes the value, the others find the load already gone
+        let mut pending = lock(&self.pending);
+        if pending.get(&key).is_some_and(|current| Arc::ptr_eq(current, &cell)) {
+            pending.remove(&key);
+            lock(&self.cache).insert_loaded(key, value.clone(), started);
+        }
+        value
+    }
+
+    /// Insert a value into the cache, returning the previous value of the key
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        lock(&self.cache).insert(key, value)
+    }
+
+    /// Remove an element from the cache and return its value
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        lock(&self.cache).remove(key)
+    }
+
+    /// A copy of the statistics of the underlying cache
+    pub fn statistics(&self) -> StatsSnapshot {
+        lock(&self.cache).statistics()
+    }
+}
+
+/// Lock a mutex, recovering it if another thread panicked while holding the lock
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_async_get_with() {
+    let cache = AsyncCache::new(2);
+
+    assert_eq!(cache.get_with("key1", || async { "value1" }).await, "value1");
+    assert_eq!(cache.get_with("key1", || async { "other" }).await, "value1");
+    assert_eq!(cache.get(&"key1"), Some("value1"));
+    assert!(lock(&cache.pending).is_empty());
+}
+
+#[cfg(test)]
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_async_single_load() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    let cache = Arc::new(AsyncCache::new(2));
+    let loads = Arc::new(AtomicUsize::new(0));
+
+    let callers: Vec<_> = (0..8)
+        .map(|_| {
+            let cache = cache.clone();
+            let loads = loads.clone();
+            tokio::spawn(async move {
+                cache
+                    .get_with("key", || async move {
+                        loads.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        42
+                    })
+                    .await
+            })
+        })
+        .collect();
+    for caller in callers {
+        assert_eq!(caller.await.unwrap(), 42);
+    }
+
+    assert_eq!(loads.load(Ordering::SeqCst), 1);
+    assert_eq!(cache.get(&"key"), Some(42));
+}
@@ -0,0 +1,391 @@
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::cache::{Cache, EvictionListener, RemovalCause, Weigher};
+use crate::clock::Clock;
+use crate::policy::Policy;
+use crate::sink::StatsSink;
+
+
+/// A builder collecting the configuration of a `Cache` and validating it before the cache is
+/// created.
+///
+/// # Examples
+/// ```rust
+/// use std::time::Duration;
+/// use slabcache::{CacheBuilder, Policy};
+///
+/// let mut cache = CacheBuilder::new(1024)
+///     .policy(Policy::Slru { protected_ratio: 0.8 })
+///     .expire_after_write(Duration::from_secs(60))
+///     .weigher(|_key: &&str, value: &String| value.len() as u64)
+///     .max_weight(1 << 20)
+///     .build()
+///     .unwrap();
+///
+/// cache.insert("foo", "bar".to_string());
+/// assert_eq!(cache.weight(), 3);
+/// ```
+pub struct CacheBuilder<K, V, S = RandomState> {
+    capacity: usize,
+    low_watermark: Option<usize>,
+    hasher: S,
+    policy: Policy,
+    tiny_lfu: bool,
+    estimated_frequencies: bool,
+    expire_after_write: Option<Duration>,
+    expire_after_access: Option<Duration>,
+    negative_ttl: Option<Duration>,
+    refresh_ahead: Option<f64>,
+    ttl_jitter: Option<Duration>,
+    eviction_history: Option<usize>,
+    clock: Option<Arc<dyn Clock>>,
+    listener: Option<EvictionListener<K, V>>,
+    weigher: Option<Weigher<K, V>>,
+    max_weight: Option<u64>,
+    /// The length and number of intervals of the windowed statistics, if enabled
+    window: Option<(Duration, usize)>,
+    #[cfg(feature = "metrics")]
+    metrics_name: Option<String>,
+    sink: Option<Box<dyn StatsSink>>,
+}
+
+/// The reason a `CacheBuilder` rejected its configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildError {
+    /// The capacity is zero, so the cache could never hold an element
+    ZeroCapacity,
+    /// A maximum weight was set without a weigher to compute the weight of the elements
+    MaxWeightWithoutWeigher,
+    /// The protected ratio of the SLRU policy is not between 0 and 1
+    InvalidProtectedRatio,
+    /// The small queue ratio of the S3-FIFO policy is not between 0 and 1
+    InvalidSmallRatio,
+    /// The HIR ratio of the LIRS policy is not between 0 and 1
+    InvalidHirRatio,
+    /// The refresh-ahead fraction is not between 0 and 1
+    InvalidRefreshAhead,
+    /// The low watermark exceeds the capacity
+    InvalidLowWatermark,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::ZeroCapacity => write!(f, "the capacity of a cache must not be zero"),
+            BuildError::MaxWeightWithoutWeigher => write!(f, "a maximum weight requires a weigher"),
+            BuildError::InvalidProtectedRatio => write!(f, "the protected ratio must be between 0 and 1"),
+            BuildError::InvalidSmallRatio => write!(f, "the small queue ratio must be between 0 and 1"),
+            BuildError::InvalidHirRatio => write!(f, "the HIR ratio must be between 0 and 1"),
+            BuildError::InvalidRefreshAhead => write!(f, "the refresh-ahead fraction must be between 0 and 1"),
+            BuildError::InvalidLowWatermark => write!(f, "the low watermark must not exceed the capacity"),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl<K: Hash + Eq + Clone, V> CacheBuilder<K, V> {
+    /// Start configuring a cache that can hold `capacity` elements
+    pub fn new(capacity: usize) -> Self {
+        CacheBuilder {
+            capacity,
+            low_watermark: None,
+            hasher: RandomState::new(),
+            policy: Policy::default(),
+            tiny_lfu: false,
+            estimated_frequencies: false,
+            expire_after_write: None,
+            expire_after_access: None,
+            negative_ttl: None,
+            refresh_ahead: None,
+            ttl_jitter: None,
+            eviction_history: None,
+            clock: None,
+            listener: None,
+            weigher: None,
+            max_weight: None,
+            window: None,
+            #[cfg(feature = "metrics")]
+            metrics_name: None,
+            sink: None,
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, S: BuildHasher> CacheBuilder<K, V, S> {
+    /// Hash the keys with `hasher` instead of the std `RandomState`
+    pub fn hasher<H: BuildHasher>(self, hasher: H) -> CacheBuilder<K, V, H> {
+        CacheBuilder {
+            capacity: self.capacity,
+            low_watermark: self.low_watermark,
+            hasher,
+            policy: self.policy,
+            tiny_lfu: self.tiny_lfu,
+            estimated_frequencies: self.estimated_frequencies,
+            expire_after_write: self.expire_after_write,
+            expire_after_access: self.expire_after_access,
+            negative_ttl: self.negative_ttl,
+            refresh_ahead: self.refresh_ahead,
+            ttl_jitter: self.ttl_jitter,
+            eviction_history: self.eviction_history,
+            clock: self.clock,
+            listener: self.listener,
+            weigher: self.weigher,
+            max_weight: self.max_weight,
+            window: self.window,
+            #[cfg(feature = "metrics")]
+            metrics_name: self.metrics_name,
+            sink: self.sink,
+        }
+    }
+
+    /// See `Cache::policy`
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// See `Cache::tiny_lfu`
+    pub fn tiny_lfu(mut self) -> Self {
+        self.tiny_lfu = true;
+        self
+    }
+
+    /// See `Cache::estimated_frequencies`
+    pub fn estimated_frequencies(mut self) -> Self {
+        self.estimated_frequencies = true;
+        self
+    }
+
+    /// See `Cache::expire_after_write`
+    pub fn expire_after_write(mut self, duration: Duration) -> Self {
+        self.expire_after_write = Some(duration);
+        self
+    }
+
+    /// See `Cache::expire_after_access`
+    pub fn expire_after_access(mut self, duration: Duration) -> Self {
+        self.expire_after_access = Some(duration);
+        self
+    }
+
+    /// See `Cache::negative_ttl`
+    pub fn negative_ttl(mut self, ttl: Duration) -> Self {
+        self.negative_ttl = Some(ttl);
+        self
+    }
+
+    /// See `Cache::refresh_ahead`, the fraction has to be between 0 and 1
+    pub fn refresh_ahead(mut self, fraction: f64) -> Self {
+        self.refresh_ahead = Some(fraction);
+        self
+    }
+
+    /// See `Cache::ttl_jitter`
+    pub fn ttl_jitter(mut self, jitter: Duration) -> Self {
+        self.ttl_jitter = Some(jitter);
+        self
+    }
+
+    /// See `Cache::eviction_history`
+    pub fn eviction_history(mut self, len: usize) -> Self {
+        self.eviction_history = Some(len);
+        self
+    }
+
+    /// See `Cache::low_watermark`, the watermark must not exceed the capacity
+    pub fn low_watermark(mut self, len: usize) -> Self {
+        self.low_watermark = Some(len);
+        self
+    }
+
+    /// See `Cache::clock`
+    pub fn clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+
+    /// See `Cache::stats_sink`
+    pub fn stats_sink<T: StatsSink + 'static>(mut self, sink: T) -> Self {
+        self.sink = Some(Box::new(sink));
+        self
+    }
+
+    /// See `Cache::metrics`
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, name: impl Into<String>) -> Self {
+        self.metrics_name = Some(name.into());
+        self
+    }
+
+    /// See `Cache::eviction_listener`
+    pub fn eviction_listener<F>(mut self, listener: F) -> Self
+    where
+        F: FnMut(K, V, RemovalCause) + Send + 'static,
+    {
+        self.listener = Some(Box::new(listener));
+        self
+    }
+
+    /// See `Cache::weigher`
+    pub fn weigher<F>(mut self, weigher: F) -> Self
+    where
+        F: Fn(&K, &V) -> u64 + Send + Sync + 'static,
+    {
+        self.weigher = Some(Arc::new(weigher));
+        self
+    }
+
+    /// See `Cache::max_weight`, requires a weigher
+    pub fn max_weight(mut self, max_weight: u64) -> Self {
+        self.max_weight = Some(max_weight);
+        self
+    }
+
+    /// See `Cache::windowed_statistics`
+    pub fn windowed_statistics(mut self, interval: Duration, intervals: usize) -> Self {
+        self.window = Some((interval, intervals));
+        self
+    }
+
+    /// Validate the configuration and create the cache
+    pub fn build(self) -> Result<Cache<K, V, S>, BuildError> {
+        if self.capacity == 0 {
+            return Err(BuildError::ZeroCapacity);
+        }
+        if self.max_weight.is_some() && self.weigher.is_none() {
+            return Err(BuildError::MaxWeightWithoutWeigher);
+        }
+        if let Policy::Slru { protected_ratio } = self.policy {
+            if !(0.0..=1.0).contains(&protected_ratio) {
+                return Err(BuildError::InvalidProtectedRatio);
+            }
+        }
+        if let Policy::S3Fifo { small_ratio } = self.policy {
+            if !(0.0..=1.0).contains(&small_ratio) {
+                return Err(BuildError::InvalidSmallRatio);
+            }
+        }
+        if let Policy::Lirs { hir_ratio } = self.policy {
+            if !(0.0..=1.0).contains(&hir_ratio) {
+                return Err(BuildError::InvalidHirRatio);
+            }
+        }
+
+        if self.refresh_ahead.is_some_and(|fraction| !(0.0..=1.0).contains(&fraction)) {
+            return Err(BuildError::InvalidRefreshAhead);
+        }
+        if self.low_watermark.is_some_and(|len| len > self.capacity) {
+            return Err(BuildError::InvalidLowWatermark);
+        }
+
+        let mut cache = Cache::with_hasher(self.capacity, self.hasher).policy(self.policy);
+        if self.tiny_lfu {
+            cache = cache.tiny_lfu();
+        }
+        if self.estimated_frequencies {
+            cache = cache.estimated_frequencies();
+        }
+        if let Some(duration) = self.expire_after_write {
+            cache = cache.expire_after_write(duration);
+        }
+        if let Some(duration) = self.expire_after_access {
+            cache = cache.expire_after_access(duration);
+        }
+        if let Some(ttl) = self.negative_ttl {
+            cache = cache.negative_ttl(ttl);
+        }
+        if let Some(fraction) = self.refresh_ahead {
+            cache = cache.refresh_ahead(fraction);
+        }
+        if let Some(jitter) = self.ttl_jitter {
+            cache = cache.ttl_jitter(jitter);
+        }
+        if let Some(len) = self.eviction_history {
+            cache = cache.eviction_history(len);
+        }
+        if let Some(len) = self.low_watermark {
+            cache = cache.low_watermark(len);
+        }
+        if let Some(max_weight) = self.max_weight {
+            cache = cache.max_weight(max_weight);
+        }
+        if let Some((interval, intervals)) = self.window {
+            cache = cache.windowed_statistics(interval, intervals);
+        }
+        if let Some(clock) = self.clock {
+            cache.set_clock(clock);
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(name) = self.metrics_name {
+            cache = cache.metrics(name);
+        }
+        cache.set_listener(self.listener);
+        cache.set_sink(self.sink);
+        cache.set_weigher(self.weigher);
+        Ok(cache)
+    }
+}
+
+
+#[cfg(test)]
+#[test]
+fn test_builder() {
+    use crate::policy::Priority;
+
+    let mut cache = CacheBuilder::new(2)
+        .policy(Policy::Clock)
+        .weigher(|_, value: &u64| *value)
+        .max_weight(10)
+        .build()
+        .unwrap();
+    cache.insert_with_priority("key1", 6, Priority::High);
+    cache.insert("key2", 3);
+    cache.insert("key3", 4);
+    assert_eq!(cache.peek(&"key1"), Some(&6));
+    assert_eq!(cache.peek(&"key2"), None);
+    assert_eq!(cache.weight(), 10);
+}
+
+#[test]
+fn test_builder_validation() {
+    assert_eq!(CacheBuilder::<&str, u64>::new(0).build().err(), Some(BuildError::ZeroCapacity));
+    assert_eq!(
+        CacheBuilder::<&str, u64>::new(1).max_weight(10).build().err(),
+        Some(BuildError::MaxWeightWithoutWeigher)
+    );
+    assert_eq!(
+        CacheBuilder::<&str, u64>::new(1)
+            .policy(Policy::Slru { protected_ratio: 1.5 })
+            .build()
+            .err(),
+        Some(BuildError::InvalidProtectedRatio)
+    );
+    assert_eq!(
+        CacheBuilder::<&str, u64>::new(1)
+            .policy(Policy::S3Fifo { small_ratio: -0.1 })
+            .build()
+            .err(),
+        Some(BuildError::InvalidSmallRatio)
+    );
+    assert_eq!(
+        CacheBuilder::<&str, u64>::new(1)
+            .policy(Policy::Lirs { hir_ratio: 2.0 })
+            .build()
+            .err(),
+        Some(BuildError::InvalidHirRatio)
+    );
+    assert_eq!(
+        CacheBuilder::<&str, u64>::new(1).refresh_ahead(-0.1).build().err(),
+        Some(BuildError::InvalidRefreshAhead)
+    );
+    assert_eq!(
+        CacheBuilder::<&str, u64>::new(1).low_watermark(2).build().err(),
+        Some(BuildError::InvalidLowWatermark)
+    );
+    assert_eq!(BuildError::ZeroCapacity.to_string(), "the capacity of a cache must not be zero");
+}
@@ -1,57 +1,739 @@
-use std::collections::{HashMap, VecDeque};
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet, TryReserveError};
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use slab::Slab;
 
-use chrono::Utc;
-use crate::statistics::Statistics;
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
+use crate::builder::{BuildError, CacheBuilder};
+use crate::clock::{Clock, SystemClock};
+use crate::entry::{Entry, OccupiedEntry, OccupiedError, Op, VacantEntry};
+#[cfg(feature = "events")]
+use crate::events::CacheEvent;
+use crate::ghost::GhostQueue;
+use crate::index::KeyIndex;
+use crate::lirs::LirsStack;
+use crate::list::{Linked, Links, List, ListIter};
+use crate::namespace::Namespace;
+use crate::policy::{Policy, Priority, PRIORITIES};
+use crate::sink::{CacheMetric, StatsSink};
+use crate::sketch::CountMinSketch;
+use crate::statistics::{Statistics, StatsSnapshot};
+use crate::trace::{AccessKind, TraceRecord, TraceRecorder};
 
 
+/// Convert a UTC UNIX timestamp in us into a system time
+fn system_time(timestamp: i64) -> SystemTime {
+    let offset = Duration::from_micros(timestamp.unsigned_abs());
+    if timestamp >= 0 {
+        SystemTime::UNIX_EPOCH + offset
+    } else {
+        SystemTime::UNIX_EPOCH - offset
+    }
+}
+
+/// Convert a UTC UNIX timestamp in us into a date and time
+#[cfg(feature = "chrono")]
+fn datetime(timestamp: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_micros(timestamp).unwrap_or_default()
+}
+
+/// The timestamp in us at which `duration` will have elapsed after `now`
+fn deadline(now: i64, duration: Duration) -> i64 {
+    now.saturating_add(i64::try_from(duration.as_micros()).unwrap_or(i64::MAX))
+}
+
+/// The number of slab slots the random policy samples for a victim before it falls back to the
+/// front of the usage list
+const RANDOM_PROBES: usize = 32;
+
+/// A pseudo-random number in [0, 1) derived from `seed` with the SplitMix64 finalizer
+fn random_fraction(seed: u64) -> f64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
 /// The metadata associated with each element in the cache
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Metadata<K> {
+    /// The time the element was inserted as a UTC UNIX timestamp in us
+    created_at: i64,
+    /// The position of the element in the order of insertion into the cache
+    #[cfg_attr(feature = "serde", serde(default))]
+    sequence: u64,
     /// The last time the element was accessed as a UTC UNIX timestamp in us
     last_accessed: i64,
     /// The number of times the element has been accessed
     frequency: usize,
     /// The number of cache hits for the element
     hits: usize,
+    /// The time after which the element is considered expired as a UTC UNIX timestamp in us
+    expires_at: Option<i64>,
+    /// The expiration time set by the TTL or the expire-after-write policy when the value was last written
+    write_deadline: Option<i64>,
+    /// The time after which a hit flags the element for a refresh-ahead reload, cleared once flagged
+    #[cfg_attr(feature = "serde", serde(default))]
+    refresh_at: Option<i64>,
+    /// The weight of the element computed when its value was last written
+    weight: u64,
+    /// Whether the element is in the protected segment of an SLRU cache
+    protected: bool,
+    /// Whether the element was accessed since the hand of a CLOCK cache last passed it
+    referenced: bool,
+    /// The number of accesses, up to 3, since an S3-FIFO cache last queued the element
+    #[cfg_attr(feature = "serde", serde(default))]
+    fifo_hits: u8,
+    /// Whether the element is exempt from eviction
+    pinned: bool,
+    /// Whether the element records that its key does not exist upstream
+    #[cfg_attr(feature = "serde", serde(default))]
+    negative: bool,
+    /// Whether the value was written after it was last stored in the secondary tier of a
+    /// `TieredCache`
+    #[cfg_attr(feature = "serde", serde(default))]
+    dirty: bool,
+    /// Whether the element was invalidated by `Cache::invalidate_entries_if` and only waits to be
+    /// removed like an expired element
+    #[cfg_attr(feature = "serde", serde(default))]
+    invalidated: bool,
+    /// The priority deciding which usage lists the element is linked into
+    priority: Priority,
+    /// The id of the namespace the element belongs to, see `Cache::namespace`
+    #[cfg_attr(feature = "serde", serde(default))]
+    namespace: Option<u32>,
+    /// The tags the element was inserted with, see `Cache::insert_tagged`
+    #[cfg_attr(feature = "serde", serde(default))]
+    tags: Vec<Arc<str>>,
+    /// How long the last load of the value by a loader API took in us
+    #[cfg_attr(feature = "serde", serde(default))]
+    load_duration: Option<u64>,
     /// The user-provided key for the element
     user_key: K,
 }
-pub struct CacheIter<'a, K, V> {
-    usage: std::collections::vec_deque::Iter<'a, usize>,
-    cache: &'a Cache<K, V>,
+
+impl<K> Metadata<K> {
+    /// The metadata of a new element inserted at timestamp `now`
+    pub(crate) fn new(key: K, now: i64, weight: u64, priority: Priority) -> Self {
+        Metadata {
+            created_at: now,
+            sequence: 0,
+            last_accessed: now,
+            frequency: 0,
+            hits: 0,
+            expires_at: None,
+            write_deadline: None,
+            refresh_at: None,
+            weight,
+            protected: false,
+            referenced: false,
+            fifo_hits: 0,
+            pinned: false,
+            negative: false,
+            dirty: true,
+            invalidated: false,
+            priority,
+            namespace: None,
+            tags: Vec::new(),
+            load_duration: None,
+            user_key: key,
+        }
+    }
+
+    /// The time the element was inserted, replacing its value does not reset it
+    pub fn created_at(&self) -> SystemTime {
+        system_time(self.created_at)
+    }
+
+    /// The position of the element in the order of insertion, see `Cache::iter_insertion_order`
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// The time the element was inserted as a UTC date and time
+    #[cfg(feature = "chrono")]
+    pub fn created_at_utc(&self) -> DateTime<Utc> {
+        datetime(self.created_at)
+    }
+
+    /// The last time the element was read or written
+    pub fn last_accessed(&self) -> SystemTime {
+        system_time(self.last_accessed)
+    }
+
+    /// The last time the element was read or written as a UTC date and time
+    #[cfg(feature = "chrono")]
+    pub fn last_accessed_utc(&self) -> DateTime<Utc> {
+        datetime(self.last_accessed)
+    }
+
+    /// The number of times the element has been accessed
+    ///
+    /// The estimate of the sketch if the cache uses `Cache::estimated_frequencies`.
+    pub fn frequency(&self) -> usize {
+        self.frequency
+    }
+
+    /// The number of cache hits for the element
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// The key of the element
+    pub fn user_key(&self) -> &K {
+        &self.user_key
+    }
+
+    /// Whether the value has not been written to the secondary store of a `TieredCache` yet
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// The tags the element was inserted with
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.tags.iter().map(|tag| &**tag)
+    }
+
+    /// How long the last load of the value took, if it was loaded by `Cache::get_or_insert_with`,
+    /// `Cache::try_get_or_insert_with`, `Entry::or_insert_with`, `Cache::get_with_beta`,
+    /// `ShardedCache::get_or_load` or `AsyncCache::get_with`
+    pub fn load_duration(&self) -> Option<Duration> {
+        self.load_duration.map(Duration::from_micros)
+    }
+
+    /// Record a hit at timestamp `now`, counting it towards the frequency if `counted`
+    pub(crate) fn record_hit(&mut self, now: i64, counted: bool) {
+        self.record_touch(now, counted);
+        self.hits += 1;
+    }
+
+    /// Record an access that did not read the value at timestamp `now`
+    fn record_touch(&mut self, now: i64, counted: bool) {
+        self.last_accessed = now;
+        if counted {
+            self.frequency += 1;
+        }
+    }
+
+    /// Record that a new value was written at timestamp `now`
+    pub(crate) fn record_write(&mut self, now: i64) {
+        self.last_accessed = now;
+        self.negative = false;
+        self.dirty = true;
+    }
+
+    /// The time the element has spent in the cache at timestamp `now`
+    fn age(&self, now: i64) -> Duration {
+        Duration::from_micros(u64::try_from(now.saturating_sub(self.created_at)).unwrap_or(0))
+    }
+}
+
+/// Where an insert stores its value, decided before anything is stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Placement {
+    /// The key is present at this index in the slab and its value is replaced
+    Occupied(usize),
+    /// The key is new and admitted
+    Vacant,
+    /// The key is new and rejected by the TinyLFU filter
+    Rejected,
+}
+
+/// What becomes of an element of a batch given to `Cache::warm`, decided before anything is stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BatchFate {
+    /// The element is stored
+    Stored,
+    /// A later element of the batch with the same key replaces it
+    Replaced,
+    /// The later elements of the batch fill the cache, so the element would be evicted right away
+    Evicted,
+}
+
+/// An opaque handle to an element, returned by `Cache::insert_with_token` and `Cache::token`
+///
+/// Accessing an element by its token skips hashing the key. The generation of a slot is bumped
+/// whenever an element leaves it, so a token never resolves to another element that reuses the
+/// slot of the one it was created for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Token {
+    /// The index of the element in the slab
+    index: usize,
+    /// The generation of the slot when the token was created
+    generation: u64,
+}
+
+/// A summary of how an element has been used, see `Cache::entry_stats`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntryStats {
+    frequency: usize,
+    hits: usize,
+    last_accessed: i64,
+    age: Duration,
+    position: usize,
+}
+
+impl EntryStats {
+    /// The number of times the element has been accessed
+    pub fn frequency(&self) -> usize {
+        self.frequency
+    }
+
+    /// The number of cache hits for the element
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// The last time the element was read or written
+    pub fn last_accessed(&self) -> SystemTime {
+        system_time(self.last_accessed)
+    }
+
+    /// The last time the element was read or written as a UTC date and time
+    #[cfg(feature = "chrono")]
+    pub fn last_accessed_utc(&self) -> DateTime<Utc> {
+        datetime(self.last_accessed)
+    }
+
+    /// The time since the element was inserted
+    pub fn age(&self) -> Duration {
+        self.age
+    }
+
+    /// The number of elements that would be evicted before this one, not counting expired ones
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+/// What the cache knew about an element when it was evicted, see `Cache::eviction_history`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvictionRecord {
+    evicted_at: i64,
+    age: Duration,
+    hits: usize,
+    weight: u64,
+}
+
+impl EvictionRecord {
+    /// The time the element was evicted
+    pub fn evicted_at(&self) -> SystemTime {
+        system_time(self.evicted_at)
+    }
+
+    /// The time the element spent in the cache
+    pub fn age(&self) -> Duration {
+        self.age
+    }
+
+    /// The number of cache hits for the element
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// The weight of the element
+    pub fn weight(&self) -> u64 {
+        self.weight
+    }
+}
+
+/// The state of an element at the time of `Cache::snapshot`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntrySnapshot {
+    frequency: usize,
+    hits: usize,
+    created_at: i64,
+    last_accessed: i64,
+    expires_at: Option<i64>,
+    weight: u64,
+    priority: Priority,
+    position: usize,
+}
+
+impl EntrySnapshot {
+    /// The number of times the element had been accessed
+    pub fn frequency(&self) -> usize {
+        self.frequency
+    }
+
+    /// The number of cache hits for the element
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// The time the element was inserted
+    pub fn created_at(&self) -> SystemTime {
+        system_time(self.created_at)
+    }
+
+    /// The last time the element was read or written
+    pub fn last_accessed(&self) -> SystemTime {
+        system_time(self.last_accessed)
+    }
+
+    /// The last time the element was read or written as a UTC date and time
+    #[cfg(feature = "chrono")]
+    pub fn last_accessed_utc(&self) -> DateTime<Utc> {
+        datetime(self.last_accessed)
+    }
+
+    /// The time the element expires, if it has an expiration time
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.expires_at.map(system_time)
+    }
+
+    /// The weight of the element
+    pub fn weight(&self) -> u64 {
+        self.weight
+    }
+
+    /// The priority of the element
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    /// The number of elements that would have been evicted before this one
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+/// A value stored in the slab together with its metadata and its links in the usage lists
+#[derive(Clone)]
+pub(crate) struct Node<K, V> {
+    value: V,
+    metadata: Metadata<K>,
+    links: Links,
+}
+
+impl<K, V> Linked for Node<K, V> {
+    fn links(&self) -> &Links {
+        &self.links
+    }
+
+    fn links_mut(&mut self) -> &mut Links {
+        &mut self.links
+    }
+}
+
+/// The indices of the elements in eviction order: for every priority from the lowest to the
+/// highest, its usage list followed by its protected segment
+type EvictionOrder<'a, K, V> =
+    std::iter::Flatten<std::array::IntoIter<ListIter<'a, Slab<Node<K, V>>>, { 2 * PRIORITIES }>>;
+
+/// An iterator over the elements of the cache in eviction order, from the least to the most
+/// recently used element
+pub struct CacheIter<'a, K, V, S = RandomState> {
+    usage: EvictionOrder<'a, K, V>,
+    /// The number of elements left, since a flattened iterator cannot tell
+    remaining: usize,
+    cache: &'a Cache<K, V, S>,
+}
+
+/// An iterator over the keys of the cache from the least to the most recently used element
+pub struct Keys<'a, K, V, S = RandomState> {
+    inner: CacheIter<'a, K, V, S>,
+}
+
+impl<'a, K, V, S> Iterator for Keys<'a, K, V, S> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, _, _)| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V, S> DoubleEndedIterator for Keys<'_, K, V, S> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(key, _, _)| key)
+    }
+}
+
+/// An iterator over the values of the cache from the least to the most recently used element
+pub struct Values<'a, K, V, S = RandomState> {
+    inner: CacheIter<'a, K, V, S>,
+}
+
+impl<'a, K, V, S> Iterator for Values<'a, K, V, S> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, value, _)| value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V, S> DoubleEndedIterator for Values<'_, K, V, S> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(_, value, _)| value)
+    }
+}
+
+/// An owning iterator over the elements of the cache from the least to the most recently used
+/// element
+pub struct IntoIter<K, V> {
+    slab: Slab<Node<K, V>>,
+    order: std::vec::IntoIter<usize>,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.slab.remove(self.order.next()?);
+        Some((node.metadata.user_key, node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.order.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let node = self.slab.remove(self.order.next_back()?);
+        Some((node.metadata.user_key, node.value))
+    }
+}
+
+/// A draining iterator over the elements of the cache from the least to the most recently used
+/// element, created by `Cache::drain`
+pub struct Drain<'a, K: Hash + Eq + Clone, V, S: BuildHasher = RandomState> {
+    cache: &'a mut Cache<K, V, S>,
+}
+
+impl<K: Hash + Eq + Clone, V, S: BuildHasher> Iterator for Drain<'_, K, V, S> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (value, metadata) = self.cache.pop_lru_node()?;
+        Some((metadata.user_key, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.cache.len()))
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, S: BuildHasher> Drop for Drain<'_, K, V, S> {
+    fn drop(&mut self) {
+        self.for_each(drop);
+    }
+}
+
+/// A mutable iterator over the elements of the cache in storage order
+pub struct CacheIterMut<'a, K, V> {
+    nodes: slab::IterMut<'a, Node<K, V>>,
+}
+
+impl<'a, K, V> Iterator for CacheIterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V, &'a Metadata<K>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (_, node) = self.nodes.next()?;
+        Some((&node.metadata.user_key, &mut node.value, &node.metadata))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.nodes.size_hint()
+    }
 }
 
-pub struct CacheIterFrequency<'a, K, V> {
-    keys: std::vec::IntoIter<usize>,
-    cache: &'a Cache<K, V>,
+/// An iterator over the elements of the cache sorted by their metadata, created by
+/// `Cache::iter_sorted` and the methods built on it
+///
+/// The elements are kept in a heap and only sorted as far as they are consumed, so taking the
+/// first k of n elements takes O(n + k log n) time.
+pub struct SortedIter<'a, K, V, S = RandomState> {
+    /// The sort keys of the remaining elements with their index in the slab, the greatest key
+    /// comes next
+    heap: BinaryHeap<([i64; SORT_KEYS + 1], usize)>,
+    cache: &'a Cache<K, V, S>,
 }
 
-impl<'a, K, V> Iterator for CacheIterFrequency<'a, K, V> {
+impl<'a, K, V, S> Iterator for SortedIter<'a, K, V, S> {
     type Item = (&'a K, &'a V, &'a Metadata<K>);
 
     fn next(&mut self) -> Option<Self::Item> {
-        let key = self.keys.next()?;
-        let value = self.cache.slab.get(key)?;
-        let metadata = self.cache.key_meta.get(&key)?;
-        Some((&metadata.user_key, value, metadata))
+        let (_, index) = self.heap.pop()?;
+        let node = self.cache.slab.get(index)?;
+        Some((&node.metadata.user_key, &node.value, &node.metadata))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.heap.len(), Some(self.heap.len()))
     }
 }
 
+impl<K, V, S> ExactSizeIterator for SortedIter<'_, K, V, S> {}
+
 pub enum SortOrder {
     Ascending,
     Descending,
 }
 
+/// The number of distinct `SortKey`s
+const SORT_KEYS: usize = 4;
+
+/// A property of the elements that `Cache::iter_sorted` sorts by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// The access frequency, see `Metadata::frequency`
+    Frequency,
+    /// The number of cache hits
+    Hits,
+    /// The time since the element was inserted, youngest first when ascending
+    Age,
+    /// The last access time, oldest first when ascending
+    LastAccessed,
+}
+
+/// The reason an element left the cache, reported to the eviction listener
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemovalCause {
+    /// The element was evicted to make room for another one
+    Capacity,
+    /// The element was past its expiration time
+    Expired,
+    /// The element was removed explicitly by the user
+    Explicit,
+    /// The value was overwritten by a new value for the same key
+    Replaced,
+}
+
+/// An inconsistency between the internal structures of a cache found by `Cache::validate`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvariantViolation {
+    /// The links of the usage list or the protected segment of a priority are broken
+    CorruptList { priority: Priority, protected: bool },
+    /// The element at the slab index is not linked exactly once into the list of its priority and
+    /// segment
+    MisplacedElement { index: usize },
+    /// The key index maps a hash to a vacant slab index or to an element whose key has another hash
+    StaleKey { hash: u64, index: usize },
+    /// Looking up the key of the element at the slab index finds no or another element
+    MissingKey { index: usize },
+    /// The key index holds another number of keys than there are elements
+    KeyCountMismatch { keys: usize, elements: usize },
+    /// The expiration index and the expiration time of the element at the slab index disagree
+    ExpiryMismatch { index: usize },
+    /// The tag index and the tags of the element at the slab index disagree
+    TagMismatch { index: usize },
+    /// The recorded total weight differs from the sum of the weights of the elements
+    WeightMismatch { recorded: u64, actual: u64 },
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvariantViolation::CorruptList { priority, protected: false } => {
+                write!(f, "the usage list of priority {priority:?} is corrupt")
+            }
+            InvariantViolation::CorruptList { priority, protected: true } => {
+                write!(f, "the protected segment of priority {priority:?} is corrupt")
+            }
+            InvariantViolation::MisplacedElement { index } => {
+                write!(f, "the element at {index} is not linked into its list exactly once")
+            }
+            InvariantViolation::StaleKey { hash, index } => {
+                write!(f, "the key index maps hash {hash:#x} to {index}, which holds no element with it")
+            }
+            InvariantViolation::MissingKey { index } => {
+                write!(f, "the key of the element at {index} cannot be found in the key index")
+            }
+            InvariantViolation::KeyCountMismatch { keys, elements } => {
+                write!(f, "the key index holds {keys} keys for {elements} elements")
+            }
+            InvariantViolation::ExpiryMismatch { index } => {
+                write!(f, "the expiration index disagrees with the element at {index}")
+            }
+            InvariantViolation::TagMismatch { index } => {
+                write!(f, "the tag index disagrees with the element at {index}")
+            }
+            InvariantViolation::WeightMismatch { recorded, actual } => {
+                write!(f, "the total weight is recorded as {recorded} but the elements weigh {actual}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for InvariantViolation {}
+
+/// The reason a fallible operation like `Cache::try_insert_bounded` left the cache unchanged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheError {
+    /// There is no room for the element even after evicting every element that is not pinned
+    CapacityExhausted,
+    /// The weight of the element alone exceeds the maximum weight of the cache
+    TooLarge { weight: u64, max_weight: u64 },
+    /// The TinyLFU admission filter rejected the key
+    Rejected,
+    /// Memory for the element could not be allocated
+    AllocError,
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheError::CapacityExhausted => write!(f, "the cache is full of pinned elements"),
+            CacheError::TooLarge { weight, max_weight } => {
+                write!(f, "the weight {weight} exceeds the maximum weight {max_weight} of the cache")
+            }
+            CacheError::Rejected => write!(f, "the admission filter rejected the key"),
+            CacheError::AllocError => write!(f, "failed to allocate memory for the element"),
+        }
+    }
+}
+
+impl std::error::Error for CacheError {}
+
+impl From<TryReserveError> for CacheError {
+    fn from(_: TryReserveError) -> Self {
+        CacheError::AllocError
+    }
+}
+
+/// A callback invoked with the key and value of every element that leaves the cache
+pub(crate) type EvictionListener<K, V> = Box<dyn FnMut(K, V, RemovalCause) + Send>;
+
+/// A function computing the weight of an element for weight-based capacity
+pub(crate) type Weigher<K, V> = Arc<dyn Fn(&K, &V) -> u64 + Send + Sync>;
+
 
-impl<'a, K, V> Iterator for CacheIter<'a, K, V> {
+impl<'a, K, V, S> Iterator for CacheIter<'a, K, V, S> {
     type Item = (&'a K, &'a V, &'a Metadata<K>);
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.usage.next().and_then(|key| {
-            let value = self.cache.slab.get(*key)?;
-            let metadata = self.cache.key_meta.get(key)?;
-            Some((&metadata.user_key, value, metadata))
-        })
+        let node = self.cache.slab.get(self.usage.next()?)?;
+        self.remaining -= 1;
+        Some((&node.metadata.user_key, &node.value, &node.metadata))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V, S> DoubleEndedIterator for CacheIter<'_, K, V, S> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let node = self.cache.slab.get(self.usage.next_back()?)?;
+        self.remaining -= 1;
+        Some((&node.metadata.user_key, &node.value, &node.metadata))
     }
 }
 /// An efficient LRU in-memory cache based on a slab allocator.
@@ -67,236 +749,5394 @@ impl<'a, K, V> Iterator for CacheIter<'a, K, V> {
 /// cache.insert("foobar", "barbaz");
 ///
 /// // Access elements
-/// let _ = cache.get("foo");
-/// let _ = cache.get("baz");
+/// let _ = cache.get(&"foo");
+/// let _ = cache.get(&"baz");
 ///
 /// // Insert another element to force eviction of the LRU element
 ///
 /// cache.insert("key", "value");
 ///
-/// assert_eq!(cache.get("foo"), Some(&"bar"));
-/// assert_eq!(cache.get("baz"), Some(&"bar"));
-/// assert_eq!(cache.get("foobar"), None));
-/// assert_eq!(cache.get("key"), Some(&"value"));
+/// assert_eq!(cache.get(&"foo"), Some(&"bar"));
+/// assert_eq!(cache.get(&"baz"), Some(&"bar"));
+/// assert_eq!(cache.get(&"foobar"), None));
+/// assert_eq!(cache.get(&"key"), Some(&"value"));
 ///
 /// // Iterate over the cache elements by access frequency
 /// for (key, value, metadata) in cache.iter_frequency(SortOrder::Ascending) {
-///     println!("Key: {}, Value: {}, Frequency: {}", key, value, metadata.frequency);
+///     println!("Key: {}, Value: {}, Frequency: {}", key, value, metadata.frequency());
 /// }```
-pub struct Cache<K, V> {
+///
+/// Keys are hashed with the std `RandomState` by default, `with_hasher` accepts any other
+/// `BuildHasher`, e.g. a faster non-cryptographic one for small integer keys.
+pub struct Cache<K, V, S = RandomState> {
     /// The slab allocator used as the storage engine for the cache
-    slab: Slab<V>,
-    /// A map from the index of an element in the slab to its metadata
-    key_meta: HashMap<usize, Metadata<K>>,
-    /// A map from the user-provided key to the index of the element in the slab
-    key_map: HashMap<K, usize>,
-    /// A list of indices of elements in the slab to enforce the LRU policy
-    usage: VecDeque<usize>,
-    /// A map from the index of an element in the slab to its position in the usage list to provide O(1) access
-    usage_map: HashMap<usize, usize>,
+    slab: Slab<Node<K, V>>,
+    /// A map from the hash of the user-provided key to the index of the element in the slab
+    key_map: KeyIndex<S>,
+    /// A list per priority of indices of elements in the slab to enforce the LRU policy, linked
+    /// through the slab entries to provide O(1) promotion and eviction
+    usage: [List; PRIORITIES],
+    /// A list per priority of indices of elements in the protected segment of the SLRU policy in
+    /// LRU order
+    protected: [List; PRIORITIES],
+    /// The eviction policy
+    policy: Policy,
+    /// The state of the generator picking the victims of the random policy
+    random_state: u64,
+    /// The hashes of the keys recently evicted from the small queue of an S3-FIFO cache
+    ghosts: GhostQueue,
+    /// The recency stack of a LIRS cache
+    lirs: LirsStack,
+    /// The records of the keys recently evicted for capacity, if enabled
+    history: Option<GhostQueue<EvictionRecord>>,
+    /// The frequency sketch shared by the TinyLFU admission filter and estimated frequencies
+    sketch: Option<CountMinSketch>,
+    /// Whether the TinyLFU admission filter is enabled
+    admission: bool,
+    /// Whether frequencies are estimated by the sketch instead of counted per element
+    estimated_frequencies: bool,
+    /// The indices of elements with a TTL ordered by their expiration time
+    expiry: BTreeSet<(i64, usize)>,
     /// The maximum number of elements that the cache can hold
     capacity: usize,
+    /// The number of elements `maintain` trims the cache down to
+    low_watermark: Option<usize>,
+    /// The time after which elements expire once they have been written
+    expire_after_write: Option<Duration>,
+    /// The time after which elements expire once they have been last read or written
+    expire_after_access: Option<Duration>,
+    /// The time after which keys recorded as missing by `insert_missing` expire
+    negative_ttl: Option<Duration>,
+    /// The share at the end of the lifetime of a value in which a hit flags it for reloading
+    refresh_ahead: Option<f64>,
+    /// The window before the end of its TTL in which each element expires at random
+    ttl_jitter: Option<Duration>,
+    /// The keys flagged for reloading since the last call to `take_refresh_candidates`
+    refresh_candidates: Vec<K>,
+    /// The callback notified about elements leaving the cache
+    listener: Option<EvictionListener<K, V>>,
+    /// The dirty elements evicted for capacity since they were last taken, collected instead of
+    /// being passed to the listener while a `TieredCache` spills them into its secondary store
+    spill: Option<Vec<(K, V)>>,
+    /// Whether expired elements are spilled as well
+    spill_expired: bool,
+    /// The source of every timestamp
+    clock: Arc<dyn Clock>,
+    /// The function computing the weight of each element, every element weighs 1 without it
+    weigher: Option<Weigher<K, V>>,
+    /// The maximum total weight of the elements that the cache can hold
+    max_weight: Option<u64>,
+    /// The total weight of the elements in the cache
+    total_weight: u64,
+    /// The sequence number given to the next inserted element
+    next_sequence: u64,
+    /// The channel receiving the events of the cache, see `Cache::subscribe`
+    #[cfg(feature = "events")]
+    events: Option<std::sync::mpsc::SyncSender<CacheEvent<K>>>,
+    /// The value of the `cache` label of the exported metrics, see `Cache::metrics`
+    #[cfg(feature = "metrics")]
+    metrics_name: Option<metrics::SharedString>,
+    /// The sink receiving a metric for every hit, miss, insert and removal, see `Cache::stats_sink`
+    sink: Option<Box<dyn StatsSink>>,
+    /// The recorder of every get and insert, see `Cache::record_trace`
+    trace: Option<TraceRecorder>,
+    /// The generation of every slot of the slab, bumped whenever an element leaves the slot
+    generations: Vec<u64>,
+    /// The name and statistics of every namespace created by `namespace`, indexed by its id
+    namespaces: Vec<(Arc<str>, Statistics)>,
+    /// The slab indices of the elements carrying each tag
+    tags: HashMap<Arc<str>, HashSet<usize>>,
     /// Statistics about the cache
     statistics: Statistics,
 }
 impl<K: std::hash::Hash + Eq + Clone, V> Cache<K, V> {
+    /// Create a cache that can hold `capacity` elements
+    ///
+    /// # Panics
+    /// Panics if `capacity` is zero, use `try_new` or the builder to handle a zero capacity.
     pub fn new(capacity: usize) -> Self {
+        Self::with_hasher(capacity, RandomState::new())
+    }
+
+    /// Create a cache that can hold `capacity` elements, failing if the capacity is zero
+    pub fn try_new(capacity: usize) -> Result<Self, BuildError> {
+        if capacity == 0 {
+            return Err(BuildError::ZeroCapacity);
+        }
+        Ok(Self::new(capacity))
+    }
+
+    /// Start configuring a cache that can hold `capacity` elements with a validating builder
+    pub fn builder(capacity: usize) -> CacheBuilder<K, V> {
+        CacheBuilder::new(capacity)
+    }
+}
+
+impl<K: std::hash::Hash + Eq + Clone, V, S: BuildHasher> Cache<K, V, S> {
+    /// Create a cache that can hold `capacity` elements and hashes its keys with `hasher`
+    ///
+    /// # Panics
+    /// Panics if `capacity` is zero.
+    pub fn with_hasher(capacity: usize, hasher: S) -> Self {
+        assert!(capacity > 0, "the capacity of a cache must not be zero");
         Cache {
             slab: Slab::with_capacity(capacity),
-            key_meta: HashMap::with_capacity(capacity),
-            key_map: HashMap::with_capacity (capacity),
-            usage: VecDeque::with_capacity(capacity),
-            usage_map: HashMap::with_capacity(capacity),
+            key_map: KeyIndex::with_capacity_and_hasher(capacity, hasher),
+            usage: [List::new(); PRIORITIES],
+            protected: [List::new(); PRIORITIES],
+            policy: Policy::Lru,
+            random_state: 0,
+            ghosts: GhostQueue::default(),
+            lirs: LirsStack::default(),
+            history: None,
+            sketch: None,
+            admission: false,
+            estimated_frequencies: false,
+            expiry: BTreeSet::new(),
+            generations: Vec::with_capacity(capacity),
+            namespaces: Vec::new(),
+            tags: HashMap::new(),
             statistics: Statistics::new(),
             capacity,
+            low_watermark: None,
+            expire_after_write: None,
+            expire_after_access: None,
+            negative_ttl: None,
+            refresh_ahead: None,
+            ttl_jitter: None,
+            refresh_candidates: Vec::new(),
+            listener: None,
+            spill: None,
+            spill_expired: false,
+            clock: Arc::new(SystemClock),
+            weigher: None,
+            max_weight: None,
+            total_weight: 0,
+            next_sequence: 0,
+            #[cfg(feature = "events")]
+            events: None,
+            #[cfg(feature = "metrics")]
+            metrics_name: None,
+            sink: None,
+            trace: None,
         }
     }
 
-    /// Insert a value into the cache
-    pub fn insert(&mut self, key: K, value: V) -> K {
-        let index= self.slab.insert(value);
-        self.key_meta.insert(
-            index,
-            Metadata {
-                last_accessed: 0,
-                frequency: 0,
-                hits: 0,
-                user_key: key.clone(),
-            },
-        );
-        self.key_map.insert(key.clone(), index);
-        self.usage.push_back(index);
-        if self.usage.len() > self.capacity {
-            if let Some(key) = self.usage.pop_front() {
-                let metadata = self.key_meta.get(&key).unwrap();
-                self.slab.remove(key);
-                self.usage_map.remove(&key);
-                self.key_map.remove(&metadata.user_key);
-                self.key_meta.remove(&key);
-            }
-        }
-        self.statistics.update_size(self.slab.len());
-        key
+    /// Expire every element once `duration` has elapsed since its value was written
+    ///
+    /// A TTL passed to `insert_with_ttl` takes precedence over this policy.
+    pub fn expire_after_write(mut self, duration: Duration) -> Self {
+        self.expire_after_write = Some(duration);
+        self
     }
 
+    /// Expire every element once `duration` has elapsed since it was last read or written
+    pub fn expire_after_access(mut self, duration: Duration) -> Self {
+        self.expire_after_access = Some(duration);
+        self
+    }
 
-    /// Get a value from the cache and update its access time and frequency
-    pub fn get(&mut self, key: K) -> Option<&V> {
-        match self.key_map.get(&key) {
-            Some(&usize_key) => {
-                if let Some(meta) = self.key_meta.get_mut(&usize_key) {
-                    meta.last_accessed = Utc::now().timestamp_micros();
-                    meta.frequency += 1;
-                    meta.hits += 1;
-                    self.statistics.hit();
-                }
-                if let Some(&position) = self.usage_map.get(&usize_key) {
-                    let k = self.usage.remove(position)?;
-                    self.usage.push_back(k);
-                    self.usage_map.insert(usize_key, self.usage.len() - 1);
-                }
-                self.slab.get(usize_key)
-            }
-            None => {
-                self.statistics.miss();
-                None
-            }
-        }
+    /// Expire keys recorded as missing by `insert_missing` once `ttl` has elapsed
+    ///
+    /// This is usually shorter than the expiration of regular values, so a key that starts to
+    /// exist upstream is picked up soon. Without it, missing keys expire like any other element.
+    pub fn negative_ttl(mut self, ttl: Duration) -> Self {
+        self.negative_ttl = Some(ttl);
+        self
     }
 
+    /// Flag values for reloading when they are hit within the last `fraction` of their lifetime
+    ///
+    /// The lifetime of a value runs from its write to the expiration set by its TTL or the
+    /// expire-after-write policy, values without one are never flagged. A hit on a flagged value
+    /// still returns the current value, the host application collects the flagged keys with
+    /// `take_refresh_candidates` and reloads them, so popular keys never expire under load.
+    /// `fraction` is clamped to between 0 and 1.
+    pub fn refresh_ahead(mut self, fraction: f64) -> Self {
+        self.refresh_ahead = Some(fraction.clamp(0.0, 1.0));
+        self
+    }
 
-    /// Return the least recently used element in the cache
-    pub fn get_lru(&self) -> Option<&V> {
-        let key = self.usage.front()?;
-        self.slab.get(*key)
+    /// Shorten the TTL of every element by a random amount of up to `jitter`
+    ///
+    /// Elements written together with the same TTL, e.g. while warming the cache, would otherwise
+    /// expire together and be reloaded all at once. With jitter each element expires at a random
+    /// time within the last `jitter` of its TTL, never later than the TTL itself, so the reloads
+    /// spread out. Applies to TTLs passed to `insert_with_ttl` and to the expire-after-write
+    /// policy alike.
+    pub fn ttl_jitter(mut self, jitter: Duration) -> Self {
+        self.ttl_jitter = Some(jitter);
+        self
     }
 
+    /// Remember the hashes of the last `len` keys evicted for capacity with an `EvictionRecord`
+    ///
+    /// Misses on remembered keys are counted as re-misses in the statistics: each of them would
+    /// have been a hit in a larger cache. A key is forgotten once it is inserted again.
+    pub fn eviction_history(mut self, len: usize) -> Self {
+        self.history = Some(GhostQueue::new(len));
+        self
+    }
 
-    /// Remove all elements from the cache but preserve allocated memory
-    pub fn flush(&mut self) {
-        self.slab.clear();
-        self.key_meta.clear();
-        self.usage.clear();
-        self.usage_map.clear();
-        self.key_map.clear();
+    /// Trim the cache down to `len` elements whenever `maintain` is called
+    ///
+    /// The capacity becomes the high watermark: inserts only evict once it is reached, while a
+    /// periodic `maintain` call evicts in a batch down to the low watermark. Under bursty writes
+    /// this keeps most inserts from paying for an eviction.
+    pub fn low_watermark(mut self, len: usize) -> Self {
+        self.low_watermark = Some(len);
+        self
     }
 
+    /// Export the statistics of the cache through the `metrics` facade, labeled with
+    /// `cache = name`
+    ///
+    /// The metrics are updated by `maintain` and `export_metrics`, so a periodic maintenance keeps
+    /// them fresh for whatever recorder is installed, e.g. a Prometheus exporter.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, name: impl Into<String>) -> Self {
+        self.metrics_name = Some(name.into().into());
+        self
+    }
 
-    /// Returns an iterator over the cache in order of access frequency
-    pub fn iter_frequency(&self, order: SortOrder) -> CacheIterFrequency<K, V> {
-        let mut keys: Vec<usize> = self.key_meta.keys().cloned().collect();
-        keys.sort_by_key(|k| self.key_meta.get(k).unwrap().frequency);
-        if let SortOrder::Descending = order {
-            keys.reverse();
-        }
-        CacheIterFrequency {
-            keys: keys.into_iter(),
-            cache: self,
+    /// Report every hit, miss, insert and removal to `sink` as it happens
+    ///
+    /// Unlike the statistics, which have to be polled, the sink is pushed every metric, e.g. to
+    /// forward them to statsd with a `StatsdSink`.
+    pub fn stats_sink<T: StatsSink + 'static>(mut self, sink: T) -> Self {
+        self.sink = Some(Box::new(sink));
+        self
+    }
+
+    /// Start recording every get and insert to `recorder`, replacing any previous recorder
+    ///
+    /// Every hit, miss and write is recorded with the hash of its key and its timestamp, which is
+    /// enough to replay the traffic against other configurations with `simulate`. Lookups by
+    /// token record hits but not misses.
+    pub fn record_trace(&mut self, recorder: TraceRecorder) {
+        self.trace = Some(recorder);
+    }
+
+    /// Stop recording and return the recorder
+    pub fn stop_trace(&mut self) -> Option<TraceRecorder> {
+        self.trace.take()
+    }
+
+    /// Read every timestamp from `clock` instead of the system clock, e.g. a `MockClock` to test
+    /// expiration deterministically
+    pub fn clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Register a callback that takes ownership of every element the cache removes on its own
+    ///
+    /// Values that are handed back to the caller, e.g. by `remove` or by `insert` when it replaces
+    /// an existing value, are not passed to the listener.
+    pub fn eviction_listener<F>(mut self, listener: F) -> Self
+    where
+        F: FnMut(K, V, RemovalCause) + Send + 'static,
+    {
+        self.listener = Some(Box::new(listener));
+        self
+    }
+
+    /// Choose the policy deciding which element is evicted when the cache is full
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        if let Policy::Random { seed } = policy {
+            self.random_state = seed;
+        }
+        self
+    }
+
+    /// Enable the TinyLFU admission filter
+    ///
+    /// The filter estimates the access frequency of every key looked up in the cache with a
+    /// count-min sketch. When the cache is full, `insert` only admits a new key if it is estimated
+    /// to be more frequent than the element it would evict. Rejected values are handed to the
+    /// eviction listener with `RemovalCause::Capacity`. Inserts through `entry` and
+    /// `get_or_insert_with` always admit the key since they return a reference to the value.
+    pub fn tiny_lfu(mut self) -> Self {
+        self.sketch.get_or_insert_with(|| CountMinSketch::new(self.capacity));
+        self.admission = true;
+        self
+    }
+
+    /// Estimate access frequencies with a count-min sketch shared by all elements instead of
+    /// counting the accesses of every element exactly
+    ///
+    /// The sketch counts every lookup of a key, including misses. Estimates never undercount,
+    /// saturate at 15 and are halved periodically, so they reflect recent popularity.
+    /// `iter_frequency`, `entry_stats`, `frequency` and `Metadata::frequency` report the estimates.
+    /// Enabling TinyLFU as well reuses the same sketch.
+    pub fn estimated_frequencies(mut self) -> Self {
+        self.sketch.get_or_insert_with(|| CountMinSketch::new(self.capacity));
+        self.estimated_frequencies = true;
+        self
+    }
+
+    /// Keep the hits and misses of the last `intervals` intervals of length `interval` to compute
+    /// recent hit ratios with `hit_ratio_last`
+    ///
+    /// Resets the statistics recorded so far.
+    pub fn windowed_statistics(mut self, interval: Duration, intervals: usize) -> Self {
+        self.statistics = Statistics::with_window(interval, intervals);
+        self.statistics.update_size(self.slab.len());
+        self
+    }
+
+    /// Compute the weight of every element with `weigher` when its value is written
+    pub fn weigher<F>(mut self, weigher: F) -> Self
+    where
+        F: Fn(&K, &V) -> u64 + Send + Sync + 'static,
+    {
+        self.weigher = Some(Arc::new(weigher));
+        self
+    }
+
+    /// Bound the total weight of the elements in addition to their number
+    ///
+    /// Inserts evict elements in LRU order until the total weight fits again. The element being
+    /// inserted is never evicted by its own insert, even if it weighs more than `max_weight` alone.
+    pub fn max_weight(mut self, max_weight: u64) -> Self {
+        self.max_weight = Some(max_weight);
+        self
+    }
+
+    /// Take the keys whose values were hit close to their expiration since the last call
+    ///
+    /// Every key is returned once per written value, reloading it with `insert` arms the
+    /// refresh-ahead check again. Keys that have left the cache in the meantime are skipped.
+    pub fn take_refresh_candidates(&mut self) -> Vec<K> {
+        let mut candidates = std::mem::take(&mut self.refresh_candidates);
+        candidates.retain(|key| self.find(key).is_some());
+        candidates
+    }
+
+
+    /// Store an element exactly as described by `metadata` at the most recently used end of its
+    /// segment, skipping keys that are already present
+    #[cfg(feature = "serde")]
+    fn restore(&mut self, metadata: Metadata<K>, value: V) {
+        let unknown = metadata.namespace.is_some_and(|namespace| namespace as usize >= self.namespaces.len());
+        let hash = self.hash_in(metadata.namespace, &metadata.user_key);
+        if unknown || self.find_hashed(metadata.namespace, &metadata.user_key, hash).is_some() {
+            return;
+        }
+        if let Some(statistics) = self.namespace_statistics_mut(metadata.namespace) {
+            statistics.update_size(statistics.get_current_size() + 1);
+        }
+        let (priority, protected) = (metadata.priority as usize, metadata.protected);
+        let (expires_at, weight) = (metadata.expires_at, metadata.weight);
+        self.next_sequence = self.next_sequence.max(metadata.sequence + 1);
+        let index = self.slab.insert(Node { value, metadata, links: Links::default() });
+        self.claim_slot(index);
+        self.key_map.insert(hash, index);
+        for tag in self.slab[index].metadata.tags.iter() {
+            self.tags.entry(tag.clone()).or_default().insert(index);
+        }
+        let list = if protected {
+            &mut self.protected[priority]
+        } else {
+            &mut self.usage[priority]
+        };
+        list.push_back(&mut self.slab, index);
+        if let Some(expires_at) = expires_at {
+            self.expiry.insert((expires_at, index));
+        }
+        self.total_weight += weight;
+        self.statistics.update_size(self.slab.len());
+    }
+
+    /// Replace the stats sink with an already boxed one
+    pub(crate) fn set_sink(&mut self, sink: Option<Box<dyn StatsSink>>) {
+        self.sink = sink;
+    }
+
+    /// Replace the eviction listener with an already boxed one
+    pub(crate) fn set_listener(&mut self, listener: Option<EvictionListener<K, V>>) {
+        self.listener = listener;
+    }
+
+    /// Collect the dirty elements evicted for capacity, and if `expired` is set the dirty expired
+    /// elements, for `take_spilled` instead of passing them to the listener
+    ///
+    /// Clean elements removed for these causes are dropped, the secondary store already holds them.
+    pub(crate) fn enable_spill(&mut self, expired: bool) {
+        self.spill.get_or_insert_with(Vec::new);
+        self.spill_expired = expired;
+    }
+
+    /// Take the elements evicted for capacity since the last call, see `enable_spill`
+    pub(crate) fn take_spilled(&mut self) -> Vec<(K, V)> {
+        self.spill.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Mark the value of `key` as stored in the secondary tier
+    pub(crate) fn mark_clean(&mut self, key: &K) {
+        if let Some(index) = self.find(key) {
+            self.slab[index].metadata.dirty = false;
+        }
+    }
+
+    /// Pass every dirty element to `f` and mark it clean, returning how many there were
+    pub(crate) fn clean_dirty<F: FnMut(&K, &V)>(&mut self, mut f: F) -> usize {
+        let mut cleaned = 0;
+        for (_, node) in self.slab.iter_mut().filter(|(_, node)| node.metadata.dirty) {
+            f(&node.metadata.user_key, &node.value);
+            node.metadata.dirty = false;
+            cleaned += 1;
+        }
+        cleaned
+    }
+
+    /// Replace the clock with an already shared one
+    pub(crate) fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Replace the weigher with an already boxed one
+    ///
+    /// Only meant to be called before the first insert, the weights of present elements are not
+    /// recomputed.
+    pub(crate) fn set_weigher(&mut self, weigher: Option<Weigher<K, V>>) {
+        self.weigher = weigher;
+    }
+
+    /// The number of elements in the cache, including expired ones that were not removed yet
+    pub fn len(&self) -> usize {
+        self.slab.len()
+    }
+
+    /// Whether the cache holds no elements
+    pub fn is_empty(&self) -> bool {
+        self.slab.is_empty()
+    }
+
+    /// The maximum number of elements that the cache can hold
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Change the maximum number of elements that the cache can hold
+    ///
+    /// Shrinking the cache evicts elements in eviction order, expired ones first, until the new
+    /// capacity is satisfied. The eviction listener is notified about every evicted element.
+    ///
+    /// # Panics
+    /// Panics if `capacity` is zero, use `try_set_capacity` to handle a zero capacity.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        assert!(capacity > 0, "the capacity of a cache must not be zero");
+        self.capacity = capacity;
+        self.make_room(0, 0, None);
+    }
+
+    /// Change the capacity like `set_capacity`, failing if it is zero or more elements are pinned
+    /// than fit
+    ///
+    /// `set_capacity` keeps pinned elements beyond the new capacity, this leaves the cache
+    /// unchanged instead.
+    pub fn try_set_capacity(&mut self, capacity: usize) -> Result<(), CacheError> {
+        let (pinned, _) = self.pinned_totals(None);
+        if capacity == 0 || pinned > capacity {
+            return Err(CacheError::CapacityExhausted);
+        }
+        self.set_capacity(capacity);
+        Ok(())
+    }
+
+    /// The number of elements that can be inserted before the cache starts evicting
+    pub fn remaining_capacity(&self) -> usize {
+        self.capacity.saturating_sub(self.slab.len())
+    }
+
+    /// The total weight of the elements in the cache
+    pub fn weight(&self) -> u64 {
+        self.total_weight
+    }
+
+    /// An estimate of the memory used by the cache in bytes
+    ///
+    /// Accounts for the slab, the key index, the expiration index, the frequency sketch and the
+    /// other internal buffers, but not for heap memory owned by the keys and values themselves,
+    /// see `estimated_memory_with`. Allocator overhead is not included either.
+    pub fn estimated_memory(&self) -> usize {
+        use std::mem::size_of;
+
+        std::mem::size_of_val(self)
+            + self.slab.capacity() * size_of::<Option<Node<K, V>>>()
+            + self.key_map.allocated_bytes()
+            + self.expiry.len() * size_of::<(i64, usize)>()
+            + self.generations.capacity() * size_of::<u64>()
+            + self.refresh_candidates.capacity() * size_of::<K>()
+            + self.spill.as_ref().map_or(0, |spill| spill.capacity() * size_of::<(K, V)>())
+            + self.sketch.as_ref().map_or(0, CountMinSketch::allocated_bytes)
+    }
+
+
+    /// Like `estimated_memory`, adding the heap memory owned by every element as reported by
+    /// `heap_size`, e.g. the length of a `String` key
+    pub fn estimated_memory_with<F>(&self, heap_size: F) -> usize
+    where
+        F: Fn(&K, &V) -> usize,
+    {
+        let owned: usize = self
+            .slab
+            .iter()
+            .map(|(_, node)| heap_size(&node.metadata.user_key, &node.value))
+            .sum();
+        self.estimated_memory() + owned
+    }
+
+    /// Cross-check the internal structures of the cache for consistency
+    ///
+    /// Checks that every element is linked into the usage list of its priority and segment, that
+    /// the key index, the expiration index and the tag index agree with the elements, and that the
+    /// total weight is the sum of their weights. This walks every structure, so it is meant for
+    /// tests, fuzzing and diagnosing corruption rather than for regular use.
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        let mut linked = vec![0usize; self.slab.capacity()];
+        let usage = self.usage.iter().map(|list| (list, false));
+        let segments = usage.chain(self.protected.iter().map(|list| (list, true)));
+        for (position, (list, protected)) in segments.enumerate() {
+            let priority = Priority::ALL[position % PRIORITIES];
+            let indices = list
+                .checked_indices(|index| self.slab.get(index))
+                .ok_or(InvariantViolation::CorruptList { priority, protected })?;
+            for index in indices {
+                let metadata = &self.slab[index].metadata;
+                if metadata.priority != priority || metadata.protected != protected {
+                    return Err(InvariantViolation::MisplacedElement { index });
+                }
+                linked[index] += 1;
+            }
+        }
+        if let Some((index, _)) = self.slab.iter().find(|(index, _)| linked[*index] != 1) {
+            return Err(InvariantViolation::MisplacedElement { index });
+        }
+
+        let mut keys = 0;
+        for (hash, index) in self.key_map.iter() {
+            if !self.slab.contains(index) || self.hash_at(index) != hash {
+                return Err(InvariantViolation::StaleKey { hash, index });
+            }
+            keys += 1;
+        }
+        if keys != self.slab.len() {
+            return Err(InvariantViolation::KeyCountMismatch { keys, elements: self.slab.len() });
+        }
+
+        let mut weight = 0;
+        for (index, node) in &self.slab {
+            let metadata = &node.metadata;
+            let hash = self.hash_at(index);
+            if self.find_hashed(metadata.namespace, &metadata.user_key, hash) != Some(index) {
+                return Err(InvariantViolation::MissingKey { index });
+            }
+            if metadata.expires_at.is_some_and(|expires_at| !self.expiry.contains(&(expires_at, index))) {
+                return Err(InvariantViolation::ExpiryMismatch { index });
+            }
+            if !metadata.tags.iter().all(|tag| self.tags.get(tag).is_some_and(|tagged| tagged.contains(&index))) {
+                return Err(InvariantViolation::TagMismatch { index });
+            }
+            weight += metadata.weight;
+        }
+        let indexed = |&&(expires_at, index): &&(i64, usize)| {
+            self.slab.get(index).is_some_and(|node| node.metadata.expires_at == Some(expires_at))
+        };
+        if let Some(&(_, index)) = self.expiry.iter().find(|entry| !indexed(entry)) {
+            return Err(InvariantViolation::ExpiryMismatch { index });
+        }
+        for (tag, members) in &self.tags {
+            if let Some(&index) = members
+                .iter()
+                .find(|&&index| self.slab.get(index).is_none_or(|node| !node.metadata.tags.contains(tag)))
+            {
+                return Err(InvariantViolation::TagMismatch { index });
+            }
+        }
+        if weight != self.total_weight {
+            return Err(InvariantViolation::WeightMismatch { recorded: self.total_weight, actual: weight });
+        }
+        Ok(())
+    }
+
+
+    /// Whether both caches hold the same live keys with equal values
+    ///
+    /// Only the contents are compared: the order of the elements, their metadata, the
+    /// configuration and the statistics are ignored, so two caches that converged to the same
+    /// contents through different sequences of operations are equal. Keys in namespaces are
+    /// matched by the name of the namespace. Expired elements that were not removed yet are
+    /// ignored as well.
+    pub fn content_eq<S2: BuildHasher>(&self, other: &Cache<K, V, S2>) -> bool
+    where
+        V: PartialEq,
+    {
+        let mut len = 0;
+        for (index, node) in &self.slab {
+            if self.is_expired(index) {
+                continue;
+            }
+            let namespace = match node.metadata.namespace {
+                Some(id) => {
+                    let name = &self.namespaces[id as usize].0;
+                    match other.namespaces.iter().position(|(other, _)| other == name) {
+                        Some(id) => Some(id as u32),
+                        None => return false,
+                    }
+                }
+                None => None,
+            };
+            let key = &node.metadata.user_key;
+            match other.find_hashed(namespace, key, other.hash_in(namespace, key)) {
+                Some(found) if !other.is_expired(found) && other.slab[found].value == node.value => len += 1,
+                _ => return false,
+            }
+        }
+        len == other.slab.iter().filter(|(index, _)| !other.is_expired(*index)).count()
+    }
+
+
+    /// A snapshot of the statistics of the cache
+    pub fn statistics(&self) -> StatsSnapshot {
+        self.statistics.snapshot()
+    }
+
+
+    /// The share of lookups within the last `duration` that were hits
+    ///
+    /// Returns `None` unless windowed statistics were enabled with `windowed_statistics`.
+    pub fn hit_ratio_last(&self, duration: Duration) -> Option<f64> {
+        self.statistics.hit_ratio_last_at(self.now(), duration)
+    }
+
+
+    /// The time below which `percentile` percent of the elements evicted for capacity had been in
+    /// the cache, `None` if nothing was evicted
+    ///
+    /// The result is approximate, see `Statistics::eviction_age_percentile`. Evicted elements
+    /// that are young compared to the working set suggest the capacity is too small.
+    pub fn eviction_age_percentile(&self, percentile: f64) -> Option<Duration> {
+        self.statistics.eviction_age_percentile(percentile)
+    }
+
+
+    /// The time below which `percentile` percent of the values loaded by `get_or_insert_with`,
+    /// `Entry::or_insert_with` and `get_with_beta` took to load, `None` if nothing was loaded
+    ///
+    /// The result is approximate, see `Statistics::eviction_age_percentile`. The load time of
+    /// every element is kept in its metadata as well, see `Metadata::load_duration`.
+    pub fn load_time_percentile(&self, percentile: f64) -> Option<Duration> {
+        self.statistics.load_time_percentile(percentile)
+    }
+
+
+    /// Zero the statistics counters, e.g. to report the statistics per monitoring interval
+    pub fn reset_statistics(&mut self) {
+        self.statistics.reset();
+        for (_, statistics) in self.namespaces.iter_mut() {
+            statistics.reset();
+        }
+    }
+
+
+    /// The live statistics of the cache, for aggregating several caches
+    pub(crate) fn raw_statistics(&self) -> &Statistics {
+        &self.statistics
+    }
+
+    /// Replace the statistics with previously saved ones, keeping the current size accurate
+    #[cfg(feature = "snapshot")]
+    pub(crate) fn restore_statistics(&mut self, mut statistics: Statistics) {
+        statistics.update_size(self.len());
+        self.statistics = statistics;
+    }
+
+    /// Insert a value into the cache
+    ///
+    /// If the key is already present its value is replaced in place, the element becomes the most
+    /// recently used one and the previous value is returned.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, fields(key_hash = self.key_map.hash(&key)))
+    )]
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.insert_expiring(key, value, None, None, None)
+    }
+
+
+    /// Insert a value into the cache and return the element evicted to make room for it
+    ///
+    /// The returned element is not passed to the eviction listener, so it can be spilled to
+    /// another tier instead. If the TinyLFU filter rejects the new element, the new element itself
+    /// is returned. Elements evicted beyond the first one, which is only possible with a weigher,
+    /// still go to the listener. If the key is already present its value is replaced like in
+    /// `insert`, the previous value is dropped and nothing is returned.
+    pub fn insert_returning(&mut self, key: K, value: V) -> Option<(K, V)> {
+        let placement = self.place(None, &key);
+        let evicted = match placement {
+            Placement::Rejected => {
+                self.count_rejected(None);
+                return Some((key, value));
+            }
+            Placement::Vacant => self
+                .next_victim(1, self.weigh(&key, &value), None)
+                .and_then(|(victim, cause)| self.remove_index(victim, cause)),
+            Placement::Occupied(_) => None,
+        };
+        self.store_placed(placement, key, value, None, None, None);
+        evicted
+    }
+
+
+    /// Insert a value into the cache that expires once `ttl` has elapsed
+    ///
+    /// Expired elements are treated as misses by `get` and are the first candidates for eviction.
+    pub fn insert_with_ttl(&mut self, key: K, value: V, ttl: Duration) -> Option<V> {
+        self.insert_expiring(key, value, Some(ttl), None, None)
+    }
+
+
+    /// Insert a value into the cache with the given eviction priority
+    ///
+    /// Elements are evicted in order of priority first and recency second. Replacing the value of
+    /// a present key changes its priority as well, `insert` keeps the priority of a present key.
+    pub fn insert_with_priority(&mut self, key: K, value: V, priority: Priority) -> Option<V> {
+        self.insert_expiring(key, value, None, Some(priority), None)
+    }
+
+
+    /// Insert or replace a value in `namespace`, expiring it after `ttl` or according to the
+    /// expire-after-write policy, and setting its priority if given
+    pub(crate) fn insert_expiring(
+        &mut self,
+        key: K,
+        value: V,
+        ttl: Option<Duration>,
+        priority: Option<Priority>,
+        namespace: Option<u32>,
+    ) -> Option<V> {
+        self.insert_indexed(key, value, ttl, priority, namespace).0
+    }
+
+
+    /// Insert like `insert_expiring` and also return the index of the element in the slab, unless
+    /// the value was rejected
+    fn insert_indexed(
+        &mut self,
+        key: K,
+        value: V,
+        ttl: Option<Duration>,
+        priority: Option<Priority>,
+        namespace: Option<u32>,
+    ) -> (Option<V>, Option<usize>) {
+        let placement = self.place(namespace, &key);
+        self.store_placed(placement, key, value, ttl, priority, namespace)
+    }
+
+
+    /// Decide where an insert of `key` into `namespace` stores its value, lazily removing an
+    /// expired element under the key
+    fn place(&mut self, namespace: Option<u32>, key: &K) -> Placement {
+        let hash = self.hash_in(namespace, key);
+        if let Some(index) = self.lookup_hashed(namespace, key, hash, self.now()) {
+            Placement::Occupied(index)
+        } else if self.admit(hash) {
+            Placement::Vacant
+        } else {
+            Placement::Rejected
+        }
+    }
+
+
+    /// Store a value where `place` decided, returning the previous value and the index of the
+    /// element in the slab like `insert_indexed`
+    fn store_placed(
+        &mut self,
+        placement: Placement,
+        key: K,
+        value: V,
+        ttl: Option<Duration>,
+        priority: Option<Priority>,
+        namespace: Option<u32>,
+    ) -> (Option<V>, Option<usize>) {
+        match placement {
+            Placement::Occupied(index) => {
+                if let Some(priority) = priority {
+                    self.set_priority(index, priority);
+                }
+                (Some(self.replace_index(index, value, ttl)), Some(index))
+            }
+            Placement::Vacant => {
+                let weight = self.weigh(&key, &value);
+                self.make_room(1, weight, None);
+                let index = self.store_new(key, value, ttl, priority.unwrap_or_default(), weight, namespace);
+                (None, Some(index))
+            }
+            Placement::Rejected => {
+                self.count_rejected(namespace);
+                self.notify(key, value, RemovalCause::Capacity, true);
+                (None, None)
+            }
+        }
+    }
+
+
+    /// Count a new element of `namespace` rejected by the TinyLFU filter as evicted for capacity
+    fn count_rejected(&mut self, namespace: Option<u32>) {
+        self.statistics.removed(RemovalCause::Capacity, 1);
+        self.record_metric(CacheMetric::Removal(RemovalCause::Capacity));
+        if let Some(statistics) = self.namespace_statistics_mut(namespace) {
+            statistics.removed(RemovalCause::Capacity, 1);
+        }
+    }
+
+
+    /// A handle to the namespace `name`, which is created on first use
+    ///
+    /// Namespaces partition the keys of a cache while sharing its capacity and eviction order:
+    /// the same key can be stored in several namespaces and outside of any namespace without
+    /// clashing, the elements of a namespace are only reachable through its handle. Every
+    /// namespace keeps its own statistics and can be invalidated as a whole. Iterating over the
+    /// cache yields the elements of every namespace.
+    pub fn namespace(&mut self, name: &str) -> Namespace<'_, K, V, S> {
+        let id = match self.namespaces.iter().position(|(other, _)| **other == *name) {
+            Some(id) => id,
+            None => {
+                self.namespaces.push((Arc::from(name), Statistics::new()));
+                self.namespaces.len() - 1
+            }
+        };
+        let id = u32::try_from(id).expect("too many namespaces");
+        Namespace::new(self, id)
+    }
+
+
+    /// The name of the namespace with the given id
+    pub(crate) fn namespace_name(&self, namespace: u32) -> &str {
+        &self.namespaces[namespace as usize].0
+    }
+
+
+    /// The statistics of the namespace with the given id
+    pub(crate) fn namespace_statistics(&self, namespace: u32) -> &Statistics {
+        &self.namespaces[namespace as usize].1
+    }
+
+
+    /// The statistics of `namespace`, if the element is in one
+    fn namespace_statistics_mut(&mut self, namespace: Option<u32>) -> Option<&mut Statistics> {
+        namespace.map(|namespace| &mut self.namespaces[namespace as usize].1)
+    }
+
+
+    /// Get a value from `namespace` and update its access time and frequency like `get`
+    pub(crate) fn get_in<Q>(&mut self, namespace: u32, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.now();
+        match self.lookup_in(Some(namespace), key, now) {
+            Some(index) => {
+                self.record_access_at(index, now);
+                self.slab.get(index).map(|node| &node.value)
+            }
+            None => {
+                self.namespaces[namespace as usize].1.miss_at(now);
+                self.record_miss(now, |cache| cache.hash_in(Some(namespace), key));
+                None
+            }
+        }
+    }
+
+
+    /// Remove every element of `namespace`, notifying the eviction listener about explicit
+    /// removals, and return how many were removed
+    pub(crate) fn invalidate_namespace(&mut self, namespace: u32) -> usize {
+        let members: Vec<usize> = self
+            .slab
+            .iter()
+            .filter(|(_, node)| node.metadata.namespace == Some(namespace))
+            .map(|(index, _)| index)
+            .collect();
+        for &index in &members {
+            self.evict_index(index, RemovalCause::Explicit);
+        }
+        members.len()
+    }
+
+
+    /// Get a view into the element stored under `key` for in-place manipulation
+    ///
+    /// Looking up the entry counts as an access: an occupied entry is recorded as a hit and
+    /// promoted like in `get`, a vacant one is recorded as a miss.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        match self.lookup(&key) {
+            Some(index) => {
+                self.record_access(index);
+                Entry::Occupied(OccupiedEntry::new(self, index))
+            }
+            None => {
+                self.record_miss(self.now(), |cache| cache.key_map.hash(&key));
+                Entry::Vacant(VacantEntry::new(self, key))
+            }
+        }
+    }
+
+
+    /// Insert a value only if the key is not present yet and return a reference to it
+    ///
+    /// Unlike `insert` this never replaces a value: if the key is present, an error containing its
+    /// entry and the rejected value is returned. Like `entry`, the lookup counts as an access.
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<&V, OccupiedError<'_, K, V, S>> {
+        match self.entry(key) {
+            Entry::Occupied(entry) => Err(OccupiedError { entry, value }),
+            Entry::Vacant(entry) => Ok(entry.insert(value)),
+        }
+    }
+
+
+    /// Insert a value like `insert`, failing instead of exceeding the bounds of the cache
+    ///
+    /// `insert` grows the cache beyond its capacity when every element is pinned, stores elements
+    /// heavier than the maximum weight and hands elements rejected by the TinyLFU filter to the
+    /// eviction listener. This returns the reason as an error instead and drops the value without
+    /// evicting anything. Like any lookup, the check counts the key in the TinyLFU sketch and
+    /// removes an expired element under the key.
+    pub fn try_insert_bounded(&mut self, key: K, value: V) -> Result<Option<V>, CacheError> {
+        let placement = self.place(None, &key);
+        let present = match placement {
+            Placement::Occupied(index) => Some(index),
+            Placement::Vacant | Placement::Rejected => None,
+        };
+        self.check_room(self.weigh(&key, &value), present)?;
+        if placement == Placement::Rejected {
+            return Err(CacheError::Rejected);
+        }
+        Ok(self.store_placed(placement, key, value, None, None, None).0)
+    }
+
+
+    /// Read, modify and write the element stored under `key` with a single lookup
+    ///
+    /// `f` receives the current value, if any, and decides with the returned `Op` whether the
+    /// element is kept, replaced or removed. Returns the value stored afterwards. Like `entry`, the
+    /// lookup counts as an access.
+    pub fn compute<F>(&mut self, key: K, f: F) -> Option<&V>
+    where
+        F: FnOnce(Option<&mut V>) -> Op<V>,
+    {
+        match self.entry(key) {
+            Entry::Occupied(mut entry) => match f(Some(entry.get_mut())) {
+                Op::Keep => Some(entry.into_mut()),
+                Op::Replace(value) => {
+                    entry.insert(value);
+                    Some(entry.into_mut())
+                }
+                Op::Remove => {
+                    entry.remove();
+                    None
+                }
+            },
+            Entry::Vacant(entry) => match f(None) {
+                Op::Replace(value) => Some(entry.insert(value)),
+                Op::Keep | Op::Remove => None,
+            },
+        }
+    }
+
+
+    /// Insert many elements at once, e.g. to fill a cold cache from a database
+    ///
+    /// This behaves like calling `insert` for every element but is much faster for large batches:
+    /// room for the batch is reserved up front and the capacity is only checked in a single pass
+    /// after the batch has been stored, so later elements of the batch take precedence over earlier
+    /// ones. Elements of the batch that later ones would evict in that pass are never stored but
+    /// go to the eviction listener right away. The TinyLFU admission filter is not consulted.
+    pub fn warm<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        let evicted = self.store_batch(iter);
+        self.make_room(0, 0, None);
+        for (key, value) in evicted {
+            self.skip_batch_element();
+            self.notify(key, value, RemovalCause::Capacity, true);
+        }
+    }
+
+
+    /// Insert many elements at once and return the elements evicted to make room for them
+    ///
+    /// Like `warm`, evictions happen in a single pass after the whole batch has been stored, so the
+    /// evicted elements may include elements of the batch itself. They are returned in eviction
+    /// order instead of being passed to the eviction listener, expired ones included.
+    pub fn insert_many<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) -> Vec<(K, V)> {
+        let skipped = self.store_batch(entries);
+        let mut evicted = Vec::new();
+        while let Some((victim, cause)) = self.next_victim(0, 0, None) {
+            evicted.extend(self.remove_index(victim, cause));
+        }
+        for element in skipped {
+            self.skip_batch_element();
+            evicted.push(element);
+        }
+        evicted
+    }
+
+
+    /// Insert or replace the elements of a batch that stay in the cache without making room for
+    /// them, returning the elements the batch itself would evict in batch order
+    ///
+    /// Only the last element of each of the last `capacity` distinct keys of the batch is stored,
+    /// so the slab grows by the capacity at most. Earlier elements of these keys would be replaced
+    /// and are dropped, the elements of the other keys are returned.
+    fn store_batch<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) -> Vec<(K, V)> {
+        let batch: Vec<(K, V)> = iter.into_iter().collect();
+        let mut fates = vec![BatchFate::Evicted; batch.len()];
+        let mut stored = HashSet::new();
+        for (fate, (key, _)) in fates.iter_mut().zip(&batch).rev() {
+            if stored.contains(key) {
+                *fate = BatchFate::Replaced;
+            } else if stored.len() < self.capacity {
+                stored.insert(key);
+                *fate = BatchFate::Stored;
+            }
+        }
+        self.reserve(stored.len());
+        drop(stored);
+
+        let mut evicted = Vec::new();
+        for ((key, value), fate) in batch.into_iter().zip(fates) {
+            match fate {
+                BatchFate::Stored => match self.lookup(&key) {
+                    Some(index) => {
+                        self.replace_value(index, value, None);
+                    }
+                    None => {
+                        let weight = self.weigh(&key, &value);
+                        self.store_new(key, value, None, Priority::Normal, weight, None);
+                    }
+                },
+                BatchFate::Replaced => {}
+                BatchFate::Evicted => evicted.push((key, value)),
+            }
+        }
+        evicted
+    }
+
+
+    /// Count an element of a batch that was never stored as inserted and evicted for capacity
+    fn skip_batch_element(&mut self) {
+        self.statistics.inserted();
+        self.record_metric(CacheMetric::Insert);
+        self.statistics.removed(RemovalCause::Capacity, 1);
+        self.record_metric(CacheMetric::Removal(RemovalCause::Capacity));
+    }
+
+
+    /// Return the cached value for `key`, computing and inserting it with `f` on a miss
+    ///
+    /// The time `f` takes is recorded, see `load_time_percentile`.
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &V {
+        self.entry(key).or_insert_with(f)
+    }
+
+
+    /// Return the cached value for `key`, loading it with the fallible `f` on a miss
+    ///
+    /// A failed load leaves the key vacant, so the next call tries again, and its error is returned.
+    /// The time a successful load takes is recorded like in `get_or_insert_with`.
+    pub fn try_get_or_insert_with<E, F>(&mut self, key: K, f: F) -> Result<&V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+    {
+        self.entry(key).or_try_insert_with(f).map(|value| &*value)
+    }
+
+
+    /// Remove an element from the cache and return its value
+    ///
+    /// The key may be any borrowed form of the key type, e.g. `&str` for `String` keys.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, fields(key_hash = self.key_map.hash(key)))
+    )]
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove_in(None, key)
+    }
+
+
+    /// Like `remove` for a key in `namespace`
+    pub(crate) fn remove_in<Q>(&mut self, namespace: Option<u32>, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.find_in(namespace, key)?;
+        let (_, value) = self.remove_index(index, RemovalCause::Explicit)?;
+        Some(value)
+    }
+
+
+    /// Exempt the element stored under `key` from eviction and return whether it is present
+    ///
+    /// Pinned elements still expire. When every element is pinned, inserts grow the cache beyond
+    /// its capacity until elements are unpinned.
+    pub fn pin<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.set_pinned(key, true)
+    }
+
+
+    /// Make the element stored under `key` evictable again and return whether it is present
+    ///
+    /// Evicts elements right away if pinned elements made the cache grow beyond its capacity.
+    pub fn unpin<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let present = self.set_pinned(key, false);
+        self.make_room(0, 0, None);
+        present
+    }
+
+
+    /// Set whether the element stored under `key` is pinned and return whether it is present
+    fn set_pinned<Q>(&mut self, key: &Q, pinned: bool) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let Some(index) = self.find(key) else {
+            return false;
+        };
+        self.slab[index].metadata.pinned = pinned;
+        true
+    }
+
+
+    /// Remove every element for which `predicate` returns false
+    ///
+    /// The predicate sees expired elements that were not removed yet as well. Removed elements are
+    /// reported to the eviction listener as explicit removals.
+    pub fn retain<F>(&mut self, mut predicate: F)
+    where
+        F: FnMut(&K, &V, &Metadata<K>) -> bool,
+    {
+        let rejected: Vec<usize> = self
+            .slab
+            .iter()
+            .filter(|(_, node)| !predicate(&node.metadata.user_key, &node.value, &node.metadata))
+            .map(|(index, _)| index)
+            .collect();
+        for index in rejected {
+            self.evict_index(index, RemovalCause::Explicit);
+        }
+    }
+
+
+    /// Invalidate every live element for which `predicate` returns true and return how many were
+    /// invalidated
+    ///
+    /// Invalidated elements are treated as expired right away, so reads miss them, but they are
+    /// only removed lazily when they are looked up or evicted, or by `purge_expired` and
+    /// `maintain`. This keeps invalidating many elements cheap for the calling thread. Their
+    /// removal is reported to the eviction listener and the statistics as explicit.
+    pub fn invalidate_entries_if<F>(&mut self, mut predicate: F) -> usize
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let now = self.now();
+        let matching: Vec<usize> = self
+            .slab
+            .iter()
+            .filter(|&(index, node)| {
+                !self.is_expired_at(index, now) && predicate(&node.metadata.user_key, &node.value)
+            })
+            .map(|(index, _)| index)
+            .collect();
+        for &index in &matching {
+            self.slab[index].metadata.invalidated = true;
+            self.set_expiry(index, Some(i64::MIN));
+        }
+        matching.len()
+    }
+
+
+    /// Evict up to `n` elements in eviction order and return how many were evicted
+    ///
+    /// Meant for trimming the cache under memory pressure. Expired elements go first, then the
+    /// least recently used ones, pinned elements are skipped. The eviction listener and the
+    /// statistics see the evictions like those made to stay within the capacity.
+    pub fn evict(&mut self, n: usize) -> usize {
+        let mut evicted = 0;
+        while evicted < n {
+            let Some((victim, cause)) = self.pick_victim(None) else {
+                break;
+            };
+            self.evict_index(victim, cause);
+            evicted += 1;
+        }
+        evicted
+    }
+
+
+    /// Evict elements like `evict` until at most `target_len` are left and return how many were
+    /// evicted
+    pub fn evict_to(&mut self, target_len: usize) -> usize {
+        self.evict(self.len().saturating_sub(target_len))
+    }
+
+
+    /// Remove expired and invalidated elements and trim the cache to its low watermark, returning
+    /// how many elements were removed
+    ///
+    /// Without a low watermark only expired and invalidated elements are removed. Afterwards the
+    /// metrics of the cache are exported if enabled with `metrics`.
+    pub fn maintain(&mut self) -> usize {
+        let purged = self.purge_expired();
+        let removed = purged + self.low_watermark.map_or(0, |len| self.evict_to(len));
+        #[cfg(feature = "metrics")]
+        self.export_metrics();
+        removed
+    }
+
+
+    /// Remove every element that is past its expiration time and return how many were removed
+    pub fn purge_expired(&mut self) -> usize {
+        let now = self.now();
+        let mut purged = 0;
+        while let Some(&(expires_at, index)) = self.expiry.first() {
+            if expires_at > now {
+                break;
+            }
+            self.evict_index(index, RemovalCause::Expired);
+            purged += 1;
+        }
+        purged
+    }
+
+
+    /// Store a key that is not yet present in the cache and return its index in the slab
+    pub(crate) fn insert_new(
+        &mut self,
+        key: K,
+        value: V,
+        ttl: Option<Duration>,
+        priority: Priority,
+    ) -> usize {
+        let weight = self.weigh(&key, &value);
+        self.make_room(1, weight, None);
+        self.store_new(key, value, ttl, priority, weight, None)
+    }
+
+
+    /// Store a key that is not yet present in `namespace` without making room for it first
+    fn store_new(
+        &mut self,
+        key: K,
+        value: V,
+        ttl: Option<Duration>,
+        priority: Priority,
+        weight: u64,
+        namespace: Option<u32>,
+    ) -> usize {
+        self.total_weight += weight;
+        let now = self.now();
+        let hash = self.hash_in(namespace, &key);
+        let mut metadata = Metadata::new(key, now, weight, priority);
+        metadata.namespace = namespace;
+        metadata.sequence = self.next_sequence;
+        self.next_sequence += 1;
+        let index = self.slab.insert(Node { value, metadata, links: Links::default() });
+        self.claim_slot(index);
+        self.key_map.insert(hash, index);
+        self.sync_estimate(index);
+        // An S3-FIFO cache lets keys evicted recently from its small queue skip it
+        if matches!(self.policy, Policy::S3Fifo { .. }) && self.ghosts.remove(hash) {
+            self.slab[index].metadata.protected = true;
+            self.protected[priority as usize].push_back(&mut self.slab, index);
+        } else {
+            self.usage[priority as usize].push_back(&mut self.slab, index);
+        }
+        if let Policy::Lirs { hir_ratio } = self.policy {
+            let reused = self.lirs.touch(hash, index);
+            self.place_lirs(index, reused, hir_ratio);
+        }
+        if let Some(history) = self.history.as_mut() {
+            history.remove(hash);
+        }
+        self.write_expiry(index, ttl, now);
+        self.statistics.update_size(self.slab.len());
+        self.statistics.inserted();
+        if let Some(statistics) = self.namespace_statistics_mut(namespace) {
+            statistics.update_size(statistics.get_current_size() + 1);
+            statistics.inserted();
+        }
+        self.record_metric(CacheMetric::Insert);
+        self.record_trace_access(hash, AccessKind::Insert, now);
+        #[cfg(feature = "tracing")]
+        tracing::trace!(key_hash = hash, weight, "cache insert");
+        #[cfg(feature = "events")]
+        self.emit(|cache| CacheEvent::Insert(cache.key_at(index).clone()));
+        index
+    }
+
+
+    /// Replace the value stored at `index` in the slab and make it the most recently used element
+    ///
+    /// The new value does not inherit the TTL of the old one.
+    pub(crate) fn replace_index(&mut self, index: usize, value: V, ttl: Option<Duration>) -> V {
+        let old = self.replace_value(index, value, ttl);
+        self.make_room(0, 0, Some(index));
+        old
+    }
+
+
+    /// Replace the value stored at `index` like `replace_index` without evicting elements if the
+    /// new value weighs more
+    fn replace_value(&mut self, index: usize, value: V, ttl: Option<Duration>) -> V {
+        let weight = self.weigh(self.key_at(index), &value);
+        let now = self.now();
+        let node = &mut self.slab[index];
+        let old = std::mem::replace(&mut node.value, value);
+        node.metadata.record_write(now);
+        self.total_weight = self.total_weight - node.metadata.weight + weight;
+        node.metadata.weight = weight;
+        self.write_expiry(index, ttl, now);
+        self.requeue(index);
+        self.statistics.removed(RemovalCause::Replaced, 1);
+        self.record_metric(CacheMetric::Removal(RemovalCause::Replaced));
+        if self.trace.is_some() {
+            self.record_trace_access(self.hash_at(index), AccessKind::Insert, now);
+        }
+        let namespace = self.slab[index].metadata.namespace;
+        if let Some(statistics) = self.namespace_statistics_mut(namespace) {
+            statistics.removed(RemovalCause::Replaced, 1);
+        }
+        old
+    }
+
+
+    /// Remove the element stored at `index` in the slab from every internal structure
+    pub(crate) fn remove_index(&mut self, index: usize, cause: RemovalCause) -> Option<(K, V)> {
+        let (value, metadata) = self.remove_node(index, cause)?;
+        Some((metadata.user_key, value))
+    }
+
+
+    /// Remove the element stored at `index` in the slab and return its value and metadata
+    fn remove_node(&mut self, index: usize, cause: RemovalCause) -> Option<(V, Metadata<K>)> {
+        if !self.slab.contains(index) {
+            return None;
+        }
+        self.detach(index);
+        let Node { value, metadata, .. } = self.slab.remove(index);
+        // Invalidated elements look expired but were removed on request
+        let cause = match cause {
+            RemovalCause::Expired if metadata.invalidated => RemovalCause::Explicit,
+            cause => cause,
+        };
+        self.generations[index] = self.generations[index].wrapping_add(1);
+        self.total_weight -= metadata.weight;
+        if let Some(expires_at) = metadata.expires_at {
+            self.expiry.remove(&(expires_at, index));
+        }
+        let hash = self.hash_in(metadata.namespace, &metadata.user_key);
+        self.key_map.remove(hash, index);
+        self.untag(index, &metadata.tags);
+        if let Policy::S3Fifo { small_ratio } = self.policy {
+            if cause == RemovalCause::Capacity && !metadata.protected {
+                let small = (self.capacity as f64 * small_ratio) as usize;
+                self.ghosts.set_capacity(self.capacity.saturating_sub(small));
+                self.ghosts.insert(hash, ());
+            }
+        }
+        if let Policy::Lirs { .. } = self.policy {
+            if cause == RemovalCause::Capacity && !metadata.protected {
+                self.lirs.evict(hash, self.capacity);
+            } else {
+                self.lirs.remove(hash);
+            }
+            self.prune_lirs();
+        }
+        self.statistics.update_size(self.slab.len());
+        self.statistics.removed(cause, 1);
+        let now = self.now();
+        let age = metadata.age(now);
+        if cause == RemovalCause::Capacity {
+            self.statistics.evicted_at_age(age);
+            if let Some(history) = self.history.as_mut() {
+                let (hits, weight) = (metadata.hits, metadata.weight);
+                history.insert(hash, EvictionRecord { evicted_at: now, age, hits, weight });
+            }
+        }
+        if let Some(statistics) = self.namespace_statistics_mut(metadata.namespace) {
+            statistics.update_size(statistics.get_current_size().saturating_sub(1));
+            statistics.removed(cause, 1);
+            if cause == RemovalCause::Capacity {
+                statistics.evicted_at_age(age);
+            }
+        }
+        self.record_metric(CacheMetric::Removal(cause));
+        #[cfg(feature = "tracing")]
+        match cause {
+            RemovalCause::Capacity => {
+                tracing::debug!(key_hash = hash, age_us = age.as_micros() as u64, "cache eviction")
+            }
+            RemovalCause::Expired => {
+                tracing::debug!(key_hash = hash, age_us = age.as_micros() as u64, "cache expiration")
+            }
+            _ => {}
+        }
+        #[cfg(feature = "events")]
+        match cause {
+            RemovalCause::Capacity => self.emit(|_| CacheEvent::Evict(metadata.user_key.clone())),
+            RemovalCause::Expired => self.emit(|_| CacheEvent::Expire(metadata.user_key.clone())),
+            _ => {}
+        }
+        Some((value, metadata))
+    }
+
+
+    /// Remove the element stored at `index` in the slab and hand it to the eviction listener
+    fn evict_index(&mut self, index: usize, cause: RemovalCause) {
+        if let Some((value, metadata)) = self.remove_node(index, cause) {
+            let cause = if metadata.invalidated { RemovalCause::Explicit } else { cause };
+            self.notify(metadata.user_key, value, cause, metadata.dirty);
+        }
+    }
+
+
+    /// The value of the `cache` label of the exported metrics, if enabled
+    #[cfg(feature = "metrics")]
+    pub(crate) fn metrics_name(&self) -> Option<&metrics::SharedString> {
+        self.metrics_name.as_ref()
+    }
+
+
+    /// Send the event built by `event` to the subscriber, if any, dropping it if the channel is
+    /// full and the subscription once the receiver is gone
+    #[cfg(feature = "events")]
+    fn emit<F: FnOnce(&Self) -> CacheEvent<K>>(&mut self, event: F) {
+        use std::sync::mpsc::TrySendError;
+
+        let Some(sender) = self.events.as_ref() else {
+            return;
+        };
+        if let Err(TrySendError::Disconnected(_)) = sender.try_send(event(self)) {
+            self.events = None;
+        }
+    }
+
+
+    /// Set the channel receiving the events of the cache
+    #[cfg(feature = "events")]
+    pub(crate) fn set_event_sender(&mut self, sender: Option<std::sync::mpsc::SyncSender<CacheEvent<K>>>) {
+        self.events = sender;
+    }
+
+
+    /// Hand an element the cache removed on its own to the spill buffer or the eviction listener
+    fn notify(&mut self, key: K, value: V, cause: RemovalCause, dirty: bool) {
+        let spilled = match cause {
+            RemovalCause::Capacity => true,
+            RemovalCause::Expired => self.spill_expired,
+            RemovalCause::Explicit | RemovalCause::Replaced => false,
+        };
+        match self.spill.as_mut() {
+            Some(spill) if spilled => {
+                if dirty {
+                    spill.push((key, value));
+                }
+            }
+            _ => {
+                if let Some(listener) = self.listener.as_mut() {
+                    listener(key, value, cause);
+                }
+            }
+        }
+    }
+
+
+    /// The user-provided key of the element stored at `index` in the slab
+    pub(crate) fn key_at(&self, index: usize) -> &K {
+        &self.slab[index].metadata.user_key
+    }
+
+
+    /// The value stored at `index` in the slab
+    pub(crate) fn value_at(&self, index: usize) -> &V {
+        &self.slab[index].value
+    }
+
+
+    /// A mutable reference to the value stored at `index` in the slab
+    pub(crate) fn value_at_mut(&mut self, index: usize) -> &mut V {
+        &mut self.slab[index].value
+    }
+
+
+    /// The metadata of the element stored at `index` in the slab
+    fn metadata(&self, index: usize) -> Option<&Metadata<K>> {
+        self.slab.get(index).map(|node| &node.metadata)
+    }
+
+
+    /// A mutable reference to the metadata of the element stored at `index` in the slab
+    fn metadata_mut(&mut self, index: usize) -> Option<&mut Metadata<K>> {
+        self.slab.get_mut(index).map(|node| &mut node.metadata)
+    }
+
+
+    /// Reset the write deadline of the element stored at `index` in the slab after its value was written
+    fn write_expiry(&mut self, index: usize, ttl: Option<Duration>, now: i64) {
+        let ttl = ttl.or(self.expire_after_write).map(|ttl| self.jittered(ttl, index, now));
+        let write_deadline = ttl.map(|ttl| deadline(now, ttl));
+        let refresh_at = ttl
+            .zip(self.refresh_ahead)
+            .map(|(ttl, fraction)| deadline(now, ttl.mul_f64(1.0 - fraction)));
+        if let Some(meta) = self.metadata_mut(index) {
+            meta.write_deadline = write_deadline;
+            meta.refresh_at = refresh_at;
+        }
+        self.refresh_expiry(index, now);
+    }
+
+
+    /// `ttl` shortened by a random amount within the TTL jitter for the element stored at `index`
+    /// in the slab and written at `now`
+    fn jittered(&self, ttl: Duration, index: usize, now: i64) -> Duration {
+        let Some(jitter) = self.ttl_jitter else {
+            return ttl;
+        };
+        // Seeding with the key and the time of the write gives every write its own offset
+        let fraction = random_fraction(self.hash_at(index) ^ now as u64);
+        ttl - jitter.min(ttl).mul_f64(fraction)
+    }
+
+
+    /// Recompute the expiration time of the element stored at `index` in the slab after it was accessed
+    ///
+    /// An element expires at its write deadline or once it has been idle for the expire-after-access
+    /// duration, whichever comes first.
+    fn refresh_expiry(&mut self, index: usize, now: i64) {
+        let write_deadline = match self.metadata(index) {
+            Some(meta) => meta.write_deadline,
+            None => return,
+        };
+        let access_deadline = self.expire_after_access.map(|idle| deadline(now, idle));
+        let expires_at = match (write_deadline, access_deadline) {
+            (Some(write), Some(access)) => Some(write.min(access)),
+            (write, access) => write.or(access),
+        };
+        self.set_expiry(index, expires_at);
+    }
+
+
+    /// Set or clear the expiration time of the element stored at `index` in the slab
+    fn set_expiry(&mut self, index: usize, expires_at: Option<i64>) {
+        if let Some(node) = self.slab.get_mut(index) {
+            if let Some(old) = std::mem::replace(&mut node.metadata.expires_at, expires_at) {
+                self.expiry.remove(&(old, index));
+            }
+            if let Some(new) = expires_at {
+                self.expiry.insert((new, index));
+            }
+        }
+    }
+
+
+    /// The current time according to the clock as a UTC UNIX timestamp in us
+    pub(crate) fn now(&self) -> i64 {
+        self.clock.now_micros()
+    }
+
+
+    /// Track the generation of a slot the slab handed out for a new element
+    fn claim_slot(&mut self, index: usize) {
+        if self.generations.len() <= index {
+            self.generations.resize(index + 1, 0);
+        }
+    }
+
+
+    /// The index of the element `token` was created for, if it is still in the cache
+    fn resolve(&self, token: &Token) -> Option<usize> {
+        let current = self.slab.contains(token.index) && self.generations[token.index] == token.generation;
+        current.then_some(token.index)
+    }
+
+
+    /// The index of the element stored under `key` outside of any namespace in the slab, even if
+    /// it has expired
+    fn find<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find_in(None, key)
+    }
+
+
+    /// The index of the element stored under `key` in `namespace` in the slab, even if it has
+    /// expired
+    pub(crate) fn find_in<Q>(&self, namespace: Option<u32>, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find_hashed(namespace, key, self.hash_in(namespace, key))
+    }
+
+
+    /// Like `find_in` with the precomputed hash of `key`
+    fn find_hashed<Q>(&self, namespace: Option<u32>, key: &Q, hash: u64) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.key_map.find(hash, |index| {
+            let metadata = &self.slab[index].metadata;
+            metadata.namespace == namespace && metadata.user_key.borrow() == key
+        })
+    }
+
+
+    /// The hash `key` is indexed under in `namespace`, keys outside of a namespace hash like
+    /// themselves
+    fn hash_in<Q: Hash + ?Sized>(&self, namespace: Option<u32>, key: &Q) -> u64 {
+        match namespace {
+            Some(namespace) => self.key_map.hash(&(namespace, key)),
+            None => self.key_map.hash(key),
+        }
+    }
+
+
+    /// The hash the element stored at `index` in the slab is indexed under
+    fn hash_at(&self, index: usize) -> u64 {
+        let metadata = &self.slab[index].metadata;
+        self.hash_in(metadata.namespace, &metadata.user_key)
+    }
+
+
+    /// Find the index of a live element, lazily removing it if it has expired
+    fn lookup<Q>(&mut self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.lookup_at(key, self.now())
+    }
+
+
+    /// Find the index of an element that is live at timestamp `now`, lazily removing it if not
+    fn lookup_at<Q>(&mut self, key: &Q, now: i64) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.lookup_in(None, key, now)
+    }
+
+
+    /// Like `lookup_at` for a key in `namespace`
+    pub(crate) fn lookup_in<Q>(&mut self, namespace: Option<u32>, key: &Q, now: i64) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.lookup_hashed(namespace, key, self.hash_in(namespace, key), now)
+    }
+
+
+    /// Like `lookup_in` for a key whose hash is already known
+    fn lookup_hashed<Q>(&mut self, namespace: Option<u32>, key: &Q, hash: u64, now: i64) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.count_in_sketch(hash);
+        let index = self.find_hashed(namespace, key, hash)?;
+        if self.is_expired_at(index, now) {
+            self.evict_index(index, RemovalCause::Expired);
+            return None;
+        }
+        self.sync_estimate(index);
+        Some(index)
+    }
+
+
+    /// Whether the element stored at `index` in the slab is past its expiration time
+    fn is_expired(&self, index: usize) -> bool {
+        self.is_expired_at(index, self.now())
+    }
+
+
+    /// Whether the element stored at `index` in the slab is past its expiration time at `now`
+    fn is_expired_at(&self, index: usize, now: i64) -> bool {
+        match self.metadata(index).and_then(|meta| meta.expires_at) {
+            Some(expires_at) => expires_at <= now,
+            None => false,
+        }
+    }
+
+
+    /// Whether the TinyLFU filter lets a new key with the given hash into the cache
+    ///
+    /// Keys are always admitted while the cache has room or if the eviction victim has expired.
+    /// Otherwise the key has to be estimated more frequent than the victim.
+    fn admit(&self, hash: u64) -> bool {
+        let Some(sketch) = self.sketch.as_ref().filter(|_| self.admission) else {
+            return true;
+        };
+        if self.slab.len() < self.capacity {
+            return true;
+        }
+        match self.eviction_candidate(None) {
+            Some(victim) if !self.is_expired(victim) => {
+                sketch.estimate(hash) > sketch.estimate(self.hash_at(victim))
+            }
+            _ => true,
+        }
+    }
+
+
+    /// The weight of an element according to the weigher
+    fn weigh(&self, key: &K, value: &V) -> u64 {
+        match &self.weigher {
+            Some(weigher) => weigher(key, value),
+            None => 1,
+        }
+    }
+
+
+    /// Whether the cache would hold more elements or more weight than it is allowed to after adding
+    /// `count` elements weighing `weight` in total
+    fn over_bounds(&self, count: usize, weight: u64) -> bool {
+        self.slab.len() + count > self.capacity
+            || self.max_weight.is_some_and(|max_weight| self.total_weight + weight > max_weight)
+    }
+
+
+    /// Whether an element weighing `weight` fits into the cache after evicting every element that
+    /// can be evicted, replacing the element at `replacing` if given
+    fn check_room(&self, weight: u64, replacing: Option<usize>) -> Result<(), CacheError> {
+        if let Some(max_weight) = self.max_weight.filter(|&max_weight| weight > max_weight) {
+            return Err(CacheError::TooLarge { weight, max_weight });
+        }
+        let count = usize::from(replacing.is_none());
+        let freed = replacing.map_or(0, |index| self.slab[index].metadata.weight);
+        let fits = |len: usize, total: u64| {
+            len <= self.capacity && self.max_weight.is_none_or(|max_weight| total <= max_weight)
+        };
+        if fits(self.slab.len() + count, self.total_weight - freed + weight) {
+            return Ok(());
+        }
+        // A single victim is enough unless pinned elements made the cache grow or weights count
+        if self.max_weight.is_none() && self.slab.len() + count <= self.capacity + 1 {
+            return match self.eviction_candidate(replacing) {
+                Some(_) => Ok(()),
+                None => Err(CacheError::CapacityExhausted),
+            };
+        }
+        let (pinned, pinned_weight) = self.pinned_totals(replacing);
+        if fits(pinned + 1, pinned_weight + weight) {
+            Ok(())
+        } else {
+            Err(CacheError::CapacityExhausted)
+        }
+    }
+
+
+    /// The number and total weight of the pinned elements that have not expired, other than the
+    /// one at `exclude`
+    fn pinned_totals(&self, exclude: Option<usize>) -> (usize, u64) {
+        let now = self.now();
+        self.slab
+            .iter()
+            .filter(|&(index, node)| node.metadata.pinned && Some(index) != exclude && !self.is_expired_at(index, now))
+            .fold((0, 0), |(len, weight), (_, node)| (len + 1, weight + node.metadata.weight))
+    }
+
+
+    /// Evict elements until `count` more elements weighing `weight` in total fit into the cache,
+    /// never evicting the element at `protect`
+    ///
+    /// Making room before storing a new element lets it reuse the slot of its victim, so the slab
+    /// never grows beyond the capacity it was allocated with.
+    fn make_room(&mut self, count: usize, weight: u64, protect: Option<usize>) {
+        while let Some((victim, cause)) = self.next_victim(count, weight, protect) {
+            self.evict_index(victim, cause);
+        }
+    }
+
+
+    /// The next element `make_room` has to evict and the cause of its removal, if any
+    fn next_victim(&mut self, count: usize, weight: u64, protect: Option<usize>) -> Option<(usize, RemovalCause)> {
+        if !self.over_bounds(count, weight) {
+            return None;
+        }
+        self.pick_victim(protect)
+    }
+
+
+    /// The element to evict next other than the one at `protect` and the cause of its removal,
+    /// advancing the hand of a CLOCK cache or the queues of an S3-FIFO cache
+    fn pick_victim(&mut self, protect: Option<usize>) -> Option<(usize, RemovalCause)> {
+        match self.policy {
+            Policy::Clock => self.advance_hand(protect),
+            Policy::S3Fifo { .. } => self.advance_queues(protect),
+            Policy::Lru | Policy::Slru { .. } | Policy::Lirs { .. } | Policy::Fifo | Policy::Random { .. } => {}
+        }
+        let victim = match self.policy {
+            Policy::Random { .. } => self.random_victim(protect)?,
+            _ => self.eviction_candidate(protect)?,
+        };
+        let cause = if self.is_expired(victim) {
+            RemovalCause::Expired
+        } else {
+            RemovalCause::Capacity
+        };
+        Some((victim, cause))
+    }
+
+
+    /// The element a random cache evicts next other than the one at `protect`: the earliest expired
+    /// element if there is one, otherwise a random element of the lowest priority with an
+    /// evictable element
+    ///
+    /// Sampling slab slots finds an element in a few probes while the cache is nearly full, a
+    /// sparse slab falls back to the front of the usage list.
+    fn random_victim(&mut self, protect: Option<usize>) -> Option<usize> {
+        let candidate = self.eviction_candidate(protect)?;
+        if self.is_expired(candidate) {
+            return Some(candidate);
+        }
+        let priority = self.slab[candidate].metadata.priority;
+        for _ in 0..RANDOM_PROBES {
+            self.random_state = self.random_state.wrapping_add(1);
+            let slot = (random_fraction(self.random_state) * self.slab.capacity() as f64) as usize;
+            let eligible = self.slab.get(slot).is_some_and(|node| node.metadata.priority == priority);
+            if eligible && self.evictable(slot, protect) {
+                return Some(slot);
+            }
+        }
+        Some(candidate)
+    }
+
+
+    /// The element to evict next other than the one at `protect`: the earliest expired element if
+    /// there is one, otherwise the LRU element, preferring the probationary segment under SLRU and
+    /// the queue that is over its share under S3-FIFO. Pinned elements are skipped.
+    fn eviction_candidate(&self, protect: Option<usize>) -> Option<usize> {
+        let now = self.now();
+        let expired = self
+            .expiry
+            .iter()
+            .take_while(|&&(expires_at, _)| expires_at <= now)
+            .find(|&&(_, index)| self.evictable(index, protect));
+        if let Some(&(_, index)) = expired {
+            return Some(index);
+        }
+        self.eviction_order().find(|&index| self.evictable(index, protect))
+    }
+
+
+    /// The indices of all elements in eviction order, ignoring expiration
+    fn eviction_order(&self) -> EvictionOrder<'_, K, V> {
+        std::array::from_fn(|list| {
+            let priority = list / 2;
+            if (list % 2 == 0) == self.evicts_small_first(priority) {
+                self.usage[priority].iter(&self.slab)
+            } else {
+                self.protected[priority].iter(&self.slab)
+            }
+        })
+        .into_iter()
+        .flatten()
+    }
+
+
+    /// Whether the usage list of `priority` is evicted before its protected segment
+    ///
+    /// An S3-FIFO cache evicts from its small queue while it holds at least its share of the
+    /// capacity or the main queue is empty, and from the main queue otherwise.
+    fn evicts_small_first(&self, priority: usize) -> bool {
+        let Policy::S3Fifo { small_ratio } = self.policy else {
+            return true;
+        };
+        let small = self.usage.iter().map(List::len).sum::<usize>();
+        self.protected[priority].len() == 0 || small as f64 >= self.capacity as f64 * small_ratio
+    }
+
+
+    /// Whether the element at `index` may be evicted, i.e. it is neither pinned nor at `protect`
+    fn evictable(&self, index: usize, protect: Option<usize>) -> bool {
+        Some(index) != protect && !self.slab[index].metadata.pinned
+    }
+
+
+    /// Unlink an element from the usage list or the protected segment of its priority
+    fn detach(&mut self, index: usize) {
+        let Some(meta) = self.metadata(index) else {
+            return;
+        };
+        let priority = meta.priority as usize;
+        let list = if meta.protected {
+            &mut self.protected[priority]
+        } else {
+            &mut self.usage[priority]
+        };
+        list.unlink(&mut self.slab, index);
+    }
+
+
+    /// Move an element to the most recently used end of the usage list of `priority`
+    fn set_priority(&mut self, index: usize, priority: Priority) {
+        self.detach(index);
+        if let Some(meta) = self.metadata_mut(index) {
+            meta.priority = priority;
+            meta.protected = false;
+        }
+        self.usage[priority as usize].push_back(&mut self.slab, index);
+    }
+
+
+    /// Advance the hand of a CLOCK cache to the next element to evict
+    ///
+    /// Every priority has its own hand at the front of its usage list. Starting with the lowest
+    /// priority, referenced elements in front of a hand lose their mark and are moved behind it
+    /// until an unreferenced element other than `protect` is found.
+    fn advance_hand(&mut self, protect: Option<usize>) {
+        for usage in 0..PRIORITIES {
+            for _ in 0..=self.usage[usage].len() {
+                let Some(front) = self.usage[usage].front() else {
+                    break;
+                };
+                let referenced = self
+                    .slab
+                    .get_mut(front)
+                    .is_some_and(|node| std::mem::take(&mut node.metadata.referenced));
+                if !referenced && self.evictable(front, protect) {
+                    return;
+                }
+                self.usage[usage].move_to_back(&mut self.slab, front);
+            }
+        }
+    }
+
+
+    /// Advance the queues of an S3-FIFO cache to the next element to evict
+    ///
+    /// Starting with the lowest priority, accessed elements at the front of the queue to evict from
+    /// are moved out of the way: from the small queue to the back of the main queue, and within the
+    /// main queue to its back with one access less. This stops at the first element other than
+    /// `protect` that was not accessed.
+    fn advance_queues(&mut self, protect: Option<usize>) {
+        for priority in 0..PRIORITIES {
+            let limit = 5 * (self.usage[priority].len() + self.protected[priority].len());
+            for _ in 0..=limit {
+                let small = self.evicts_small_first(priority);
+                let queue = if small { &self.usage[priority] } else { &self.protected[priority] };
+                let Some(front) = queue.front() else {
+                    break;
+                };
+                let hits = self.slab[front].metadata.fifo_hits;
+                if hits == 0 && self.evictable(front, protect) {
+                    return;
+                }
+                let meta = &mut self.slab[front].metadata;
+                if small && hits > 0 {
+                    meta.fifo_hits = 0;
+                    meta.protected = true;
+                    self.usage[priority].unlink(&mut self.slab, front);
+                    self.protected[priority].push_back(&mut self.slab, front);
+                } else if small {
+                    self.usage[priority].move_to_back(&mut self.slab, front);
+                } else {
+                    meta.fifo_hits = hits.saturating_sub(1);
+                    self.protected[priority].move_to_back(&mut self.slab, front);
+                }
+            }
+        }
+    }
+
+
+    /// Move an element to the most recently used end of the segment it is in
+    ///
+    /// CLOCK and S3-FIFO caches never reorder elements on access, the element is marked as
+    /// referenced or its accesses are counted instead. FIFO and random caches ignore accesses.
+    fn requeue(&mut self, index: usize) {
+        let policy = self.policy;
+        let Some(meta) = self.metadata_mut(index) else {
+            return;
+        };
+        match policy {
+            Policy::Clock => {
+                meta.referenced = true;
+                return;
+            }
+            Policy::S3Fifo { .. } => {
+                meta.fifo_hits = (meta.fifo_hits + 1).min(3);
+                return;
+            }
+            Policy::Fifo | Policy::Random { .. } => return,
+            Policy::Lru | Policy::Slru { .. } | Policy::Lirs { .. } => {}
+        }
+        let priority = meta.priority as usize;
+        let list = if meta.protected {
+            &mut self.protected[priority]
+        } else {
+            &mut self.usage[priority]
+        };
+        list.move_to_back(&mut self.slab, index);
+    }
+
+
+    /// Move an element of an SLRU cache to the most recently used end of the protected segment
+    ///
+    /// If the protected segments of all priorities outgrow their share of the capacity, the least
+    /// recently used elements of the lowest priority are demoted to the most recently used end of
+    /// the probationary segment of their priority.
+    fn promote(&mut self, index: usize, protected_ratio: f64) {
+        self.detach(index);
+        let Some(meta) = self.metadata_mut(index) else {
+            return;
+        };
+        meta.protected = true;
+        let priority = meta.priority as usize;
+        self.protected[priority].push_back(&mut self.slab, index);
+        let limit = (self.capacity as f64 * protected_ratio) as usize;
+        while self.protected.iter().map(List::len).sum::<usize>() > limit {
+            let Some(segment) = self.protected.iter().position(|list| list.len() > 0) else {
+                break;
+            };
+            let Some(demoted) = self.protected[segment].pop_front(&mut self.slab) else {
+                break;
+            };
+            if let Some(meta) = self.metadata_mut(demoted) {
+                meta.protected = false;
+            }
+            self.usage[segment].push_back(&mut self.slab, demoted);
+        }
+    }
+
+
+    /// Move an accessed element of a LIRS cache to the top of the recency stack
+    ///
+    /// A LIR element moves to the back of the protected segment. A HIR element that was still in
+    /// the stack was reused within the reuse distance of the LIR set and becomes LIR, otherwise it
+    /// moves to the back of the usage list.
+    fn reuse_lirs(&mut self, index: usize, hir_ratio: f64) {
+        let hash = self.hash_at(index);
+        let bottom = self.lirs.bottom().is_some_and(|(bottom, _)| bottom == hash);
+        let reused = self.lirs.touch(hash, index);
+        let meta = &self.slab[index].metadata;
+        if meta.protected {
+            self.protected[meta.priority as usize].move_to_back(&mut self.slab, index);
+            if bottom {
+                self.prune_lirs();
+            }
+        } else {
+            self.place_lirs(index, reused, hir_ratio);
+        }
+    }
+
+
+    /// Make the HIR element at `index` of a LIRS cache LIR if it was `reused` within the reuse
+    /// distance of the LIR set or the LIR set has room, demoting the bottom LIR element if the LIR
+    /// set outgrows its share of the capacity. Otherwise the element moves to the back of the
+    /// usage list of its priority.
+    fn place_lirs(&mut self, index: usize, reused: bool, hir_ratio: f64) {
+        let hir = ((self.capacity as f64 * hir_ratio).ceil() as usize).max(1);
+        let limit = self.capacity.saturating_sub(hir);
+        let lir = self.protected.iter().map(List::len).sum::<usize>();
+        let priority = self.slab[index].metadata.priority as usize;
+        if !reused && lir >= limit {
+            self.usage[priority].move_to_back(&mut self.slab, index);
+            return;
+        }
+        self.usage[priority].unlink(&mut self.slab, index);
+        self.slab[index].metadata.protected = true;
+        self.protected[priority].push_back(&mut self.slab, index);
+        if lir + 1 > limit {
+            if let Some((_, Some(bottom))) = self.lirs.bottom() {
+                self.detach(bottom);
+                let meta = &mut self.slab[bottom].metadata;
+                meta.protected = false;
+                self.usage[meta.priority as usize].push_back(&mut self.slab, bottom);
+            }
+        }
+        self.prune_lirs();
+    }
+
+
+    /// Remove the keys at the bottom of the recency stack of a LIRS cache until the bottom key is
+    /// a LIR element
+    fn prune_lirs(&mut self) {
+        while let Some((hash, index)) = self.lirs.bottom() {
+            if index.is_some_and(|index| self.slab[index].metadata.protected) {
+                break;
+            }
+            self.lirs.remove(hash);
+        }
+    }
+
+
+    /// Update the access time, frequency and recency of the element stored at `index` in the slab
+    fn record_access(&mut self, index: usize) {
+        self.record_access_at(index, self.now());
+    }
+
+
+    /// Hand `metric` to the stats sink, if any
+    fn record_metric(&self, metric: CacheMetric) {
+        if let Some(sink) = self.sink.as_ref() {
+            sink.record(metric);
+        }
+    }
+
+
+    /// Record a lookup by key that missed at timestamp `now`, `hash` computes the hash of the key
+    /// if an observer needs it
+    fn record_miss<F: FnOnce(&Self) -> u64>(&mut self, now: i64, hash: F) {
+        self.statistics.miss_at(now);
+        self.record_metric(CacheMetric::Miss);
+        let observed = self.trace.is_some() || cfg!(any(feature = "events", feature = "tracing"));
+        if observed || self.history.is_some() {
+            let hash = hash(self);
+            if self.history.as_ref().is_some_and(|history| history.get(hash).is_some()) {
+                self.statistics.re_missed();
+            }
+            self.record_trace_access(hash, AccessKind::Miss, now);
+            #[cfg(feature = "tracing")]
+            tracing::trace!(key_hash = hash, "cache miss");
+            #[cfg(feature = "events")]
+            self.emit(|_| CacheEvent::Miss(hash));
+        }
+    }
+
+
+    /// Hand an access to the key with the given hash at timestamp `now` to the trace recorder, if
+    /// any
+    fn record_trace_access(&self, key_hash: u64, kind: AccessKind, now: i64) {
+        if let Some(trace) = self.trace.as_ref() {
+            trace.record(TraceRecord { key_hash, timestamp: now, kind });
+        }
+    }
+
+
+    /// Record an access to the element stored at `index` in the slab at timestamp `now`
+    fn record_access_at(&mut self, index: usize, now: i64) {
+        self.record_metric(CacheMetric::Hit);
+        if self.trace.is_some() {
+            self.record_trace_access(self.hash_at(index), AccessKind::Hit, now);
+        }
+        #[cfg(feature = "tracing")]
+        tracing::trace!(key_hash = self.hash_at(index), "cache hit");
+        #[cfg(feature = "events")]
+        self.emit(|cache| CacheEvent::Hit(cache.key_at(index).clone()));
+        if let Some(node) = self.slab.get_mut(index) {
+            node.metadata.record_hit(now, !self.estimated_frequencies);
+            self.statistics.hit_at(now);
+            if let Some(namespace) = node.metadata.namespace {
+                self.namespaces[namespace as usize].1.hit_at(now);
+            }
+            if node.metadata.negative {
+                self.statistics.negative_hit();
+            }
+            if node.metadata.refresh_at.is_some_and(|refresh_at| refresh_at <= now) {
+                node.metadata.refresh_at = None;
+                self.refresh_candidates.push(node.metadata.user_key.clone());
+            }
+        }
+        self.reorder_accessed(index, now);
+    }
+
+
+    /// Extend the expiration of the element accessed at `index` and reorder it per the policy
+    fn reorder_accessed(&mut self, index: usize, now: i64) {
+        if self.expire_after_access.is_some() {
+            self.refresh_expiry(index, now);
+        }
+        match self.policy {
+            Policy::Lru | Policy::Clock | Policy::S3Fifo { .. } | Policy::Fifo | Policy::Random { .. } => {
+                self.requeue(index)
+            }
+            Policy::Slru { protected_ratio } => self.promote(index, protected_ratio),
+            Policy::Lirs { hir_ratio } => self.reuse_lirs(index, hir_ratio),
+        }
+    }
+
+
+    /// Mark an element as used without reading it, e.g. when an external event should keep it alive
+    ///
+    /// The element is promoted and its access time and frequency are updated like in `get`, but
+    /// no hit is recorded. Returns whether the key was present.
+    pub fn touch<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_quiet(key).is_some()
+    }
+
+
+    /// Get a value and promote it like `get` without recording a hit or a miss
+    ///
+    /// Meant for bookkeeping reads that should not skew the hit ratio. The access time and
+    /// frequency of the element are updated, its hit count is not.
+    pub fn get_quiet<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.lookup(key)?;
+        let now = self.now();
+        self.slab[index].metadata.record_touch(now, !self.estimated_frequencies);
+        self.reorder_accessed(index, now);
+        self.slab.get(index).map(|node| &node.value)
+    }
+
+
+    /// Get a value from the cache and update its access time and frequency
+    ///
+    /// The key may be any borrowed form of the key type, e.g. `&str` for `String` keys.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, fields(key_hash = self.key_map.hash(key)))
+    )]
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.lookup(key) {
+            Some(usize_key) => {
+                self.record_access(usize_key);
+                self.slab.get(usize_key).map(|node| &node.value)
+            }
+            None => {
+                self.record_miss(self.now(), |cache| cache.key_map.hash(key));
+                None
+            }
+        }
+    }
+
+
+    /// Return the cached value for `key` like `get`, reloading it with `load` on a miss or when it
+    /// expires early
+    ///
+    /// Implements probabilistic early expiration (XFetch) against cache stampedes: instead of
+    /// every caller missing at once when a popular value expires, each read treats the value as
+    /// expired with a probability that rises as the expiration approaches, so a single caller
+    /// usually reloads it ahead of time. The window grows with the time the previous load took
+    /// and with `beta`, 1.0 being the usual choice and larger values reloading earlier. Values
+    /// without a TTL or written without a recorded load duration never expire early. An early
+    /// expiration counts as a miss and the reloaded value keeps the TTL policy of the cache.
+    pub fn get_with_beta<F: FnOnce() -> V>(&mut self, key: K, beta: f64, load: F) -> &V {
+        let now = self.now();
+        let present = self.lookup_at(&key, now);
+        if let Some(index) = present.filter(|&index| !self.expires_early(index, beta, now)) {
+            self.record_access(index);
+            return &self.slab[index].value;
+        }
+        self.record_miss(now, |cache| cache.key_map.hash(&key));
+        let value = load();
+        let index = match present {
+            Some(index) => {
+                self.replace_index(index, value, None);
+                index
+            }
+            None => self.insert_new(key, value, None, Priority::Normal),
+        };
+        self.record_load(index, now);
+        &self.slab[index].value
+    }
+
+
+    /// Record that the value of the element stored at `index` in the slab was loaded by a loader
+    /// that started at timestamp `started`
+    pub(crate) fn record_load(&mut self, index: usize, started: i64) {
+        let micros = u64::try_from(self.now().saturating_sub(started)).unwrap_or(0);
+        self.slab[index].metadata.load_duration = Some(micros);
+        self.statistics.loaded(Duration::from_micros(micros));
+    }
+
+
+    /// Insert a value like `insert` that a loader started to load at timestamp `started`
+    pub(crate) fn insert_loaded(&mut self, key: K, value: V, started: i64) -> Option<V> {
+        let (previous, index) = self.insert_indexed(key, value, None, None, None);
+        if let Some(index) = index {
+            self.record_load(index, started);
+        }
+        previous
+    }
+
+
+    /// Whether the element stored at `index` in the slab is treated as expired at `now` by the
+    /// probabilistic early expiration of `get_with_beta`
+    fn expires_early(&self, index: usize, beta: f64, now: i64) -> bool {
+        let metadata = &self.slab[index].metadata;
+        let (Some(expires_at), Some(load_duration)) = (metadata.expires_at, metadata.load_duration) else {
+            return false;
+        };
+        // -ln(u) for u in (0, 1] is exponentially distributed, mostly small but unbounded
+        let draw = -(1.0 - random_fraction(self.hash_at(index) ^ now as u64)).ln();
+        now as f64 + load_duration as f64 * beta * draw >= expires_at as f64
+    }
+
+
+    /// Get the values of several keys at once, updating their metadata like `get`
+    ///
+    /// The values are returned in the order of the keys. The whole batch is processed with a single
+    /// timestamp, which amortizes reading the clock over many keys.
+    pub fn get_many<'a, Q, I>(&mut self, keys: I) -> Vec<Option<&V>>
+    where
+        I: IntoIterator<Item = &'a Q>,
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + 'a,
+    {
+        let now = self.now();
+        let indices: Vec<Option<usize>> = keys
+            .into_iter()
+            .map(|key| {
+                let index = self.lookup_at(key, now);
+                match index {
+                    Some(index) => self.record_access_at(index, now),
+                    None => self.record_miss(now, |cache| cache.key_map.hash(key)),
+                }
+                index
+            })
+            .collect();
+        indices
+            .into_iter()
+            .map(|index| self.slab.get(index?).map(|node| &node.value))
+            .collect()
+    }
+
+
+    /// Get a mutable reference to a value and update its access time and frequency like `get`
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, fields(key_hash = self.key_map.hash(key)))
+    )]
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.lookup(key) {
+            Some(index) => {
+                self.record_access(index);
+                self.slab.get_mut(index).map(|node| &mut node.value)
+            }
+            None => {
+                self.record_miss(self.now(), |cache| cache.key_map.hash(key));
+                None
+            }
+        }
+    }
+
+
+    /// Get a value from the cache without updating its recency, metadata or the statistics
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.peek_in(None, key)
+    }
+
+
+    /// Like `peek` for a key in `namespace`
+    pub(crate) fn peek_in<Q>(&self, namespace: Option<u32>, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.find_in(namespace, key)?;
+        if self.is_expired(index) {
+            return None;
+        }
+        self.slab.get(index).map(|node| &node.value)
+    }
+
+
+    /// Insert a value like `insert` and attach `tags` to the element, replacing the tags of a
+    /// present key
+    ///
+    /// `invalidate_tag` removes every element carrying a tag at once, e.g. every cached page that
+    /// was rendered from a record when the record changes. Replacing the value with `insert` keeps
+    /// the tags of the element.
+    pub fn insert_tagged<I, T>(&mut self, key: K, value: V, tags: I) -> Option<V>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<str>,
+    {
+        let (previous, index) = self.insert_indexed(key, value, None, None, None);
+        if let Some(index) = index {
+            let mut tags: Vec<Arc<str>> = tags.into_iter().map(|tag| Arc::from(tag.as_ref())).collect();
+            tags.sort_unstable();
+            tags.dedup();
+            let old = std::mem::take(&mut self.slab[index].metadata.tags);
+            self.untag(index, &old);
+            for tag in tags.iter() {
+                self.tags.entry(tag.clone()).or_default().insert(index);
+            }
+            self.slab[index].metadata.tags = tags;
+        }
+        previous
+    }
+
+
+    /// Remove every element carrying `tag`, notifying the eviction listener about explicit
+    /// removals, and return how many were removed
+    pub fn invalidate_tag(&mut self, tag: &str) -> usize {
+        let Some(members) = self.tags.remove(tag) else {
+            return 0;
+        };
+        for &index in &members {
+            self.evict_index(index, RemovalCause::Explicit);
+        }
+        members.len()
+    }
+
+
+    /// Forget that the element stored at `index` in the slab carries `tags`
+    fn untag(&mut self, index: usize, tags: &[Arc<str>]) {
+        for tag in tags {
+            if let Some(members) = self.tags.get_mut(tag) {
+                members.remove(&index);
+                if members.is_empty() {
+                    self.tags.remove(tag);
+                }
+            }
+        }
+    }
+
+
+    /// Insert a value like `insert` and return a token for accessing it without hashing its key
+    ///
+    /// Returns `None` if the TinyLFU admission filter rejected the element. Replacing the value of
+    /// a present key keeps its existing tokens valid.
+    pub fn insert_with_token(&mut self, key: K, value: V) -> Option<Token> {
+        let (_, index) = self.insert_indexed(key, value, None, None, None);
+        let index = index?;
+        Some(Token { index, generation: self.generations[index] })
+    }
+
+
+    /// A token for the element stored under `key`
+    pub fn token<Q>(&self, key: &Q) -> Option<Token>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.find(key)?;
+        Some(Token { index, generation: self.generations[index] })
+    }
+
+
+    /// Get a value by its token and update its access time and frequency like `get`
+    ///
+    /// Returns `None` if the element has left the cache since the token was created or has expired.
+    pub fn get_by_token(&mut self, token: &Token) -> Option<&V> {
+        let index = match self.resolve(token) {
+            Some(index) if !self.is_expired(index) => index,
+            expired => {
+                if let Some(index) = expired {
+                    self.evict_index(index, RemovalCause::Expired);
+                }
+                self.statistics.miss_at(self.now());
+                self.record_metric(CacheMetric::Miss);
+                return None;
+            }
+        };
+        if self.sketch.is_some() {
+            self.count_in_sketch(self.hash_at(index));
+            self.sync_estimate(index);
+        }
+        self.record_access(index);
+        self.slab.get(index).map(|node| &node.value)
+    }
+
+
+    /// Get a value by its token without updating its access time, frequency or recency
+    pub fn peek_by_token(&self, token: &Token) -> Option<&V> {
+        let index = self.resolve(token)?;
+        if self.is_expired(index) {
+            return None;
+        }
+        self.slab.get(index).map(|node| &node.value)
+    }
+
+
+    /// Whether a live element is stored under `key`
+    ///
+    /// Unlike `get` this does not count as an access, so it changes neither the metadata, the
+    /// statistics nor the eviction order. Expired elements that were not removed yet are absent.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).is_some_and(|index| !self.is_expired(index))
+    }
+
+
+    /// Whether `key` is in the eviction history, i.e. it was evicted for capacity recently and
+    /// not inserted again since, see `eviction_history`
+    pub fn was_recently_evicted<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.eviction_record(key).is_some()
+    }
+
+
+    /// What the cache knew about `key` when it was recently evicted, see `eviction_history`
+    pub fn eviction_record<Q>(&self, key: &Q) -> Option<EvictionRecord>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.history.as_ref()?.get(self.hash_in(None, key)).copied()
+    }
+
+
+    /// Describe how the element stored under `key` has been used without counting as an access
+    ///
+    /// Finding the position of the element walks the eviction order, so this takes O(n) time.
+    pub fn entry_stats<Q>(&self, key: &Q) -> Option<EntryStats>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.find(key)?;
+        if self.is_expired(index) {
+            return None;
+        }
+        let metadata = &self.slab[index].metadata;
+        Some(EntryStats {
+            frequency: self.frequency_at(index),
+            hits: metadata.hits,
+            last_accessed: metadata.last_accessed,
+            age: metadata.age(self.now()),
+            position: self
+                .eviction_order()
+                .position(|other| other == index)?,
+        })
+    }
+
+
+    /// Copy every live element with its metadata in eviction order
+    ///
+    /// The copy does not borrow the cache, so it can be handed to another thread for analysis.
+    /// Taking it does not count as an access. Elements of every namespace are included.
+    pub fn snapshot(&self) -> Vec<(K, V, EntrySnapshot)>
+    where
+        V: Clone,
+    {
+        let now = self.now();
+        self.eviction_order()
+            .filter(|&index| !self.is_expired_at(index, now))
+            .enumerate()
+            .map(|(position, index)| {
+                let Node { value, metadata, .. } = &self.slab[index];
+                let entry = EntrySnapshot {
+                    frequency: self.frequency_at(index),
+                    hits: metadata.hits,
+                    created_at: metadata.created_at,
+                    last_accessed: metadata.last_accessed,
+                    expires_at: metadata.expires_at,
+                    weight: metadata.weight,
+                    priority: metadata.priority,
+                    position,
+                };
+                (metadata.user_key.clone(), value.clone(), entry)
+            })
+            .collect()
+    }
+
+
+    /// The hash of `key` computed by the hasher of the cache, e.g. to match it against the hash of
+    /// a `CacheEvent::Miss`
+    pub fn hash_key<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        self.key_map.hash(key)
+    }
+
+
+    /// The access frequency of the element stored under `key`, estimated by the sketch if the
+    /// cache uses `estimated_frequencies`
+    pub fn frequency<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.find(key)?;
+        if self.is_expired(index) {
+            return None;
+        }
+        Some(self.frequency_at(index))
+    }
+
+
+    /// Record an occurrence of the key with the given hash in the sketch, if any
+    fn count_in_sketch(&mut self, hash: u64) {
+        let halved = self.sketch.as_mut().is_some_and(|sketch| sketch.increment(hash));
+        if halved {
+            self.sync_estimates();
+        }
+    }
+
+
+    /// Copy the estimate of the sketch into the metadata of the element stored at `index` in the
+    /// slab if the cache uses `estimated_frequencies`
+    fn sync_estimate(&mut self, index: usize) {
+        if self.estimated_frequencies {
+            self.slab[index].metadata.frequency = self.frequency_at(index);
+        }
+    }
+
+
+    /// Copy the estimates of the sketch into the metadata of every element, e.g. after the sketch
+    /// halved its counters
+    fn sync_estimates(&mut self) {
+        if self.estimated_frequencies {
+            let indices: Vec<usize> = self.slab.iter().map(|(index, _)| index).collect();
+            for index in indices {
+                self.sync_estimate(index);
+            }
+        }
+    }
+
+
+    /// The access frequency of the element stored at `index` in the slab
+    fn frequency_at(&self, index: usize) -> usize {
+        match self.sketch.as_ref().filter(|_| self.estimated_frequencies) {
+            Some(sketch) => usize::from(sketch.estimate(self.hash_at(index))),
+            None => self.slab[index].metadata.frequency,
+        }
+    }
+
+
+    /// Get a mutable reference to a value without updating its recency, metadata or the statistics
+    pub fn peek_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.find(key)?;
+        if self.is_expired(index) {
+            return None;
+        }
+        self.slab.get_mut(index).map(|node| &mut node.value)
+    }
+
+
+    /// Return the least recently used element in the cache
+    pub fn get_lru(&self) -> Option<&V> {
+        let key = self.eviction_order().next()?;
+        self.slab.get(key).map(|node| &node.value)
+    }
+
+
+    /// Remove the least recently used element from the cache and return it
+    ///
+    /// Expired elements are dropped along the way, so repeatedly popping drains the live elements
+    /// in eviction order.
+    pub fn pop_lru(&mut self) -> Option<(K, V, Metadata<K>)> {
+        let (value, metadata) = self.pop_lru_node()?;
+        Some((metadata.user_key.clone(), value, metadata))
+    }
+
+
+    /// Return the most recently used element in the cache, the one that would be evicted last
+    pub fn get_mru(&self) -> Option<&V> {
+        let key = self.eviction_order().next_back()?;
+        self.slab.get(key).map(|node| &node.value)
+    }
+
+
+    /// Remove the most recently used element from the cache and return it
+    ///
+    /// Expired elements are dropped along the way like in `pop_lru`.
+    pub fn pop_mru(&mut self) -> Option<(K, V, Metadata<K>)> {
+        let (value, metadata) = self.pop_with(|cache| cache.eviction_order().next_back())?;
+        Some((metadata.user_key.clone(), value, metadata))
+    }
+
+
+    /// Remove and return the live element selected by `next`, evicting expired elements it selects
+    fn pop_with<F>(&mut self, next: F) -> Option<(V, Metadata<K>)>
+    where
+        F: Fn(&Self) -> Option<usize>,
+    {
+        loop {
+            let index = next(self)?;
+            if self.is_expired(index) {
+                self.evict_index(index, RemovalCause::Expired);
+                continue;
+            }
+            return self.remove_node(index, RemovalCause::Explicit);
+        }
+    }
+
+
+    /// Remove all elements and return them as an iterator from the least to the most recently used
+    /// element, keeping the allocated memory
+    ///
+    /// Expired elements are dropped along the way like in `pop_lru`. Elements that were not yielded
+    /// when the iterator is dropped are removed as well.
+    pub fn drain(&mut self) -> Drain<'_, K, V, S> {
+        Drain { cache: self }
+    }
+
+
+    /// Remove the least recently used live element without cloning its key
+    fn pop_lru_node(&mut self) -> Option<(V, Metadata<K>)> {
+        self.pop_with(|cache| cache.eviction_order().next())
+    }
+
+
+    /// Remove all elements from the cache but preserve allocated memory
+    pub fn flush(&mut self) {
+        self.statistics.removed(RemovalCause::Explicit, self.slab.len());
+        for _ in 0..self.slab.len() {
+            self.record_metric(CacheMetric::Removal(RemovalCause::Explicit));
+        }
+        self.statistics.update_size(0);
+        for (_, statistics) in self.namespaces.iter_mut() {
+            statistics.removed(RemovalCause::Explicit, statistics.get_current_size());
+            statistics.update_size(0);
+        }
+        for (index, _) in self.slab.iter() {
+            self.generations[index] = self.generations[index].wrapping_add(1);
+        }
+        if let Some(listener) = self.listener.as_mut() {
+            for node in self.slab.drain() {
+                listener(node.metadata.user_key, node.value, RemovalCause::Explicit);
+            }
+        }
+        self.slab.clear();
+        for list in self.usage.iter_mut().chain(self.protected.iter_mut()) {
+            list.clear();
+        }
+        self.expiry.clear();
+        self.total_weight = 0;
+        self.key_map.clear();
+        self.tags.clear();
+        self.lirs = LirsStack::default();
+    }
+
+
+    /// Give memory that is not needed for the current elements back to the allocator
+    ///
+    /// Meant to be called after `flush` or after shrinking the capacity. Inserts have to allocate
+    /// again afterwards, up to the capacity.
+    pub fn shrink_to_fit(&mut self) {
+        self.slab.shrink_to_fit();
+        self.key_map.shrink_to_fit();
+        self.generations.shrink_to_fit();
+        self.refresh_candidates.shrink_to_fit();
+        if let Some(spill) = self.spill.as_mut() {
+            spill.shrink_to_fit();
+        }
+    }
+
+
+    /// Allocate room for at least `additional` more elements up front
+    ///
+    /// The reservation is capped at the remaining capacity, since the cache never holds more
+    /// elements than that.
+    pub fn reserve(&mut self, additional: usize) {
+        let additional = additional.min(self.remaining_capacity());
+        self.slab.reserve(additional);
+        self.key_map.reserve(additional);
+        let slots = self.slab.capacity().saturating_sub(self.generations.len());
+        self.generations.reserve(slots);
+    }
+
+
+    /// Allocate room for at least `additional` more elements like `reserve`, failing with
+    /// `CacheError::AllocError` instead of aborting when memory runs out
+    ///
+    /// The slab has no fallible allocation API, so its growth is probed by allocating the same
+    /// amount of memory fallibly first. This is a best effort: another thread may take the memory
+    /// in between.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), CacheError> {
+        let additional = additional.min(self.remaining_capacity());
+        let missing = (self.slab.len() + additional).saturating_sub(self.slab.capacity());
+        if missing > 0 {
+            let total = self.slab.capacity().checked_add(missing).ok_or(CacheError::AllocError)?;
+            Vec::<Node<K, V>>::new().try_reserve_exact(total)?;
+            self.slab.reserve(additional);
+        }
+        self.key_map.try_reserve(additional)?;
+        let slots = self.slab.capacity().saturating_sub(self.generations.len());
+        self.generations.try_reserve(slots)?;
+        Ok(())
+    }
+
+
+    /// Insert a value like `insert`, allocating the memory for a new key fallibly with
+    /// `try_reserve` first
+    ///
+    /// Returns `CacheError::AllocError` and leaves the cache unchanged if the memory cannot be
+    /// allocated, dropping the value. A cache that is full reuses the slot of the evicted element
+    /// and does not allocate.
+    pub fn try_insert_alloc(&mut self, key: K, value: V) -> Result<Option<V>, CacheError> {
+        if self.find(&key).is_none() {
+            self.try_reserve(1)?;
+        }
+        Ok(self.insert(key, value))
+    }
+
+
+    /// Returns an iterator over the cache from the least to the most recently used element
+    ///
+    /// This is the order in which elements are evicted, apart from expired elements which are
+    /// evicted first. Expired elements that were not removed yet are included. Iterating does not
+    /// count as an access.
+    pub fn iter(&self) -> CacheIter<'_, K, V, S> {
+        CacheIter {
+            usage: self.eviction_order(),
+            remaining: self.slab.len(),
+            cache: self,
+        }
+    }
+
+
+    /// Returns an iterator over the keys from the least to the most recently used element
+    pub fn keys(&self) -> Keys<'_, K, V, S> {
+        Keys { inner: self.iter() }
+    }
+
+
+    /// Returns an iterator over the values from the least to the most recently used element
+    pub fn values(&self) -> Values<'_, K, V, S> {
+        Values { inner: self.iter() }
+    }
+
+
+    /// Returns an iterator allowing to modify every value in the cache
+    ///
+    /// The elements are visited in the order they are stored in the slab, not in eviction order.
+    /// Expired elements that were not removed yet are included. Iterating does not count as an
+    /// access.
+    pub fn iter_mut(&mut self) -> CacheIterMut<'_, K, V> {
+        CacheIterMut {
+            nodes: self.slab.iter_mut(),
+        }
+    }
+
+
+    /// The `n` live elements with the most hits, starting with the hottest one
+    ///
+    /// Runs in O(len * log n) time by keeping the best candidates in a bounded heap.
+    pub fn hottest(&self, n: usize) -> Vec<(&K, &V, &Metadata<K>)> {
+        self.top_by(n, |metadata| metadata.hits)
+    }
+
+
+    /// The `n` live elements with the fewest hits, starting with the coldest one
+    pub fn coldest(&self, n: usize) -> Vec<(&K, &V, &Metadata<K>)> {
+        self.top_by(n, |metadata| Reverse(metadata.hits))
+    }
+
+
+    /// The `n` live elements with the largest `rank`, largest first, ties broken by slab index
+    fn top_by<T, F>(&self, n: usize, rank: F) -> Vec<(&K, &V, &Metadata<K>)>
+    where
+        T: Ord,
+        F: Fn(&Metadata<K>) -> T,
+    {
+        if n == 0 {
+            return Vec::new();
+        }
+        let mut heap = BinaryHeap::with_capacity(n + 1);
+        for (index, node) in self.slab.iter() {
+            if self.is_expired(index) {
+                continue;
+            }
+            heap.push(Reverse((rank(&node.metadata), index)));
+            if heap.len() > n {
+                heap.pop();
+            }
+        }
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((_, index))| {
+                let node = &self.slab[index];
+                (&node.metadata.user_key, &node.value, &node.metadata)
+            })
+            .collect()
+    }
+
+
+    /// Halve the access frequency of every element
+    ///
+    /// Frequencies only ever grow otherwise, so elements that were hot long ago keep ranking above
+    /// the currently popular ones. Calling this periodically, e.g. from a timer, ages them out.
+    /// Hit counts are not affected. With `estimated_frequencies` the counters of the sketch are
+    /// halved instead, on top of the halving the sketch does on its own.
+    pub fn decay_frequencies(&mut self) {
+        match self.sketch.as_mut().filter(|_| self.estimated_frequencies) {
+            Some(sketch) => {
+                sketch.reset();
+                self.sync_estimates();
+            }
+            None => {
+                for (_, node) in self.slab.iter_mut() {
+                    node.metadata.frequency /= 2;
+                }
+            }
+        }
+    }
+
+
+    /// Returns an iterator over the cache in order of access frequency
+    ///
+    /// Elements with the same frequency are ordered by their last access time, so the least
+    /// recently used of them comes first when ascending.
+    pub fn iter_frequency(&self, order: SortOrder) -> SortedIter<'_, K, V, S> {
+        self.iter_sorted(&[SortKey::Frequency, SortKey::LastAccessed], order)
+    }
+
+
+    /// Returns an iterator over the cache in order of the last access time, oldest first when
+    /// ascending
+    ///
+    /// Unlike the eviction order this does not depend on the list an element is linked into, e.g.
+    /// under SLRU or priorities, so it shows which elements have gone cold.
+    pub fn iter_by_last_accessed(&self, order: SortOrder) -> SortedIter<'_, K, V, S> {
+        self.iter_sorted(&[SortKey::LastAccessed], order)
+    }
+
+
+    /// Returns an iterator over the cache sorted by `keys`, with each key breaking the ties of
+    /// the previous ones
+    ///
+    /// Remaining ties are broken by the insertion order, so the order is always deterministic.
+    /// Descending reverses the whole order, including the tie breaks.
+    pub fn iter_sorted(&self, keys: &[SortKey], order: SortOrder) -> SortedIter<'_, K, V, S> {
+        // A key repeating an earlier one never breaks a tie
+        let mut distinct: Vec<SortKey> = Vec::with_capacity(SORT_KEYS);
+        for &key in keys {
+            if !distinct.contains(&key) {
+                distinct.push(key);
+            }
+        }
+        let heap = self
+            .slab
+            .iter()
+            .map(|(index, node)| {
+                let mut values = [0; SORT_KEYS + 1];
+                for (value, &key) in values.iter_mut().zip(&distinct) {
+                    *value = self.sort_value(key, index);
+                }
+                values[distinct.len()] = node.metadata.sequence as i64;
+                // The heap yields the greatest key first, inverting every value reverses the order
+                if let SortOrder::Ascending = order {
+                    values = values.map(|value| !value);
+                }
+                (values, index)
+            })
+            .collect();
+        SortedIter { heap, cache: self }
+    }
+
+
+    /// The value of `key` for the element stored at `index` in the slab, ascending in the order
+    /// the key sorts in
+    fn sort_value(&self, key: SortKey, index: usize) -> i64 {
+        let metadata = &self.slab[index].metadata;
+        match key {
+            SortKey::Frequency => self.frequency_at(index) as i64,
+            SortKey::Hits => metadata.hits as i64,
+            SortKey::Age => !metadata.created_at,
+            SortKey::LastAccessed => metadata.last_accessed,
+        }
+    }
+
+
+    /// Returns an iterator over the cache in the order the elements were inserted, oldest first
+    ///
+    /// Every insert of a new key takes the next sequence number, replacing the value of a present
+    /// key keeps its position. The order neither depends on accesses nor on the clock, so the
+    /// cache can be replayed deterministically like a bounded log.
+    pub fn iter_insertion_order(&self) -> SortedIter<'_, K, V, S> {
+        self.iter_sorted(&[], SortOrder::Ascending)
+    }
+}
+
+
+
+impl<K: std::hash::Hash + Eq + Clone, V, S: BuildHasher> IntoIterator for Cache<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    /// Consume the cache, yielding its elements from the least to the most recently used one
+    fn into_iter(self) -> IntoIter<K, V> {
+        let order: Vec<usize> = self.eviction_order().collect();
+        IntoIter {
+            slab: self.slab,
+            order: order.into_iter(),
+        }
+    }
+}
+
+impl<K: std::hash::Hash + Eq + Clone, V, S: BuildHasher> Cache<K, Option<V>, S> {
+    /// Record that `key` does not exist upstream, returning the previous value of the key
+    ///
+    /// The key is stored as `None` and expires according to `negative_ttl`, so repeated lookups
+    /// of a missing key are answered by the cache instead of the backend. Hits on such keys are
+    /// counted as hits and additionally as negative hits in the statistics. Inserting `None` with
+    /// `insert` stores a regular value instead.
+    pub fn insert_missing(&mut self, key: K) -> Option<Option<V>> {
+        let (previous, index) = self.insert_indexed(key, None, self.negative_ttl, None, None);
+        if let Some(index) = index {
+            self.slab[index].metadata.negative = true;
+        }
+        previous
+    }
+}
+
+impl<K: std::hash::Hash + Eq + Clone, V, S: BuildHasher> Extend<(K, V)> for Cache<K, V, S> {
+    /// Insert every element of `iter` in a single batch, see `warm`
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        self.warm(iter);
+    }
+}
+
+impl<K: std::hash::Hash + Eq + Clone + fmt::Debug, V: fmt::Debug, S: BuildHasher> Cache<K, V, S> {
+    /// Write a human readable listing of the cache to `out`, e.g. a `String` or a `fmt::Formatter`
+    ///
+    /// A header with the size, weight and policy is followed by a line per element in eviction
+    /// order, the next victim first, showing its frequency, hits, priority, the segment it is in,
+    /// whether it is pinned and the time left until it expires.
+    pub fn dump<W: fmt::Write>(&self, out: &mut W) -> fmt::Result {
+        writeln!(
+            out,
+            "Cache: {}/{} elements, weight {}, policy {:?}",
+            self.len(),
+            self.capacity,
+            self.total_weight,
+            self.policy
+        )?;
+        let now = self.now();
+        for (position, index) in self.eviction_order().enumerate() {
+            let node = &self.slab[index];
+            let metadata = &node.metadata;
+            write!(
+                out,
+                "{position:>4}: {:?} => {:?} frequency={} hits={} priority={:?}",
+                metadata.user_key,
+                node.value,
+                self.frequency_at(index),
+                metadata.hits,
+                metadata.priority
+            )?;
+            if metadata.protected {
+                write!(out, " protected")?;
+            }
+            if metadata.pinned {
+                write!(out, " pinned")?;
+            }
+            match metadata.expires_at {
+                Some(expires_at) if expires_at <= now => write!(out, " expired")?,
+                Some(expires_at) => {
+                    write!(out, " expires_in={:?}", Duration::from_micros((expires_at - now) as u64))?
+                }
+                None => {}
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+impl<K: std::hash::Hash + Eq + Clone + fmt::Debug, V: fmt::Debug, S: BuildHasher> fmt::Debug for Cache<K, V, S> {
+    /// The capacity, the policy, the elements in eviction order and the statistics
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("capacity", &self.capacity)
+            .field("policy", &self.policy)
+            .field("elements", &DebugElements(self))
+            .field("statistics", &self.statistics)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K: Clone, V: Clone, S: Clone> Clone for Cache<K, V, S> {
+    /// Deep-copy the elements, their order, the indices and the statistics
+    ///
+    /// The clone shares the clock and the weigher but none of the observers of the original: the
+    /// eviction listener, the stats sink, the event subscription, the metrics name and the trace
+    /// recorder are left unset, so a fork for a what-if computation does not report into them.
+    fn clone(&self) -> Self {
+        Cache {
+            slab: self.slab.clone(),
+            key_map: self.key_map.clone(),
+            usage: self.usage,
+            protected: self.protected,
+            policy: self.policy,
+            random_state: self.random_state,
+            ghosts: self.ghosts.clone(),
+            lirs: self.lirs.clone(),
+            history: self.history.clone(),
+            sketch: self.sketch.clone(),
+            admission: self.admission,
+            estimated_frequencies: self.estimated_frequencies,
+            expiry: self.expiry.clone(),
+            generations: self.generations.clone(),
+            namespaces: self.namespaces.clone(),
+            tags: self.tags.clone(),
+            statistics: self.statistics.clone(),
+            capacity: self.capacity,
+            low_watermark: self.low_watermark,
+            expire_after_write: self.expire_after_write,
+            expire_after_access: self.expire_after_access,
+            negative_ttl: self.negative_ttl,
+            refresh_ahead: self.refresh_ahead,
+            ttl_jitter: self.ttl_jitter,
+            refresh_candidates: self.refresh_candidates.clone(),
+            listener: None,
+            spill: self.spill.clone(),
+            spill_expired: self.spill_expired,
+            clock: self.clock.clone(),
+            weigher: self.weigher.clone(),
+            max_weight: self.max_weight,
+            total_weight: self.total_weight,
+            next_sequence: self.next_sequence,
+            #[cfg(feature = "events")]
+            events: None,
+            #[cfg(feature = "metrics")]
+            metrics_name: None,
+            sink: None,
+            trace: None,
+        }
+    }
+}
+
+impl<K: std::hash::Hash + Eq + Clone, V: PartialEq, S: BuildHasher> PartialEq for Cache<K, V, S> {
+    /// Compare the contents of the caches, see `content_eq`
+    fn eq(&self, other: &Self) -> bool {
+        self.content_eq(other)
+    }
+}
+
+/// Formats the elements of a cache as a map in eviction order
+struct DebugElements<'a, K, V, S>(&'a Cache<K, V, S>);
+
+impl<K, V, S> fmt::Debug for DebugElements<'_, K, V, S>
+where
+    K: std::hash::Hash + Eq + Clone + fmt::Debug,
+    V: fmt::Debug,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.0.iter().map(|(key, value, _)| (key, value))).finish()
+    }
+}
+
+impl<'a, K: std::hash::Hash + Eq + Clone, V, S: BuildHasher> IntoIterator for &'a Cache<K, V, S> {
+    type Item = (&'a K, &'a V, &'a Metadata<K>);
+    type IntoIter = CacheIter<'a, K, V, S>;
+
+    fn into_iter(self) -> CacheIter<'a, K, V, S> {
+        self.iter()
+    }
+}
+
+impl<'a, K: std::hash::Hash + Eq + Clone, V, S: BuildHasher> IntoIterator for &'a mut Cache<K, V, S> {
+    type Item = (&'a K, &'a mut V, &'a Metadata<K>);
+    type IntoIter = CacheIterMut<'a, K, V>;
+
+    fn into_iter(self) -> CacheIterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+/// Serializes the capacity, the names of the namespaces and every element with its metadata in
+/// eviction order
+///
+/// The configuration, e.g. the policy, the listener and the weigher, and the statistics are not
+/// part of the serialized form.
+#[cfg(feature = "serde")]
+impl<K, V, S> serde::Serialize for Cache<K, V, S>
+where
+    K: std::hash::Hash + Eq + Clone + serde::Serialize,
+    V: serde::Serialize,
+    S: BuildHasher,
+{
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        use serde::ser::SerializeStruct;
+
+        /// The elements in eviction order
+        struct Elements<'a, K, V, S>(&'a Cache<K, V, S>);
+
+        impl<K, V, S> serde::Serialize for Elements<'_, K, V, S>
+        where
+            K: std::hash::Hash + Eq + Clone + serde::Serialize,
+            V: serde::Serialize,
+            S: BuildHasher,
+        {
+            fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+                serializer.collect_seq(self.0.iter().map(|(_, value, metadata)| (metadata, value)))
+            }
+        }
+
+        let namespaces: Vec<&str> = self.namespaces.iter().map(|(name, _)| &**name).collect();
+        let mut state = serializer.serialize_struct("Cache", 3)?;
+        state.serialize_field("capacity", &self.capacity)?;
+        state.serialize_field("namespaces", &namespaces)?;
+        state.serialize_field("elements", &Elements(self))?;
+        state.end()
+    }
+}
+
+/// Restores a cache with the default configuration from its serialized form, keeping the
+/// eviction order and the metadata of every element
+#[cfg(feature = "serde")]
+impl<'de, K, V, S> serde::Deserialize<'de> for Cache<K, V, S>
+where
+    K: std::hash::Hash + Eq + Clone + serde::Deserialize<'de>,
+    V: serde::Deserialize<'de>,
+    S: BuildHasher + Default,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        #[serde(rename = "Cache")]
+        struct Contents<K, V> {
+            capacity: usize,
+            #[serde(default)]
+            namespaces: Vec<String>,
+            elements: Vec<(Metadata<K>, V)>,
+        }
+
+        let contents = Contents::deserialize(deserializer)?;
+        if contents.capacity == 0 {
+            return Err(serde::de::Error::custom(BuildError::ZeroCapacity));
+        }
+        let mut cache = Cache::with_hasher(contents.capacity, S::default());
+        for name in contents.namespaces {
+            cache.namespace(&name);
+        }
+        for (metadata, value) in contents.elements {
+            cache.restore(metadata, value);
+        }
+        cache.make_room(0, 0, None);
+        Ok(cache)
+    }
+}
+
+#[cfg(test)]
+fn list_keys<K: std::hash::Hash + Eq + Clone, V, S: BuildHasher>(cache: &Cache<K, V, S>, list: &List) -> Vec<K> {
+    list.iter(&cache.slab).map(|index| cache.key_at(index).clone()).collect()
+}
+
+#[test]
+fn test_cache_basic() {
+    let mut cache = Cache::new(10);
+    let key = "hello";
+    cache.insert(key, "world");
+    assert_eq!(cache.get(&key), Some(&"world"));
+}
+
+#[test]
+fn test_insert_does_not_clone_key() {
+    #[derive(Debug, PartialEq, Eq, Hash)]
+    struct Key(String);
+
+    impl Clone for Key {
+        fn clone(&self) -> Self {
+            panic!("key cloned");
+        }
+    }
+
+    impl Borrow<str> for Key {
+        fn borrow(&self) -> &str {
+            &self.0
+        }
+    }
+
+    let mut cache = Cache::new(1);
+    cache.insert(Key("key1".to_string()), 1);
+    cache.insert(Key("key2".to_string()), 2);
+    assert_eq!(cache.get("key2"), Some(&2));
+    assert_eq!(cache.remove("key2"), Some(2));
+}
+
+#[test]
+fn test_lru_eviction() {
+    let mut cache = Cache::new(2);
+
+    let (key1, key2, key3) = ("key1", "key2", "key3");
+
+    cache.insert(key1, "value1");
+    cache.insert(key2, "value2");
+
+    let _value = cache.get(&key1);
+    let _value = cache.get(&key2);
+    let _value  = cache.get(&key2);
+
+    // At this point, the cache is full. The next insert should evict the least recently used item (key1).
+    cache.insert(key3, "value3");
+
+    // Check that the value associated with key1 has been evicted.
+    assert_eq!(cache.get(&key1), None);
+
+    // Check that the values associated with key2 and key3 are still in the cache.
+    assert_eq!(cache.get(&key2), Some(&"value2"));
+    assert_eq!(cache.get(&key3), Some(&"value3"));
+}
+
+#[test]
+fn test_get_lru_element() {
+    let mut cache = Cache::new(2);
+    let key1 = "key1";
+    cache.insert(key1, "value1");
+    cache.insert("key2", "value2");
+
+    let _value = cache.get(&key1);
+
+    // Accessing "key1" promotes it, leaving "key2" as the least recently used element
+    assert_eq!(cache.get_lru(), Some(&"value2"));
+}
+
+#[test]
+fn test_frequency_iter() {
+    let mut cache = Cache::new(3);
+
+    let (key1, key2, key3) = ("key1", "key2", "key3");
+
+    cache.insert(key1, "value1");
+    cache.insert(key2, "value2");
+    cache.insert(key3, "value3");
+
+    let _ = cache.get(&key1);
+    let _ = cache.get(&key1);
+    let _ = cache.get(&key1);
+    let _ = cache.get(&key2);
+    let _ = cache.get(&key2);
+    let _ = cache.get(&key3);
+
+    let ascending_keys: Vec<&str> = cache.iter_frequency(SortOrder::Ascending).map(|(k, _, _)| k).cloned().collect();
+    let descending_keys: Vec<&str> = cache.iter_frequency(SortOrder::Descending).map(|(k, _, _)| k).cloned().collect();
+    assert_eq!(ascending_keys, vec!["key3", "key2", "key1"]);
+    assert_eq!(descending_keys, vec!["key1", "key2", "key3"]);
+}
+
+#[test]
+fn test_iter_by_last_accessed() {
+    let clock = crate::clock::MockClock::new();
+    let mut cache = Cache::new(3).clock(clock.clone());
+    cache.insert_with_priority("key1", 1, Priority::High);
+    clock.advance(Duration::from_secs(1));
+    cache.insert("key2", 2);
+    clock.advance(Duration::from_secs(1));
+    cache.insert("key3", 3);
+    clock.advance(Duration::from_secs(1));
+    cache.get(&"key2");
+
+    let ascending: Vec<&str> = cache.iter_by_last_accessed(SortOrder::Ascending).map(|(k, _, _)| *k).collect();
+    let descending: Vec<&str> = cache.iter_by_last_accessed(SortOrder::Descending).map(|(k, _, _)| *k).collect();
+    assert_eq!(ascending, vec!["key1", "key3", "key2"]);
+    assert_eq!(descending, vec!["key2", "key3", "key1"]);
+}
+
+#[test]
+fn test_iter_insertion_order() {
+    let mut cache = Cache::new(3);
+    cache.insert("key1", 1);
+    cache.insert("key2", 2);
+    cache.insert("key3", 3);
+    cache.get(&"key1");
+    cache.insert("key2", 20);
+    cache.insert("key4", 4);
+
+    let keys: Vec<&str> = cache.iter_insertion_order().map(|(k, _, _)| *k).collect();
+    assert_eq!(keys, vec!["key1", "key2", "key4"]);
+    let sequences: Vec<u64> = cache.iter_insertion_order().map(|(_, _, m)| m.sequence()).collect();
+    assert_eq!(sequences, vec![0, 1, 3]);
+}
+
+#[test]
+fn test_iter_sorted() {
+    let clock = crate::clock::MockClock::new();
+    let mut cache = Cache::new(4).clock(clock.clone());
+    for key in ["key1", "key2", "key3", "key4"] {
+        cache.insert(key, ());
+        clock.advance(Duration::from_secs(1));
+    }
+    for key in ["key3", "key1", "key4", "key4"] {
+        cache.get(&key);
+        clock.advance(Duration::from_secs(1));
+    }
+
+    // Equal frequencies are ordered by recency instead of the layout of the slab
+    let keys = |iter: SortedIter<'_, &'static str, ()>| iter.map(|(k, _, _)| *k).collect::<Vec<_>>();
+    assert_eq!(keys(cache.iter_frequency(SortOrder::Ascending)), ["key2", "key3", "key1", "key4"]);
+    assert_eq!(keys(cache.iter_frequency(SortOrder::Descending)), ["key4", "key1", "key3", "key2"]);
+    assert_eq!(keys(cache.iter_sorted(&[SortKey::Hits], SortOrder::Ascending)), ["key2", "key1", "key3", "key4"]);
+    assert_eq!(keys(cache.iter_sorted(&[SortKey::Age], SortOrder::Descending)), ["key1", "key2", "key3", "key4"]);
+    assert_eq!(keys(cache.iter_sorted(&[], SortOrder::Ascending)), ["key1", "key2", "key3", "key4"]);
+
+    // Taking the first elements only sorts as many as needed
+    let mut top = cache.iter_frequency(SortOrder::Descending);
+    assert_eq!(top.len(), 4);
+    assert_eq!(top.next().map(|(k, _, _)| *k), Some("key4"));
+    assert_eq!(top.len(), 3);
+}
+
+#[test]
+fn test_snapshot() {
+    let mut cache = Cache::new(4);
+    cache.insert_with_priority("key1", 1, Priority::High);
+    cache.insert("key2", 2);
+    cache.insert_with_ttl("key3", 3, Duration::ZERO);
+    cache.insert_with_ttl("key4", 4, Duration::from_secs(60));
+    cache.get(&"key2");
+
+    let snapshot = cache.snapshot();
+    cache.flush();
+    let keys: Vec<_> = snapshot.iter().map(|(key, value, entry)| (*key, *value, entry.position())).collect();
+    assert_eq!(keys, [("key4", 4, 0), ("key2", 2, 1), ("key1", 1, 2)]);
+    let (_, _, entry) = snapshot[1];
+    assert_eq!((entry.hits(), entry.priority(), entry.expires_at()), (1, Priority::Normal, None));
+    assert!(snapshot[0].2.expires_at().is_some());
+}
+
+#[test]
+fn test_estimated_frequencies() {
+    let mut cache = Cache::new(2).estimated_frequencies();
+    cache.insert("key1", "value1");
+    cache.insert("key2", "value2");
+    for _ in 0..3 {
+        cache.get(&"key1");
+    }
+    cache.get(&"key3");
+
+    // Every lookup counts, including the one made by the insert
+    assert_eq!(cache.frequency(&"key1"), Some(4));
+    assert_eq!(cache.entry_stats(&"key2").unwrap().frequency(), 1);
+    assert_eq!(cache.frequency(&"key3"), None);
+    assert_eq!(cache.slab[cache.find(&"key1").unwrap()].metadata.frequency(), 4);
+    let keys: Vec<_> = cache.iter_frequency(SortOrder::Descending).map(|(k, _, _)| *k).collect();
+    assert_eq!(keys, vec!["key1", "key2"]);
+
+    // The sketch alone does not filter admissions
+    cache.insert("key4", "value4");
+    assert_eq!(cache.peek(&"key4"), Some(&"value4"));
+}
+
+#[test]
+fn test_estimated_memory() {
+    let mut cache: Cache<String, Vec<u8>> = Cache::new(16);
+    let empty = cache.estimated_memory();
+    assert!(empty >= 16 * std::mem::size_of::<Node<String, Vec<u8>>>());
+
+    // The slab and the key index are allocated up front, the expiration index is not
+    cache.insert("key1".to_string(), vec![0; 100]);
+    assert_eq!(cache.estimated_memory(), empty);
+    cache.insert_with_ttl("key2".to_string(), Vec::new(), Duration::from_secs(60));
+    assert!(cache.estimated_memory() > empty);
+    let owned = cache.estimated_memory_with(|key, value| key.capacity() + value.capacity());
+    assert_eq!(owned, cache.estimated_memory() + 108);
+}
+
+#[test]
+fn test_evict() {
+    let evicted = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = evicted.clone();
+    let mut cache = Cache::new(8).eviction_listener(move |key, _, cause| sink.lock().unwrap().push((key, cause)));
+    cache.extend((0..6).map(|i| (i, i)));
+    cache.get(&0);
+    cache.pin(&1);
+
+    assert_eq!(cache.evict(2), 2);
+    assert_eq!(cache.evict_to(2), 2);
+    assert_eq!(cache.evict_to(2), 0);
+    assert_eq!(cache.keys().copied().collect::<Vec<_>>(), vec![1, 0]);
+    assert_eq!(cache.evict(10), 1);
+    assert_eq!(cache.keys().copied().collect::<Vec<_>>(), vec![1]);
+
+    let keys: Vec<_> = evicted.lock().unwrap().iter().map(|&(key, _)| key).collect();
+    assert_eq!(keys, vec![2, 3, 4, 5, 0]);
+    assert!(evicted.lock().unwrap().iter().all(|&(_, cause)| cause == RemovalCause::Capacity));
+    assert_eq!(cache.statistics().get_evictions(), 5);
+}
+
+#[test]
+fn test_low_watermark() {
+    let mut cache = Cache::new(4).low_watermark(2);
+    cache.extend((0..4).map(|i| (i, i)));
+    cache.insert_with_ttl(4, 4, Duration::ZERO);
+    assert_eq!(cache.len(), 4);
+
+    // The expired element is purged first, then the LRU elements down to the low watermark
+    assert_eq!(cache.maintain(), 2);
+    assert_eq!(cache.keys().copied().collect::<Vec<_>>(), vec![2, 3]);
+    assert_eq!(cache.maintain(), 0);
+    assert_eq!(Cache::<u32, u32>::new(1).maintain(), 0);
+}
+
+#[test]
+fn test_shrink_to_fit_and_reserve() {
+    let mut cache = Cache::new(1024);
+    cache.shrink_to_fit();
+    let shrunk = cache.estimated_memory();
+    assert!(shrunk < Cache::<u32, u32>::new(1024).estimated_memory());
+
+    cache.reserve(100_000);
+    assert!((1024..100_000).contains(&cache.slab.capacity()));
+    cache.extend((0..1024).map(|i| (i, i)));
+    cache.flush();
+    cache.shrink_to_fit();
+    assert_eq!(cache.slab.capacity(), 0);
+    cache.insert(1, 1);
+    assert_eq!(cache.peek(&1), Some(&1));
+}
+
+#[test]
+fn test_decay_frequencies() {
+    let mut cache = Cache::new(2);
+    cache.insert("key1", "value1");
+    cache.insert("key2", "value2");
+    for _ in 0..5 {
+        cache.get(&"key1");
+    }
+    cache.decay_frequencies();
+    cache.decay_frequencies();
+    for _ in 0..2 {
+        cache.get(&"key2");
+    }
+
+    let frequencies: Vec<_> = cache.iter_frequency(SortOrder::Descending).map(|(k, _, m)| (*k, m.frequency())).collect();
+    assert_eq!(frequencies, vec![("key2", 2), ("key1", 1)]);
+    assert_eq!(cache.entry_stats(&"key1").unwrap().hits(), 5);
+
+    // The estimates of the sketch decay as well
+    let mut cache = Cache::new(2).estimated_frequencies();
+    cache.insert("key1", "value1");
+    for _ in 0..7 {
+        cache.get(&"key1");
+    }
+    assert_eq!(cache.frequency(&"key1"), Some(8));
+    cache.decay_frequencies();
+    assert_eq!(cache.frequency(&"key1"), Some(4));
+    assert_eq!(cache.iter().next().unwrap().2.frequency(), 4);
+}
+
+#[test]
+fn test_statistics() {
+    let mut cache = Cache::new(3);
+
+    cache.insert("key1", "value1");
+    cache.insert("key2", "value2");
+    cache.insert("key3", "value3");
+
+    cache.get(&"key1");
+    cache.get(&"key2");
+    cache.get(&"key4"); // Miss
+
+    assert_eq!(cache.statistics.get_hits(), 2);
+    assert_eq!(cache.statistics.get_misses(), 1);
+    assert_eq!(cache.statistics.get_current_size(), 3);
+
+    assert_eq!(cache.statistics.requests(), 3);
+    assert!((cache.statistics.miss_ratio() - 1.0 / 3.0).abs() < f64::EPSILON);
+
+    let snapshot = cache.statistics();
+    assert_eq!(snapshot.get_hits(), 2);
+    assert_eq!(snapshot.requests(), 3);
+    assert!((snapshot.hit_ratio() - 2.0 / 3.0).abs() < f64::EPSILON);
+
+    cache.reset_statistics();
+    assert_eq!(cache.statistics().get_hits(), 0);
+    assert_eq!(cache.statistics().get_misses(), 0);
+    assert_eq!(cache.statistics().get_current_size(), 3);
+    assert_eq!(cache.statistics().hit_ratio(), 0.0);
+    assert_eq!(cache.statistics().miss_ratio(), 0.0);
+    // The snapshot taken before the reset is unaffected
+    assert_eq!(snapshot.get_misses(), 1);
+}
+#[test]
+fn test_metadata_fields() {
+    let mut cache = Cache::new(3);
+
+    let (key1, key2) = ("key1", "key2");
+
+    cache.insert(key1, "value1");
+    cache.insert(key2, "value2");
+
+    cache.get(&key1);
+    cache.get(&key1);
+    cache.get(&key2);
+    // Force a miss
+    cache.get(&"key4");
+
+    let meta1 = &cache.slab[cache.find(&key1).unwrap()].metadata;
+    let meta2 = &cache.slab[cache.find(&key2).unwrap()].metadata;
+
+    assert!(meta1.last_accessed >= meta1.created_at);
+    assert_eq!(meta1.frequency, 2);
+    assert_eq!(meta1.hits, 2);
+
+    assert!(meta2.last_accessed >= meta2.created_at);
+    assert_eq!(meta2.frequency, 1);
+    assert_eq!(meta2.hits, 1);
+
+    assert_eq!(meta1.user_key(), &key1);
+    assert_eq!(meta1.frequency(), 2);
+    assert_eq!(meta1.hits(), 2);
+    assert!(meta1.created_at() <= meta1.last_accessed());
+    assert!(meta1.created_at() > SystemTime::UNIX_EPOCH);
+    #[cfg(feature = "chrono")]
+    assert_eq!(SystemTime::from(meta1.created_at_utc()), meta1.created_at());
+}
+
+
+#[test]
+fn test_remove() {
+    let mut cache = Cache::new(3);
+
+    cache.insert("key1", "value1");
+    cache.insert("key2", "value2");
+    cache.insert("key3", "value3");
+
+    assert_eq!(cache.remove(&"key2"), Some("value2"));
+    assert_eq!(cache.remove(&"key2"), None);
+    assert_eq!(cache.get(&"key2"), None);
+    assert_eq!(cache.statistics.get_current_size(), 2);
+
+    // The removed element must be gone from every internal structure
+    assert_eq!(list_keys(&cache, &cache.usage[Priority::Normal as usize]), vec!["key1", "key3"]);
+    assert_eq!(cache.slab.len(), 2);
+
+    cache.insert("key4", "value4");
+    cache.insert("key5", "value5");
+    assert_eq!(cache.get(&"key1"), None);
+    assert_eq!(cache.get(&"key3"), Some(&"value3"));
+    assert_eq!(cache.get(&"key4"), Some(&"value4"));
+    assert_eq!(cache.get(&"key5"), Some(&"value5"));
+}
+
+#[test]
+fn test_peek() {
+    let mut cache = Cache::new(2);
+
+    cache.insert("key1", "value1");
+    cache.insert("key2", "value2");
+
+    assert_eq!(cache.peek(&"key1"), Some(&"value1"));
+    assert_eq!(cache.peek(&"key3"), None);
+    if let Some(value) = cache.peek_mut(&"key2") {
+        *value = "updated";
+    }
+    assert_eq!(cache.peek(&"key2"), Some(&"updated"));
+
+    // Peeking must not touch the statistics or the metadata
+    assert_eq!(cache.statistics.get_hits(), 0);
+    assert_eq!(cache.statistics.get_misses(), 0);
+    let meta1 = &cache.slab[cache.find(&"key1").unwrap()].metadata;
+    assert_eq!(meta1.frequency, 0);
+    assert_eq!(meta1.last_accessed, meta1.created_at);
+
+    // Peeking must not promote the element, so "key1" is still evicted first
+    cache.insert("key3", "value3");
+    assert_eq!(cache.peek(&"key1"), None);
+    assert_eq!(cache.peek(&"key3"), Some(&"value3"));
+}
+
+#[test]
+fn test_insert_existing_key() {
+    let mut cache = Cache::new(2);
+
+    assert_eq!(cache.insert("key1", "value1"), None);
+    assert_eq!(cache.insert("key2", "value2"), None);
+    let _ = cache.get(&"key1");
+
+    assert_eq!(cache.insert("key1", "updated"), Some("value1"));
+    assert_eq!(cache.slab.len(), 2);
+    assert_eq!(cache.usage[Priority::Normal as usize].len(), 2);
+    assert_eq!(cache.statistics.get_current_size(), 2);
+
+    // The replaced element keeps its history and becomes the most recently used one
+    let meta1 = &cache.slab[cache.find(&"key1").unwrap()].metadata;
+    assert_eq!(meta1.frequency, 1);
+    assert_eq!(list_keys(&cache, &cache.usage[Priority::Normal as usize]), vec!["key2", "key1"]);
+
+    cache.insert("key3", "value3");
+    assert_eq!(cache.peek(&"key1"), Some(&"updated"));
+    assert_eq!(cache.peek(&"key2"), None);
+}
+
+#[test]
+fn test_entry() {
+    let mut cache = Cache::new(2);
+
+    *cache.entry("key1").or_insert(1) += 10;
+    assert_eq!(cache.peek(&"key1"), Some(&11));
+
+    cache.entry("key1").and_modify(|v| *v += 1).or_insert(0);
+    cache.entry("key2").and_modify(|v| *v += 1).or_insert(5);
+    assert_eq!(cache.peek(&"key1"), Some(&12));
+    assert_eq!(cache.peek(&"key2"), Some(&5));
+
+    let mut called = false;
+    cache.entry("key2").or_insert_with(|| {
+        called = true;
+        0
+    });
+    assert!(!called);
+
+    // Occupied lookups count as hits, vacant ones as misses
+    assert_eq!(cache.statistics.get_hits(), 2);
+    assert_eq!(cache.statistics.get_misses(), 2);
+
+    match cache.entry("key1") {
+        Entry::Occupied(entry) => {
+            assert_eq!(entry.key(), &"key1");
+            assert_eq!(entry.remove(), 12);
+        }
+        Entry::Vacant(_) => unreachable!(),
+    }
+    assert_eq!(cache.peek(&"key1"), None);
+    assert_eq!(cache.statistics.get_current_size(), 1);
+}
+
+#[test]
+fn test_try_insert() {
+    let mut cache = Cache::new(2);
+    assert_eq!(cache.try_insert("key1", 1).ok(), Some(&1));
+
+    let error = cache.try_insert("key1", 10).unwrap_err();
+    assert_eq!(error.entry.get(), &1);
+    assert_eq!(error.value, 10);
+    assert_eq!(error.to_string(), "failed to insert 10, key \"key1\" already exists with value 1");
+    assert_eq!(cache.peek(&"key1"), Some(&1));
+}
+
+#[test]
+fn test_compute() {
+    let mut cache = Cache::new(2);
+    assert_eq!(cache.compute("key1", |value| value.map_or(Op::Replace(1), |_| Op::Remove)), Some(&1));
+    assert_eq!(cache.compute("key2", |_| Op::Keep), None);
+    assert_eq!(cache.peek(&"key2"), None);
+
+    let doubled = cache.compute("key1", |value| {
+        *value.unwrap() *= 2;
+        Op::Keep
+    });
+    assert_eq!(doubled, Some(&2));
+    assert_eq!(cache.compute("key1", |value| Op::Replace(*value.unwrap() + 1)), Some(&3));
+    assert_eq!(cache.compute("key1", |_| Op::Remove), None);
+    assert!(cache.is_empty());
+    assert_eq!(cache.statistics().get_hits(), 3);
+    assert_eq!(cache.statistics().get_misses(), 2);
+}
+
+#[test]
+fn test_get_or_insert_with() {
+    let mut cache = Cache::new(2);
+    let mut loads = 0;
+
+    assert_eq!(cache.get_or_insert_with("key1", || { loads += 1; "value1" }), &"value1");
+    assert_eq!(cache.get_or_insert_with("key1", || { loads += 1; "other" }), &"value1");
+    assert_eq!(loads, 1);
+    assert_eq!(cache.statistics.get_hits(), 1);
+    assert_eq!(cache.statistics.get_misses(), 1);
+    assert_eq!(cache.statistics.get_current_size(), 1);
+}
+
+#[test]
+fn test_try_get_or_insert_with() {
+    let mut cache = Cache::new(2);
+
+    assert_eq!(cache.try_get_or_insert_with("key1", || Err("unavailable")), Err("unavailable"));
+    assert_eq!(cache.peek(&"key1"), None);
+    assert_eq!(cache.try_get_or_insert_with("key1", || Ok::<_, &str>("value1")), Ok(&"value1"));
+    assert_eq!(cache.try_get_or_insert_with("key1", || Err("unavailable")), Ok(&"value1"));
+    assert_eq!(cache.statistics.get_hits(), 1);
+    assert_eq!(cache.statistics.get_misses(), 2);
+    assert_eq!(cache.len(), 1);
+}
+
+#[test]
+fn test_negative_caching() {
+    let mut cache = Cache::new(3).negative_ttl(Duration::ZERO);
+    cache.insert("key1", Some("value1"));
+    cache.insert_missing("key2");
+    assert_eq!(cache.get(&"key2"), None);
+
+    let mut cache = Cache::new(3).negative_ttl(Duration::from_secs(60));
+    cache.insert("key1", Some("value1"));
+    cache.insert_missing("key2");
+    assert_eq!(cache.get(&"key1"), Some(&Some("value1")));
+    assert_eq!(cache.get(&"key2"), Some(&None));
+    assert_eq!(cache.expiry.len(), 1);
+    let statistics = cache.statistics();
+    assert_eq!((statistics.get_hits(), statistics.get_negative_hits()), (2, 1));
+
+    // A value loaded later replaces the negative entry
+    cache.insert("key2", Some("value2"));
+    cache.get(&"key2");
+    assert_eq!(cache.statistics().get_negative_hits(), 1);
+}
+
+#[test]
+fn test_refresh_ahead() {
+    let mut cache = Cache::new(3).refresh_ahead(1.0);
+    cache.insert_with_ttl("key1", "value1", Duration::from_secs(60));
+    cache.insert_with_ttl("key2", "value2", Duration::from_secs(60));
+    cache.insert("key3", "value3");
+
+    // Hits return the current value and flag each key only once
+    assert_eq!(cache.get(&"key1"), Some(&"value1"));
+    cache.get(&"key1");
+    cache.get(&"key2");
+    cache.get(&"key3");
+    cache.remove(&"key2");
+    assert_eq!(cache.take_refresh_candidates(), vec!["key1"]);
+    assert!(cache.take_refresh_candidates().is_empty());
+
+    // Reloading the value arms the check again
+    cache.insert_with_ttl("key1", "reloaded", Duration::from_secs(60));
+    cache.get(&"key1");
+    assert_eq!(cache.take_refresh_candidates(), vec!["key1"]);
+
+    let mut cache = Cache::new(1).refresh_ahead(0.5);
+    cache.insert_with_ttl("key1", "value1", Duration::from_secs(60));
+    cache.get(&"key1");
+    assert!(cache.take_refresh_candidates().is_empty());
+}
+
+#[test]
+fn test_ttl() {
+    let mut cache = Cache::new(3);
+
+    cache.insert_with_ttl("key1", "value1", Duration::ZERO);
+    cache.insert_with_ttl("key2", "value2", Duration::from_secs(60));
+    cache.insert("key3", "value3");
+
+    // Expired elements are misses and are removed lazily
+    assert_eq!(cache.peek(&"key1"), None);
+    assert_eq!(cache.get(&"key1"), None);
+    assert_eq!(cache.statistics.get_misses(), 1);
+    assert_eq!(cache.statistics.get_current_size(), 2);
+    assert_eq!(cache.expiry.len(), 1);
+    assert_eq!(cache.get(&"key2"), Some(&"value2"));
+
+    // Replacing a value through insert clears its TTL
+    cache.insert("key2", "updated");
+    assert!(cache.expiry.is_empty());
+}
+
+#[test]
+fn test_ttl_eviction_prefers_expired() {
+    let mut cache = Cache::new(2);
+
+    cache.insert("key1", "value1");
+    cache.insert_with_ttl("key2", "value2", Duration::ZERO);
+    cache.insert("key3", "value3");
+
+    // "key1" is the LRU element, but the expired "key2" is evicted instead
+    assert_eq!(cache.peek(&"key1"), Some(&"value1"));
+    assert_eq!(cache.find(&"key2"), None);
+    assert_eq!(cache.peek(&"key3"), Some(&"value3"));
+    assert!(cache.expiry.is_empty());
+}
+
+#[test]
+fn test_expire_after_write() {
+    let mut cache = Cache::new(2).expire_after_write(Duration::ZERO);
+
+    cache.insert("key1", "value1");
+    assert_eq!(cache.get(&"key1"), None);
+    assert_eq!(cache.statistics.get_misses(), 1);
+    assert_eq!(cache.statistics.get_current_size(), 0);
+
+    // A TTL passed explicitly takes precedence over the policy
+    cache.insert_with_ttl("key2", "value2", Duration::from_secs(60));
+    assert_eq!(cache.get(&"key2"), Some(&"value2"));
+}
+
+#[test]
+fn test_expire_after_access() {
+    let clock = crate::clock::MockClock::new();
+    let mut cache = Cache::new(2).expire_after_access(Duration::from_secs(60)).clock(clock.clone());
+
+    cache.insert("key1", "value1");
+    let inserted = cache.slab[cache.find(&"key1").unwrap()].metadata.expires_at.unwrap();
+    clock.advance(Duration::from_secs(59));
+    assert_eq!(cache.get(&"key1"), Some(&"value1"));
+    let accessed = cache.slab[cache.find(&"key1").unwrap()].metadata.expires_at.unwrap();
+    assert_eq!(accessed - inserted, 59_000_000);
+    clock.advance(Duration::from_secs(60));
+    assert_eq!(cache.get(&"key1"), None);
+
+    // Accesses never extend an element beyond its write deadline
+    let mut cache = Cache::new(2)
+        .expire_after_write(Duration::from_secs(1))
+        .expire_after_access(Duration::from_secs(60));
+    cache.insert("key1", "value1");
+    cache.get(&"key1");
+    let meta = &cache.slab[cache.find(&"key1").unwrap()].metadata;
+    assert_eq!(meta.expires_at, meta.write_deadline);
+    assert_eq!(cache.expiry.len(), 1);
+}
+
+#[test]
+fn test_purge_expired() {
+    let mut cache = Cache::new(4);
+
+    cache.insert_with_ttl("key1", "value1", Duration::ZERO);
+    cache.insert_with_ttl("key2", "value2", Duration::ZERO);
+    cache.insert_with_ttl("key3", "value3", Duration::from_secs(60));
+    cache.insert("key4", "value4");
+
+    assert_eq!(cache.purge_expired(), 2);
+    assert_eq!(cache.purge_expired(), 0);
+    assert_eq!(cache.statistics.get_current_size(), 2);
+    assert_eq!(cache.usage[Priority::Normal as usize].len(), 2);
+    assert_eq!(cache.expiry.len(), 1);
+    assert_eq!(cache.peek(&"key3"), Some(&"value3"));
+    assert_eq!(cache.peek(&"key4"), Some(&"value4"));
+}
+
+#[test]
+fn test_eviction_listener() {
+    use std::sync::{Arc, Mutex};
+
+    let removed = Arc::new(Mutex::new(Vec::new()));
+    let sink = removed.clone();
+    let mut cache = Cache::new(2).eviction_listener(move |key, value, cause| {
+        sink.lock().unwrap().push((key, value, cause));
+    });
+
+    cache.insert("key1", "value1");
+    cache.insert("key2", "value2");
+    cache.insert("key3", "value3");
+    cache.insert_with_ttl("key4", "value4", Duration::ZERO);
+    assert_eq!(cache.get(&"key4"), None);
+
+    // Values returned to the caller are not reported
+    assert_eq!(cache.remove(&"key3"), Some("value3"));
+    assert_eq!(cache.insert("key5", "value5"), None);
+    cache.flush();
+
+    assert_eq!(
+        *removed.lock().unwrap(),
+        vec![
+            ("key1", "value1", RemovalCause::Capacity),
+            ("key2", "value2", RemovalCause::Capacity),
+            ("key4", "value4", RemovalCause::Expired),
+            ("key5", "value5", RemovalCause::Explicit),
+        ]
+    );
+}
+
+#[test]
+fn test_weigher() {
+    let mut cache = Cache::new(10)
+        .weigher(|_: &&str, value: &Vec<u8>| value.len() as u64)
+        .max_weight(10);
+
+    cache.insert("key1", vec![0; 4]);
+    cache.insert("key2", vec![0; 4]);
+    assert_eq!(cache.weight(), 8);
+
+    // Inserting 6 more bytes has to evict the LRU element
+    cache.insert("key3", vec![0; 6]);
+    assert_eq!(cache.weight(), 10);
+    assert_eq!(cache.peek(&"key1"), None);
+    assert_eq!(cache.peek(&"key2").map(Vec::len), Some(4));
+
+    cache.insert("key4", vec![0; 2]);
+    assert_eq!(cache.weight(), 8);
+    assert_eq!(cache.peek(&"key2"), None);
+
+    // Replacements are reweighed as well
+    cache.insert("key4", vec![0; 4]);
+    assert_eq!(cache.weight(), 10);
+    cache.insert("key4", vec![0; 5]);
+    assert_eq!(cache.weight(), 5);
+    assert_eq!(cache.peek(&"key3"), None);
+
+    assert_eq!(cache.remove(&"key4").map(|value| value.len()), Some(5));
+    assert_eq!(cache.weight(), 0);
+}
+
+#[test]
+fn test_slru() {
+    let mut cache = Cache::new(4).policy(Policy::Slru { protected_ratio: 0.5 });
+
+    for key in ["a", "b", "c", "d"] {
+        cache.insert(key, key);
+    }
+    cache.get(&"a");
+    cache.get(&"b");
+
+    // A scan of keys seen only once evicts from the probationary segment only
+    for key in ["e", "f", "g"] {
+        cache.insert(key, key);
+    }
+    assert_eq!(cache.peek(&"a"), Some(&"a"));
+    assert_eq!(cache.peek(&"b"), Some(&"b"));
+    assert_eq!(cache.peek(&"c"), None);
+    assert_eq!(cache.peek(&"d"), None);
+    assert_eq!(cache.peek(&"e"), None);
+    assert_eq!(cache.get_lru(), Some(&"f"));
+
+    // Promoting "f" overflows the protected segment and demotes "a" back to probation
+    cache.get(&"f");
+    assert_eq!(list_keys(&cache, &cache.protected[Priority::Normal as usize]), vec!["b", "f"]);
+    assert_eq!(list_keys(&cache, &cache.usage[Priority::Normal as usize]), vec!["g", "a"]);
+    assert!(!cache.slab[cache.find(&"a").unwrap()].metadata.protected);
+
+    cache.insert("h", "h");
+    assert_eq!(cache.peek(&"g"), None);
+    assert_eq!(cache.peek(&"a"), Some(&"a"));
+}
+
+#[test]
+fn test_tiny_lfu() {
+    use std::sync::{Arc, Mutex};
+
+    let rejected = Arc::new(Mutex::new(Vec::new()));
+    let sink = rejected.clone();
+    let mut cache = Cache::new(2).tiny_lfu().eviction_listener(move |key, _, cause| {
+        sink.lock().unwrap().push((key, cause));
+    });
+
+    cache.insert("key1", "value1");
+    cache.insert("key2", "value2");
+    for _ in 0..3 {
+        cache.get(&"key1");
+        cache.get(&"key2");
+    }
+
+    // A key seen for the first time is less valuable than the LRU victim
+    assert_eq!(cache.insert("key3", "value3"), None);
+    assert_eq!(cache.peek(&"key3"), None);
+    assert_eq!(*rejected.lock().unwrap(), vec![("key3", RemovalCause::Capacity)]);
+
+    // Once it is requested often enough it replaces the victim
+    for _ in 0..5 {
+        cache.get(&"key3");
+    }
+    cache.insert("key3", "value3");
+    assert_eq!(cache.peek(&"key3"), Some(&"value3"));
+    assert_eq!(cache.peek(&"key1"), None);
+    assert_eq!(cache.peek(&"key2"), Some(&"value2"));
+}
+
+#[test]
+fn test_clock() {
+    let mut cache = Cache::new(3).policy(Policy::Clock);
+
+    for key in ["a", "b", "c"] {
+        cache.insert(key, key);
+    }
+    cache.get(&"a");
+    // Hits only mark the element
+    assert_eq!(list_keys(&cache, &cache.usage[Priority::Normal as usize]), vec!["a", "b", "c"]);
+    assert!(cache.slab[cache.find(&"a").unwrap()].metadata.referenced);
+
+    // The hand gives "a" a second chance and evicts "b"
+    cache.insert("d", "d");
+    assert_eq!(cache.peek(&"b"), None);
+    assert!(!cache.slab[cache.find(&"a").unwrap()].metadata.referenced);
+    assert_eq!(list_keys(&cache, &cache.usage[Priority::Normal as usize]), vec!["c", "a", "d"]);
+
+    cache.insert("e", "e");
+    assert_eq!(cache.peek(&"c"), None);
+    cache.insert("f", "f");
+    assert_eq!(cache.peek(&"a"), None);
+}
+
+#[test]
+fn test_lru_promotion() {
+    let mut cache = Cache::new(3);
+
+    for key in ["a", "b", "c"] {
+        cache.insert(key, key);
+    }
+    cache.get(&"a");
+    cache.get(&"b");
+    assert_eq!(list_keys(&cache, &cache.usage[Priority::Normal as usize]), vec!["c", "a", "b"]);
+
+    cache.insert("d", "d");
+    assert_eq!(cache.peek(&"c"), None);
+    cache.get(&"a");
+    assert_eq!(list_keys(&cache, &cache.usage[Priority::Normal as usize]), vec!["b", "d", "a"]);
+    assert_eq!(cache.usage[Priority::Normal as usize].iter(&cache.slab).rev().count(), 3);
+}
+
+#[test]
+fn test_borrowed_lookup() {
+    let mut cache: Cache<String, usize> = Cache::new(2);
+    cache.insert("key1".to_string(), 1);
+    cache.insert("key2".to_string(), 2);
+
+    assert_eq!(cache.get("key1"), Some(&1));
+    assert_eq!(cache.peek("key2"), Some(&2));
+    if let Some(value) = cache.peek_mut("key2") {
+        *value += 1;
+    }
+    assert_eq!(cache.remove("key2"), Some(3));
+    assert_eq!(cache.get("key2"), None);
+}
+
+#[test]
+fn test_custom_hasher() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::BuildHasherDefault;
+
+    let mut cache = Cache::with_hasher(2, BuildHasherDefault::<DefaultHasher>::default()).tiny_lfu();
+    cache.insert(1, "value1");
+    cache.insert(2, "value2");
+
+    assert_eq!(cache.get(&1), Some(&"value1"));
+    // The sketch hashes keys with the custom hasher as well
+    cache.insert(3, "value3");
+    assert_eq!(cache.peek(&2), Some(&"value2"));
+    assert_eq!(cache.peek(&3), None);
+}
+
+#[test]
+fn test_len_and_capacity() {
+    let mut cache = Cache::new(2);
+    assert!(cache.is_empty());
+    assert_eq!(cache.capacity(), 2);
+    assert_eq!(cache.remaining_capacity(), 2);
+
+    cache.insert("key1", "value1");
+    assert_eq!(cache.len(), 1);
+    assert!(!cache.is_empty());
+    assert_eq!(cache.remaining_capacity(), 1);
+
+    cache.insert("key2", "value2");
+    cache.insert("key3", "value3");
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.remaining_capacity(), 0);
+}
+
+#[test]
+fn test_set_capacity() {
+    use std::sync::{Arc, Mutex};
+
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    let sink = evicted.clone();
+    let mut cache = Cache::new(4).eviction_listener(move |key, _, cause| {
+        sink.lock().unwrap().push((key, cause));
+    });
+    for key in ["key1", "key2", "key3", "key4"] {
+        cache.insert(key, key);
+    }
+    cache.get(&"key1");
+
+    cache.set_capacity(2);
+    assert_eq!(cache.capacity(), 2);
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.statistics().get_current_size(), 2);
+    assert_eq!(
+        *evicted.lock().unwrap(),
+        vec![("key2", RemovalCause::Capacity), ("key3", RemovalCause::Capacity)]
+    );
+
+    cache.set_capacity(3);
+    cache.insert("key5", "key5");
+    assert_eq!(cache.len(), 3);
+    assert_eq!(evicted.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn test_pop_lru() {
+    let mut cache = Cache::new(3);
+    cache.insert("key1", "value1");
+    cache.insert("key2", "value2");
+    cache.insert_with_ttl("key3", "value3", Duration::ZERO);
+    cache.get(&"key1");
+
+    let (key, value, metadata) = cache.pop_lru().unwrap();
+    assert_eq!((key, value), ("key2", "value2"));
+    assert_eq!(metadata.user_key, "key2");
+
+    // The expired element is skipped
+    let (key, value, metadata) = cache.pop_lru().unwrap();
+    assert_eq!((key, value), ("key1", "value1"));
+    assert_eq!(metadata.hits, 1);
+
+    assert!(cache.pop_lru().is_none());
+    assert!(cache.is_empty());
+    assert_eq!(cache.statistics().get_current_size(), 0);
+}
+
+#[test]
+fn test_mru() {
+    let mut cache = Cache::new(3);
+    cache.insert("key1", "value1");
+    cache.insert("key2", "value2");
+    cache.insert("key3", "value3");
+    cache.get(&"key1");
+    assert_eq!(cache.get_mru(), Some(&"value1"));
+
+    let (key, value, _) = cache.pop_mru().unwrap();
+    assert_eq!((key, value), ("key1", "value1"));
+    assert_eq!(cache.get_mru(), Some(&"value3"));
+    assert_eq!(cache.get_lru(), Some(&"value2"));
+
+    cache.pop_mru();
+    cache.pop_mru();
+    assert_eq!(cache.get_mru(), None);
+    assert!(cache.pop_mru().is_none());
+}
+
+#[test]
+fn test_get_mut() {
+    let mut cache = Cache::new(2);
+    cache.insert("key1", 1);
+    cache.insert("key2", 2);
+
+    if let Some(value) = cache.get_mut(&"key1") {
+        *value += 10;
+    }
+    assert_eq!(cache.get_mut(&"key3"), None);
+    assert_eq!(cache.peek(&"key1"), Some(&11));
+    assert_eq!(cache.slab[cache.find(&"key1").unwrap()].metadata.hits, 1);
+    assert_eq!(cache.statistics().get_misses(), 1);
+
+    // The mutated element became the most recently used one
+    cache.insert("key3", 3);
+    assert_eq!(cache.peek(&"key2"), None);
+}
+
+#[test]
+fn test_iter() {
+    let mut cache = Cache::new(3);
+    cache.insert("key1", "value1");
+    cache.insert("key2", "value2");
+    cache.insert("key3", "value3");
+    cache.get(&"key1");
+
+    let keys: Vec<_> = cache.iter().map(|(key, _, _)| *key).collect();
+    assert_eq!(keys, vec!["key2", "key3", "key1"]);
+    let values: Vec<_> = cache.iter().rev().map(|(_, value, _)| *value).collect();
+    assert_eq!(values, vec!["value1", "value3", "value2"]);
+    assert_eq!(cache.iter().size_hint(), (3, Some(3)));
+
+    // Protected elements come after the probationary ones under SLRU
+    let mut cache = Cache::new(3).policy(Policy::Slru { protected_ratio: 0.5 });
+    cache.insert("key1", "value1");
+    cache.insert("key2", "value2");
+    cache.get(&"key1");
+    let keys: Vec<_> = cache.iter().map(|(key, _, _)| *key).collect();
+    assert_eq!(keys, vec!["key2", "key1"]);
+}
+
+#[test]
+fn test_iter_mut() {
+    let mut cache = Cache::new(3);
+    cache.insert("key1", 1);
+    cache.insert("key2", 2);
+    cache.get(&"key1");
+
+    for (key, value, metadata) in cache.iter_mut() {
+        assert_eq!(metadata.user_key, *key);
+        *value *= 10;
+    }
+    assert_eq!(cache.peek(&"key1"), Some(&10));
+    assert_eq!(cache.peek(&"key2"), Some(&20));
+
+    // Iterating does not touch the usage order
+    let keys: Vec<_> = cache.iter().map(|(key, _, _)| *key).collect();
+    assert_eq!(keys, vec!["key2", "key1"]);
+}
+
+#[test]
+fn test_into_iter() {
+    let mut cache = Cache::new(3);
+    cache.insert("key1", "value1");
+    cache.insert("key2", "value2");
+    cache.insert("key3", "value3");
+    cache.get(&"key1");
+
+    assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&"key2", &"key3", &"key1"]);
+    assert_eq!(cache.values().rev().collect::<Vec<_>>(), vec![&"value1", &"value3", &"value2"]);
+    assert_eq!((&cache).into_iter().count(), 3);
+    for (_, value, _) in &mut cache {
+        *value = "updated";
+    }
+
+    let drained: std::collections::HashMap<_, _> = cache.into_iter().collect();
+    assert_eq!(drained.len(), 3);
+    assert_eq!(drained["key1"], "updated");
+}
+
+#[test]
+fn test_retain() {
+    let mut cache = Cache::new(4);
+    for (key, tenant) in [("key1", 1), ("key2", 2), ("key3", 1), ("key4", 2)] {
+        cache.insert(key, tenant);
+    }
+    cache.get(&"key2");
+
+    cache.retain(|_, tenant, _| *tenant != 1);
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.statistics().get_current_size(), 2);
+    assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&"key4", &"key2"]);
+
+    // The freed slots are reused without evicting the survivors
+    cache.insert("key5", 3);
+    cache.insert("key6", 3);
+    assert_eq!(cache.len(), 4);
+    assert_eq!(cache.peek(&"key2"), Some(&2));
+}
+
+#[test]
+fn test_drain() {
+    let mut cache = Cache::new(3);
+    cache.insert("key1", "value1");
+    cache.insert("key2", "value2");
+    cache.insert_with_ttl("key3", "value3", Duration::ZERO);
+    cache.get(&"key1");
+
+    assert_eq!(cache.drain().collect::<Vec<_>>(), vec![("key2", "value2"), ("key1", "value1")]);
+    assert!(cache.is_empty());
+    assert_eq!(cache.weight(), 0);
+    assert!(cache.expiry.is_empty());
+
+    // Dropping the iterator early still empties the cache
+    cache.insert("key4", "value4");
+    cache.insert("key5", "value5");
+    assert_eq!(cache.drain().next(), Some(("key4", "value4")));
+    assert!(cache.is_empty());
+    assert_eq!(cache.statistics().get_current_size(), 0);
+}
+
+#[test]
+fn test_removal_statistics() {
+    let mut cache = Cache::new(2);
+    cache.insert("key1", "value1");
+    cache.insert("key2", "value2");
+    cache.insert("key1", "updated");
+    cache.insert("key3", "value3");
+    cache.insert_with_ttl("key4", "value4", Duration::ZERO);
+    assert_eq!(cache.get(&"key4"), None);
+    cache.remove(&"key3");
+    cache.insert("key5", "value5");
+    cache.insert("key6", "value6");
+    cache.flush();
+
+    let statistics = cache.statistics();
+    assert_eq!(statistics.get_inserts(), 6);
+    assert_eq!(statistics.get_replacements(), 1);
+    assert_eq!(statistics.get_evictions(), 2);
+    assert_eq!(statistics.get_expirations(), 1);
+    assert_eq!(statistics.get_removals(), 3);
+    assert_eq!(statistics.get_current_size(), 0);
+
+    cache.reset_statistics();
+    assert_eq!(cache.statistics().get_inserts(), 0);
+    assert_eq!(cache.statistics().get_evictions(), 0);
+}
+
+#[test]
+fn test_windowed_statistics() {
+    let mut cache = Cache::new(2);
+    cache.insert("key1", "value1");
+    cache.get(&"key1");
+    assert_eq!(cache.hit_ratio_last(Duration::from_secs(60)), None);
+
+    let mut cache = Cache::new(2).windowed_statistics(Duration::from_secs(60), 5);
+    assert_eq!(cache.hit_ratio_last(Duration::from_secs(60)), Some(0.0));
+    cache.insert("key1", "value1");
+    cache.get(&"key1");
+    cache.get(&"key1");
+    cache.get(&"key1");
+    cache.get(&"key2");
+    assert_eq!(cache.hit_ratio_last(Duration::from_secs(300)), Some(0.75));
+
+    cache.reset_statistics();
+    assert_eq!(cache.hit_ratio_last(Duration::from_secs(300)), Some(0.0));
+
+    // Lookups older than the window no longer count
+    let clock = crate::clock::MockClock::new();
+    let mut cache = Cache::new(2).windowed_statistics(Duration::from_secs(60), 5).clock(clock.clone());
+    cache.get(&"key1");
+    clock.advance(Duration::from_secs(60));
+    cache.insert("key1", "value1");
+    cache.get(&"key1");
+    assert_eq!(cache.hit_ratio_last(Duration::ZERO), Some(1.0));
+    assert_eq!(cache.hit_ratio_last(Duration::from_secs(300)), Some(0.5));
+    clock.advance(Duration::from_secs(300));
+    assert_eq!(cache.hit_ratio_last(Duration::from_secs(300)), Some(0.0));
+}
+
+#[test]
+fn test_entry_stats() {
+    let mut cache = Cache::new(3);
+    cache.insert("key1", "value1");
+    cache.insert("key2", "value2");
+    cache.insert("key3", "value3");
+    cache.get(&"key1");
+    cache.get(&"key1");
+
+    let stats = cache.entry_stats(&"key1").unwrap();
+    assert_eq!(stats.frequency(), 2);
+    assert_eq!(stats.hits(), 2);
+    assert_eq!(stats.position(), 2);
+    assert!(stats.last_accessed() > SystemTime::UNIX_EPOCH);
+    assert_eq!(cache.entry_stats(&"key2").unwrap().position(), 0);
+    assert_eq!(cache.entry_stats(&"key4"), None);
+
+    // Querying does not promote the element
+    cache.entry_stats(&"key2");
+    cache.insert("key4", "value4");
+    assert_eq!(cache.entry_stats(&"key2"), None);
+    assert_eq!(cache.statistics().get_hits(), 2);
+}
+
+#[test]
+fn test_eviction_age_percentile() {
+    let mut cache = Cache::new(1);
+    assert_eq!(cache.eviction_age_percentile(50.0), None);
+
+    cache.insert("key1", "value1");
+    cache.insert("key2", "value2");
+    cache.remove(&"key2");
+    let median = cache.eviction_age_percentile(50.0).unwrap();
+    assert!(median < Duration::from_secs(1));
+    assert_eq!(cache.eviction_age_percentile(100.0), Some(median));
+
+    cache.reset_statistics();
+    assert_eq!(cache.eviction_age_percentile(50.0), None);
+}
+
+#[test]
+fn test_hottest_and_coldest() {
+    let mut cache = Cache::new(4);
+    for (key, hits) in [("key1", 3), ("key2", 0), ("key3", 5), ("key4", 1)] {
+        cache.insert(key, hits);
+        for _ in 0..hits {
+            cache.get(&key);
         }
     }
+
+    let hottest: Vec<_> = cache.hottest(2).into_iter().map(|(key, _, _)| *key).collect();
+    assert_eq!(hottest, vec!["key3", "key1"]);
+    let coldest: Vec<_> = cache
+        .coldest(3)
+        .into_iter()
+        .map(|(key, _, metadata)| (*key, metadata.hits()))
+        .collect();
+    assert_eq!(coldest, vec![("key2", 0), ("key4", 1), ("key1", 3)]);
+    assert_eq!(cache.hottest(10).len(), 4);
+    assert!(cache.coldest(0).is_empty());
 }
 
+#[test]
+fn test_pin() {
+    let mut cache = Cache::new(2);
+    cache.insert("key1", "value1");
+    cache.insert("key2", "value2");
+    assert!(cache.pin(&"key1"));
+    assert!(!cache.pin(&"key3"));
+
+    cache.insert("key3", "value3");
+    assert_eq!(cache.peek(&"key1"), Some(&"value1"));
+    assert_eq!(cache.peek(&"key2"), None);
 
+    // With every element pinned the cache grows instead of evicting
+    cache.pin(&"key3");
+    cache.insert("key4", "value4");
+    cache.pin(&"key4");
+    assert_eq!(cache.len(), 3);
+
+    assert!(cache.unpin(&"key1"));
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.peek(&"key1"), None);
+
+    let mut cache = Cache::new(2).policy(Policy::Clock);
+    cache.insert("key1", "value1");
+    cache.insert("key2", "value2");
+    cache.pin(&"key1");
+    cache.insert("key3", "value3");
+    assert_eq!(cache.peek(&"key1"), Some(&"value1"));
+    assert_eq!(cache.peek(&"key2"), None);
+}
 
-#[cfg(test)]
 #[test]
-fn test_cache_basic() {
-    let mut cache = Cache::new(10);
-    let key = cache.insert("hello", "world");
-    assert_eq!(cache.get(key), Some(&"world"));
+fn test_priority() {
+    let mut cache = Cache::new(3);
+    cache.insert_with_priority("key1", "value1", Priority::High);
+    cache.insert_with_priority("key2", "value2", Priority::Low);
+    cache.insert("key3", "value3");
+    cache.get(&"key2");
+    assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&"key2", &"key3", &"key1"]);
+
+    // The low priority element is evicted although it was used most recently
+    cache.insert("key4", "value4");
+    assert_eq!(cache.peek(&"key2"), None);
+    cache.insert("key5", "value5");
+    assert_eq!(cache.peek(&"key3"), None);
+    assert_eq!(cache.peek(&"key1"), Some(&"value1"));
+
+    // Replacing a value through insert_with_priority changes the priority
+    cache.insert_with_priority("key1", "updated", Priority::Low);
+    cache.insert("key6", "value6");
+    assert_eq!(cache.peek(&"key1"), None);
+    assert_eq!(cache.get_mru(), Some(&"value6"));
+
+    let mut cache = Cache::new(2).policy(Policy::Slru { protected_ratio: 0.5 });
+    cache.insert_with_priority("key1", "value1", Priority::Low);
+    cache.insert("key2", "value2");
+    cache.get(&"key1");
+    cache.insert("key3", "value3");
+    assert_eq!(cache.peek(&"key1"), None);
 }
 
 #[test]
-fn test_lru_eviction() {
+fn test_warm() {
+    use std::sync::{Arc, Mutex};
+
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    let sink = evicted.clone();
+    let mut cache = Cache::new(3).eviction_listener(move |key, _, cause| {
+        sink.lock().unwrap().push((key, cause));
+    });
+    cache.insert(0, 0);
+    cache.warm((1..=4).map(|i| (i, i * 10)));
+
+    assert_eq!(cache.len(), 3);
+    assert_eq!(cache.keys().copied().collect::<Vec<_>>(), vec![2, 3, 4]);
+    assert_eq!(*evicted.lock().unwrap(), vec![(0, RemovalCause::Capacity), (1, RemovalCause::Capacity)]);
+    assert_eq!(cache.statistics().get_current_size(), 3);
+    assert_eq!(cache.statistics().get_inserts(), 5);
+
+    // Present keys are replaced and become the most recently used elements
+    cache.extend([(2, 200), (5, 50)]);
+    assert_eq!(cache.keys().copied().collect::<Vec<_>>(), vec![4, 2, 5]);
+    assert_eq!(cache.peek(&2), Some(&200));
+
+    // Elements evicted by the batch itself are never stored, so they do not grow the slab
     let mut cache = Cache::new(2);
+    cache.warm((0..10_000).map(|i| (i, i)).chain([(5, 50)]));
+    assert_eq!(cache.keys().copied().collect::<Vec<_>>(), vec![9999, 5]);
+    assert_eq!(cache.peek(&5), Some(&50));
+    assert!(cache.slab.capacity() < 16);
+    let statistics = cache.statistics();
+    assert_eq!((statistics.get_inserts(), statistics.get_evictions()), (10_000, 9_998));
+}
+
+#[test]
+fn test_insert_many() {
+    let evicted = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = evicted.clone();
+    let mut cache = Cache::new(3).eviction_listener(move |key, _, _| sink.lock().unwrap().push(key));
+    cache.insert("key1", 1);
+    cache.insert("key2", 2);
 
-    let key1 = cache.insert("key1", "value1");
-    let key2 = cache.insert("key2", "value2");
+    let returned = cache.insert_many([("key3", 3), ("key1", 10), ("key4", 4), ("key5", 5)]);
+    assert_eq!(returned, vec![("key2", 2), ("key3", 3)]);
+    assert!(evicted.lock().unwrap().is_empty());
+    assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&"key1", &"key4", &"key5"]);
+    assert_eq!(cache.peek(&"key1"), Some(&10));
+    assert!(cache.insert_many([("key1", 1)]).is_empty());
 
-    let _value = cache.get(key1);
-    let _value = cache.get(key2);
-    let _value  = cache.get(key2);
+    // Elements the batch itself evicts come last and are never stored
+    let returned = cache.insert_many((0..1000).map(|i| ("batch", i)).chain([("key6", 6), ("key7", 7)]));
+    assert_eq!(returned, vec![("key4", 4), ("key5", 5), ("key1", 1)]);
+    let returned = cache.insert_many([("key8", 8), ("key9", 9), ("key10", 10), ("key11", 11)]);
+    assert_eq!(returned, vec![("batch", 999), ("key6", 6), ("key7", 7), ("key8", 8)]);
+    assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&"key9", &"key10", &"key11"]);
+    assert!(cache.slab.capacity() < 16);
+}
 
-    // At this point, the cache is full. The next insert should evict the least recently used item (key1).
-    let key3 = cache.insert("key3", "value3");
+#[test]
+fn test_insert_returning() {
+    let evicted = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = evicted.clone();
+    let mut cache = Cache::new(2).eviction_listener(move |key, _, _| sink.lock().unwrap().push(key));
+    assert_eq!(cache.insert_returning("key1", 1), None);
+    assert_eq!(cache.insert_returning("key2", 2), None);
+    assert_eq!(cache.insert_returning("key1", 10), None);
 
-    // Check that the value associated with key1 has been evicted.
-    assert_eq!(cache.get(key1), None);
+    assert_eq!(cache.insert_returning("key3", 3), Some(("key2", 2)));
+    assert!(evicted.lock().unwrap().is_empty());
+    assert_eq!(cache.statistics().get_evictions(), 1);
+    assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&"key1", &"key3"]);
 
-    // Check that the values associated with key2 and key3 are still in the cache.
-    assert_eq!(cache.get(key2), Some(&"value2"));
-    assert_eq!(cache.get(key3), Some(&"value3"));
+    // A key rejected by the admission filter is handed back like an evicted element
+    let mut cache = Cache::new(1).tiny_lfu();
+    assert_eq!(cache.insert_returning("key1", 1), None);
+    cache.get(&"key1");
+    assert_eq!(cache.insert_returning("key2", 2), Some(("key2", 2)));
+    assert_eq!(cache.statistics().get_evictions(), 1);
+    assert_eq!(cache.peek(&"key1"), Some(&1));
 }
 
 #[test]
-fn test_get_lru_element() {
-    let mut cache = Cache::new(2);
-    let key1 = cache.insert("key1", "value1");
-    let _key2 = cache.insert("key2", "value2");
+fn test_touch() {
+    let clock = crate::clock::MockClock::new();
+    let mut cache = Cache::new(2).expire_after_access(Duration::from_secs(60)).clock(clock.clone());
+    cache.insert("key1", "value1");
+    cache.insert("key2", "value2");
 
-    let _value = cache.get(key1);
+    clock.advance(Duration::from_secs(30));
+    assert!(cache.touch(&"key1"));
+    assert!(!cache.touch(&"key3"));
+    let meta = &cache.slab[cache.find(&"key1").unwrap()].metadata;
+    assert_eq!((meta.frequency(), meta.hits()), (1, 0));
+    assert_eq!(cache.statistics().get_hits(), 0);
 
-    assert_eq!(cache.get_lru(), Some(&"value1"));
+    // The touched element became the most recently used one and its idle time restarted
+    clock.advance(Duration::from_secs(45));
+    assert_eq!(cache.peek(&"key2"), None);
+    assert_eq!(cache.peek(&"key1"), Some(&"value1"));
+    cache.insert("key3", "value3");
+    assert_eq!(cache.len(), 2);
 }
 
 #[test]
-fn test_frequency_iter() {
-    let mut cache = Cache::new(3);
+fn test_get_quiet() {
+    let mut cache = Cache::new(2);
+    cache.insert("key1", "value1");
+    cache.insert("key2", "value2");
 
-    let key1 = cache.insert("key1", "value1");
-    let key2 = cache.insert("key2", "value2");
-    let key3 = cache.insert("key3", "value3");
+    assert_eq!(cache.get_quiet(&"key1"), Some(&"value1"));
+    assert_eq!(cache.get_quiet(&"key3"), None);
+    assert_eq!(cache.statistics().requests(), 0);
 
-    let _ = cache.get(key1);
-    let _ = cache.get(key1);
-    let _ = cache.get(key1);
-    let _ = cache.get(key2);
-    let _ = cache.get(key2);
-    let _ = cache.get(key3);
+    cache.insert("key3", "value3");
+    assert_eq!(cache.peek(&"key2"), None);
+    assert_eq!(cache.peek(&"key1"), Some(&"value1"));
+}
 
-    let ascending_keys: Vec<&str> = cache.iter_frequency(SortOrder::Ascending).map(|(k, _, _)| k).cloned().collect();
-    let descending_keys: Vec<&str> = cache.iter_frequency(SortOrder::Descending).map(|(k, _, _)| k).cloned().collect();
-    assert_eq!(ascending_keys, vec!["key3", "key2", "key1"]);
-    assert_eq!(descending_keys, vec!["key1", "key2", "key3"]);
+#[test]
+fn test_contains_key() {
+    let mut cache = Cache::new(2);
+    cache.insert("key1", "value1");
+    cache.insert("key2", "value2");
+    cache.insert_with_ttl("key3", "value3", Duration::ZERO);
+
+    assert!(cache.contains_key(&"key2"));
+    assert!(!cache.contains_key(&"key3"));
+    assert!(!cache.contains_key(&"key4"));
+    assert_eq!(cache.statistics().requests(), 0);
+    assert_eq!(cache.slab[cache.find(&"key2").unwrap()].metadata.frequency(), 0);
+
+    // Checking the key did not promote it
+    cache.insert("key4", "value4");
+    cache.insert("key5", "value5");
+    assert!(!cache.contains_key(&"key2"));
 }
+
 #[test]
-fn test_statistics() {
+fn test_get_many() {
     let mut cache = Cache::new(3);
-
     cache.insert("key1", "value1");
     cache.insert("key2", "value2");
     cache.insert("key3", "value3");
 
-    cache.get("key1");
+    let values = cache.get_many([&"key1", &"key4", &"key1", &"key2"]);
+    assert_eq!(values, vec![Some(&"value1"), None, Some(&"value1"), Some(&"value2")]);
+    assert_eq!(cache.statistics().get_hits(), 3);
+    assert_eq!(cache.statistics().get_misses(), 1);
+    assert_eq!(cache.slab[cache.find(&"key1").unwrap()].metadata.hits(), 2);
+
+    cache.insert("key4", "value4");
+    assert_eq!(cache.peek(&"key3"), None);
+}
+
+#[test]
+fn test_token() {
+    let mut cache = Cache::new(2);
+    let token1 = cache.insert_with_token("key1", "value1").unwrap();
+    let token2 = cache.insert_with_token("key2", "value2").unwrap();
+    assert_eq!(cache.token(&"key1"), Some(token1));
+
+    assert_eq!(cache.get_by_token(&token1), Some(&"value1"));
+    assert_eq!(cache.statistics().get_hits(), 1);
+    cache.insert("key1", "updated");
+    assert_eq!(cache.peek_by_token(&token1), Some(&"updated"));
+
+    // The slot of an evicted element is reused, but its token no longer resolves
+    cache.insert("key3", "value3");
+    assert_eq!(cache.peek(&"key2"), None);
+    assert_eq!(cache.token(&"key3").unwrap().index, token2.index);
+    assert_eq!(cache.get_by_token(&token2), None);
+    assert_eq!(cache.peek_by_token(&token2), None);
+    assert_eq!(cache.statistics().get_misses(), 1);
+
+    let token = cache.insert_with_token("key4", "value4").unwrap();
+    cache.insert_with_ttl("key4", "value4", Duration::ZERO);
+    assert_eq!(cache.get_by_token(&token), None);
+    assert_eq!(cache.peek(&"key4"), None);
+
+    // Flushing bumps the generation of every slot
+    let token = cache.token(&"key3").unwrap();
+    cache.flush();
+    cache.insert("key5", "value5");
+    cache.insert("key6", "value6");
+    assert_eq!(cache.generations[token.index], token.generation + 1);
+    assert_eq!(cache.peek_by_token(&token), None);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() {
+    let mut cache = Cache::new(3).policy(Policy::Slru { protected_ratio: 0.5 });
+    cache.insert_with_priority("key1".to_string(), 1, Priority::High);
+    cache.insert("key2".to_string(), 2);
+    cache.insert_with_ttl("key3".to_string(), 3, Duration::from_secs(60));
+    cache.get("key2");
     cache.get("key2");
-    cache.get("key4"); // Miss
 
-    assert_eq!(cache.statistics.get_hits(), 2);
-    assert_eq!(cache.statistics.get_misses(), 1);
-    assert_eq!(cache.statistics.get_current_size(), 3);
+    let json = serde_json::to_string(&cache).unwrap();
+    let mut restored: Cache<String, i32> = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(restored.capacity(), 3);
+    assert_eq!(restored.keys().collect::<Vec<_>>(), cache.keys().collect::<Vec<_>>());
+    let (stats, original) = (restored.entry_stats("key2").unwrap(), cache.entry_stats("key2").unwrap());
+    assert_eq!(stats.hits(), original.hits());
+    assert_eq!(stats.position(), original.position());
+    assert_eq!(stats.last_accessed(), original.last_accessed());
+    assert_eq!(restored.expiry.len(), 1);
+    assert_eq!(restored.weight(), 3);
+
+    // The restored order decides what is evicted next
+    restored.insert("key4".to_string(), 4);
+    assert_eq!(restored.peek("key3"), None);
+    assert_eq!(restored.peek("key1"), Some(&1));
+
+    // Elements stay in their namespace
+    let mut cache = Cache::new(2);
+    cache.namespace("a").insert("key".to_string(), 1);
+    let json = serde_json::to_string(&cache).unwrap();
+    let mut restored: Cache<String, i32> = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored.peek("key"), None);
+    assert_eq!(restored.namespace("a").get("key"), Some(&1));
+    assert_eq!(restored.namespace("a").len(), 1);
+
+    // A cache without capacity is rejected
+    let json = r#"{"capacity":0,"elements":[]}"#;
+    assert!(serde_json::from_str::<Cache<String, i32>>(json).is_err());
 }
+
 #[test]
-fn test_metadata_fields() {
-    let mut cache = Cache::new(3);
+fn test_insert_tagged() {
+    let evicted = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = evicted.clone();
+    let mut cache = Cache::new(4).eviction_listener(move |key, _, cause| sink.lock().unwrap().push((key, cause)));
+    cache.insert_tagged("page1", 1, ["user:1", "user:2"]);
+    cache.insert_tagged("page2", 2, ["user:2"]);
+    cache.insert_tagged("page3", 3, ["user:3", "user:3"]);
+    cache.insert("page4", 4);
+    assert_eq!(cache.iter().find(|(key, _, _)| **key == "page3").unwrap().2.tags().collect::<Vec<_>>(), ["user:3"]);
 
-    let key1 = cache.insert("key1", "value1");
-    let key2 = cache.insert("key2", "value2");
+    assert_eq!(cache.invalidate_tag("user:2"), 2);
+    assert_eq!(cache.invalidate_tag("user:2"), 0);
+    assert_eq!(cache.keys().copied().collect::<Vec<_>>(), ["page3", "page4"]);
+    let mut evicted = std::mem::take(&mut *evicted.lock().unwrap());
+    evicted.sort_by_key(|(key, _)| *key);
+    assert_eq!(evicted, [("page1", RemovalCause::Explicit), ("page2", RemovalCause::Explicit)]);
+    assert!(!cache.tags.contains_key("user:1"));
 
-    cache.get(key1);
-    cache.get(key1);
-    cache.get(key2);
-    // Force a miss
-    cache.get("key4");
+    // Retagging drops the old tags, evicted elements leave the index
+    cache.insert_tagged("page3", 30, ["user:4"]);
+    assert_eq!(cache.invalidate_tag("user:3"), 0);
+    cache.insert_tagged("page5", 5, ["user:5"]);
+    cache.insert("page6", 6);
+    cache.insert("page7", 7);
+    cache.insert("page8", 8);
+    assert!(!cache.tags.contains_key("user:4"));
+    assert_eq!(cache.invalidate_tag("user:5"), 1);
+    assert_eq!(cache.len(), 3);
+}
 
-    let meta1 = cache.key_meta.get(&cache.key_map[&key1]).unwrap();
-    let meta2 = cache.key_meta.get(&cache.key_map[&key2]).unwrap();
+#[test]
+fn test_invalidate_entries_if() {
+    let evicted = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = evicted.clone();
+    let mut cache = Cache::new(8).eviction_listener(move |key, _, cause| sink.lock().unwrap().push((key, cause)));
+    for i in 0..6 {
+        cache.insert(i, i * 10);
+    }
+    cache.insert_with_ttl(6, 60, Duration::ZERO);
 
-    assert!(meta1.last_accessed > 0);
-    assert_eq!(meta1.frequency, 2);
-    assert_eq!(meta1.hits, 2);
+    assert_eq!(cache.invalidate_entries_if(|key, _| key % 2 == 0), 3);
+    assert_eq!(cache.len(), 7);
+    assert_eq!(cache.get(&0), None);
+    assert!(!cache.contains_key(&2));
+    assert_eq!(cache.peek(&1), Some(&10));
 
-    assert!(meta2.last_accessed > 0);
-    assert_eq!(meta2.frequency, 1);
-    assert_eq!(meta2.hits, 1);
+    // A new value for an invalidated key is not affected
+    cache.insert(4, 40);
+    assert_eq!(cache.get(&4), Some(&40));
+
+    assert_eq!(cache.maintain(), 2);
+    assert_eq!(cache.len(), 4);
+    assert_eq!(cache.statistics().get_removals(), 3);
+    assert_eq!(cache.statistics().get_expirations(), 1);
+    assert_eq!(
+        *evicted.lock().unwrap(),
+        [
+            (0, RemovalCause::Explicit),
+            (4, RemovalCause::Explicit),
+            (2, RemovalCause::Explicit),
+            (6, RemovalCause::Expired)
+        ]
+    );
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn test_tracing() {
+    use std::sync::Mutex;
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata as TracingMetadata, Subscriber};
+
+    /// Collects the messages of every event and the names of every span
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl Visit for &Recorder {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            if field.name() == "message" {
+                self.0.lock().unwrap().push(format!("{value:?}"));
+            }
+        }
+    }
+
+    impl Subscriber for &'static Recorder {
+        fn enabled(&self, _: &TracingMetadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            self.0.lock().unwrap().push(span.metadata().name().to_string());
+            Id::from_u64(1)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            event.record(&mut &**self);
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    let recorder: &'static Recorder = Box::leak(Box::default());
+    tracing::subscriber::with_default(recorder, || {
+        let mut cache = Cache::new(1);
+        cache.insert("key1", 1);
+        cache.get(&"key1");
+        cache.get(&"key2");
+        cache.insert("key2", 2);
+    });
+    assert_eq!(
+        *recorder.0.lock().unwrap(),
+        [
+            "insert",
+            "cache insert",
+            "get",
+            "cache hit",
+            "get",
+            "cache miss",
+            "insert",
+            "cache eviction",
+            "cache insert"
+        ]
+    );
+}
+
+
+#[test]
+fn test_validate() {
+    let mut cache = Cache::new(8).policy(Policy::Slru { protected_ratio: 0.5 }).weigher(|_, value: &u64| *value);
+    cache.insert("key1", 1);
+    cache.insert_with_ttl("key2", 2, Duration::from_secs(60));
+    cache.insert_with_priority("key3", 3, Priority::High);
+    cache.insert_tagged("key4", 4, ["tag"]);
+    cache.get(&"key1");
+    cache.get(&"key1");
+    cache.insert("key5", 5);
+    cache.namespace("ns").insert("key1", 6);
+    cache.remove(&"key3");
+    assert_eq!(cache.validate(), Ok(()));
+    assert_eq!(Cache::<u32, u32>::new(1).validate(), Ok(()));
+
+    cache.total_weight += 1;
+    assert_eq!(cache.validate(), Err(InvariantViolation::WeightMismatch { recorded: 19, actual: 18 }));
+    cache.total_weight -= 1;
+
+    let index = cache.find_hashed(None, &"key2", cache.hash_in(None, &"key2")).unwrap();
+    cache.expiry.clear();
+    assert_eq!(cache.validate(), Err(InvariantViolation::ExpiryMismatch { index }));
+    cache.set_expiry(index, None);
+    assert_eq!(cache.validate(), Ok(()));
+
+    let hash = cache.hash_at(index);
+    cache.key_map.remove(hash, index);
+    assert_eq!(cache.validate(), Err(InvariantViolation::KeyCountMismatch { keys: 4, elements: 5 }));
+    cache.key_map.insert(hash ^ 1, index);
+    assert_eq!(cache.validate(), Err(InvariantViolation::StaleKey { hash: hash ^ 1, index }));
+    cache.key_map.remove(hash ^ 1, index);
+    cache.key_map.insert(hash, index);
+
+    let priority = Priority::Normal as usize;
+    let front = cache.usage[priority].front().unwrap();
+    let stale = cache.usage[priority];
+    cache.usage[priority].unlink(&mut cache.slab, front);
+    assert_eq!(cache.validate(), Err(InvariantViolation::MisplacedElement { index: front }));
+    cache.usage[Priority::High as usize].push_back(&mut cache.slab, front);
+    assert_eq!(cache.validate(), Err(InvariantViolation::MisplacedElement { index: front }));
+    cache.usage[priority] = stale;
+    assert_eq!(
+        cache.validate(),
+        Err(InvariantViolation::CorruptList { priority: Priority::Normal, protected: false })
+    );
+}
+
+
+#[test]
+fn test_dump() {
+    use crate::clock::MockClock;
+
+    let mut cache = Cache::new(4).clock(MockClock::at(0));
+    cache.insert("key1", 1);
+    cache.insert_with_ttl("key2", 2, Duration::from_secs(5));
+    cache.insert_with_priority("key3", 3, Priority::High);
+    cache.get(&"key1");
+    cache.pin(&"key1");
+
+    let mut dump = String::new();
+    cache.dump(&mut dump).unwrap();
+    let lines: Vec<&str> = dump.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "Cache: 3/4 elements, weight 3, policy Lru",
+            "   0: \"key2\" => 2 frequency=0 hits=0 priority=Normal expires_in=5s",
+            "   1: \"key1\" => 1 frequency=1 hits=1 priority=Normal pinned",
+            "   2: \"key3\" => 3 frequency=0 hits=0 priority=High",
+        ]
+    );
+
+    let debug = format!("{cache:?}");
+    assert!(debug.starts_with("Cache { capacity: 4, policy: Lru, elements: {\"key2\": 2, \"key1\": 1, \"key3\": 3}"));
+    assert!(debug.ends_with(", .. }"));
+    let (_, _, metadata) = cache.iter().next().unwrap();
+    assert!(format!("{metadata:?}").contains("user_key: \"key2\""));
+}
+
+
+#[test]
+fn test_clone() {
+    let evicted = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = evicted.clone();
+    let mut cache = Cache::new(3)
+        .weigher(|_, value: &u64| *value)
+        .eviction_listener(move |key, _, _| sink.lock().unwrap().push(key));
+    cache.insert("key1", 1);
+    cache.insert_with_ttl("key2", 2, Duration::from_secs(60));
+    cache.insert_tagged("key3", 3, ["tag"]);
+    cache.get(&"key1");
+
+    let mut fork = cache.clone();
+    assert_eq!(fork.validate(), Ok(()));
+    assert_eq!(fork.iter().map(|(key, _, _)| *key).collect::<Vec<_>>(), ["key2", "key3", "key1"]);
+    assert_eq!(fork.weight(), 6);
+    assert_eq!(fork.statistics(), cache.statistics());
+    assert_eq!(fork.frequency(&"key1"), Some(1));
+
+    // The fork evolves independently and does not report to the listener of the original
+    fork.insert("key4", 4);
+    assert_eq!(fork.invalidate_tag("tag"), 1);
+    assert_eq!(fork.validate(), Ok(()));
+    assert_eq!(fork.iter().map(|(key, _, _)| *key).collect::<Vec<_>>(), ["key1", "key4"]);
+    assert!(evicted.lock().unwrap().is_empty());
+    assert_eq!(cache.len(), 3);
+    assert_eq!(cache.validate(), Ok(()));
+    cache.insert("key5", 5);
+    assert_eq!(*evicted.lock().unwrap(), ["key2"]);
+}
+
+
+#[test]
+fn test_content_eq() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::BuildHasherDefault;
+
+    let mut left = Cache::new(3);
+    left.insert("key1", 1);
+    left.insert("key2", 2);
+    left.namespace("ns").insert("key1", 10);
+    left.get(&"key1");
+
+    // Other operations in another order with another hasher
+    let mut right = Cache::with_hasher(4, BuildHasherDefault::<DefaultHasher>::default());
+    right.namespace("other").insert("key3", 3);
+    right.namespace("ns").insert("key1", 10);
+    right.insert("key2", 20);
+    right.insert("key1", 1);
+    right.insert("key2", 2);
+    right.namespace("other").remove(&"key3");
+    assert!(left.content_eq(&right));
+    assert!(right.content_eq(&left));
+    assert_eq!(left, left.clone());
+
+    right.insert("key2", 3);
+    assert!(!left.content_eq(&right));
+    right.insert("key2", 2);
+    right.remove(&"key1");
+    right.namespace("ns").insert("key4", 4);
+    assert!(!left.content_eq(&right));
+    assert!(!right.content_eq(&left));
+
+    let mut fork = left.clone();
+    fork.namespace("ns").remove(&"key1");
+    fork.insert("key1", 10);
+    assert_ne!(left, fork);
+}
+
+
+#[test]
+fn test_zero_capacity() {
+    assert!(matches!(Cache::<&str, u32>::try_new(0), Err(BuildError::ZeroCapacity)));
+    assert_eq!(Cache::<&str, u32>::try_new(1).unwrap().capacity(), 1);
+    assert!(std::panic::catch_unwind(|| Cache::<&str, u32>::new(0)).is_err());
+
+    // A capacity can only be changed to zero through the fallible setter, which leaves the cache as is
+    let mut cache = Cache::new(2);
+    cache.insert("key1", 1);
+    assert_eq!(cache.try_set_capacity(0), Err(CacheError::CapacityExhausted));
+    assert_eq!((cache.capacity(), cache.get(&"key1")), (2, Some(&1)));
+    assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| cache.set_capacity(0))).is_err());
 }
 
+
+#[test]
+fn test_try_insert_bounded() {
+    let mut cache = Cache::new(2).weigher(|_, value: &u64| *value).max_weight(10);
+    assert_eq!(cache.try_insert_bounded("key1", 4), Ok(None));
+    assert_eq!(cache.try_insert_bounded("key2", 11), Err(CacheError::TooLarge { weight: 11, max_weight: 10 }));
+    assert_eq!(cache.try_insert_bounded("key1", 5), Ok(Some(4)));
+    cache.pin(&"key1");
+    assert_eq!(cache.try_insert_bounded("key2", 6), Err(CacheError::CapacityExhausted));
+    assert_eq!(cache.try_insert_bounded("key2", 5), Ok(None));
+    assert_eq!(cache.try_insert_bounded("key2", 6), Err(CacheError::CapacityExhausted));
+    cache.pin(&"key2");
+    assert_eq!(cache.try_insert_bounded("key3", 1), Err(CacheError::CapacityExhausted));
+    assert_eq!(cache.try_set_capacity(1), Err(CacheError::CapacityExhausted));
+    assert_eq!(cache.capacity(), 2);
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.weight(), 10);
+
+    cache.unpin(&"key1");
+    assert_eq!(cache.try_insert_bounded("key3", 5), Ok(None));
+    assert_eq!(cache.get(&"key1"), None);
+    assert_eq!(cache.try_set_capacity(1), Ok(()));
+    assert_eq!(cache.keys().collect::<Vec<_>>(), [&"key2"]);
+    assert_eq!(cache.validate(), Ok(()));
+    assert_eq!(CacheError::CapacityExhausted.to_string(), "the cache is full of pinned elements");
+
+    let mut cache = Cache::new(1).tiny_lfu();
+    assert_eq!(cache.try_insert_bounded("key1", 1), Ok(None));
+    cache.get(&"key1");
+    assert_eq!(cache.try_insert_bounded("key2", 2), Err(CacheError::Rejected));
+}
+
+
+#[test]
+fn test_try_reserve() {
+    let mut cache = Cache::new(1);
+    cache.set_capacity(4);
+    assert_eq!(cache.try_reserve(8), Ok(()));
+    assert!(cache.slab.capacity() >= 4);
+    assert_eq!(cache.try_insert_alloc("key1", 1), Ok(None));
+    assert_eq!(cache.try_insert_alloc("key1", 2), Ok(Some(1)));
+
+    // More memory than can ever be allocated is reported instead of aborting
+    cache.set_capacity(usize::MAX);
+    assert_eq!(cache.try_reserve(usize::MAX / 2), Err(CacheError::AllocError));
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache.validate(), Ok(()));
+}
+
+
+#[test]
+fn test_ttl_jitter() {
+    use crate::clock::MockClock;
+
+    let clock = MockClock::at(0);
+    let mut cache = CacheBuilder::new(101)
+        .clock(clock.clone())
+        .expire_after_write(Duration::from_secs(10))
+        .ttl_jitter(Duration::from_secs(4))
+        .build()
+        .unwrap();
+    for key in 0..100 {
+        cache.insert(key, key);
+    }
+    cache.insert_with_ttl(100, 100, Duration::from_secs(2));
+    let deadlines: HashSet<i64> = cache.slab.iter().filter_map(|(_, node)| node.metadata.expires_at).collect();
+    assert!(deadlines.len() > 90);
+    assert!(deadlines.iter().all(|&deadline| deadline <= 10_000_000));
+    assert!(deadlines.iter().filter(|&&deadline| deadline > 2_000_000).all(|&deadline| deadline >= 6_000_000));
+
+    clock.advance(Duration::from_secs(6) - Duration::from_micros(1));
+    cache.purge_expired();
+    assert_eq!(cache.len(), 100);
+    clock.advance(Duration::from_secs(2));
+    let expired = cache.purge_expired();
+    assert!(expired > 20 && expired < 80);
+    clock.advance(Duration::from_secs(2));
+    cache.purge_expired();
+    assert!(cache.is_empty());
+}
+
+
+#[test]
+fn test_get_with_beta() {
+    use crate::clock::MockClock;
+
+    let clock = MockClock::at(0);
+    let mut cache = Cache::new(4).clock(clock.clone()).expire_after_write(Duration::from_secs(1));
+    let mut loads = 0;
+    let mut load = |value| {
+        loads += 1;
+        clock.advance(Duration::from_millis(1));
+        value
+    };
+    assert_eq!(*cache.get_with_beta("key1", 1.0, || load(1)), 1);
+    let metadata = cache.iter().next().unwrap().2;
+    assert_eq!(metadata.load_duration(), Some(Duration::from_millis(1)));
+
+    // Far from the expiration the value is never reloaded
+    for _ in 0..100 {
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(*cache.get_with_beta("key1", 1.0, || load(2)), 1);
+    }
+
+    // Right before the expiration a large beta reloads it
+    clock.advance(Duration::from_millis(900) - Duration::from_micros(1));
+    assert_eq!(*cache.get_with_beta("key1", 1e9, || load(3)), 3);
+    let statistics = cache.statistics();
+    assert_eq!((statistics.get_hits(), statistics.get_misses()), (100, 2));
+
+    // Values without a recorded load duration only expire on time
+    cache.insert("key2", 4);
+    clock.advance(Duration::from_millis(999));
+    assert_eq!(*cache.get_with_beta("key2", 1e9, || load(5)), 4);
+    assert_eq!(loads, 2);
+    assert_eq!(cache.validate(), Ok(()));
+}
+
+
+#[test]
+fn test_load_times() {
+    use crate::clock::MockClock;
+
+    let clock = MockClock::at(0);
+    let mut cache = Cache::new(4).clock(clock.clone());
+    assert_eq!(cache.load_time_percentile(50.0), None);
+    cache.get_or_insert_with("key1", || {
+        clock.advance(Duration::from_micros(100));
+        1
+    });
+    cache.entry("key2").or_insert_with(|| {
+        clock.advance(Duration::from_micros(3000));
+        2
+    });
+    cache.insert("key3", 3);
+    // A hit does not load
+    cache.get_or_insert_with("key1", || unreachable!());
+    // Neither does a failed load
+    assert_eq!(cache.try_get_or_insert_with("key4", || Err("unavailable")), Err("unavailable"));
+    let loaded = cache.try_get_or_insert_with("key4", || {
+        clock.advance(Duration::from_micros(200));
+        Ok::<_, &str>(4)
+    });
+    assert_eq!(loaded, Ok(&4));
+
+    let durations: Vec<_> = cache.iter().map(|(key, _, metadata)| (*key, metadata.load_duration())).collect();
+    assert_eq!(
+        durations,
+        [
+            ("key2", Some(Duration::from_micros(3000))),
+            ("key3", None),
+            ("key1", Some(Duration::from_micros(100))),
+            ("key4", Some(Duration::from_micros(200))),
+        ]
+    );
+    assert_eq!(cache.load_time_percentile(50.0), Some(Duration::from_micros(256)));
+    assert_eq!(cache.load_time_percentile(100.0), Some(Duration::from_micros(4096)));
+}
+
+
+#[test]
+fn test_s3_fifo() {
+    let mut cache = Cache::new(4).policy(Policy::S3Fifo { small_ratio: 0.5 });
+    let queue = |cache: &Cache<&'static str, &str>, main: bool| {
+        let lists = if main { &cache.protected } else { &cache.usage };
+        list_keys(cache, &lists[Priority::Normal as usize])
+    };
+
+    for key in ["a", "b", "c", "d"] {
+        cache.insert(key, key);
+    }
+    cache.get(&"a");
+    cache.get(&"b");
+    // Hits do not reorder the small queue, eviction moves the accessed elements to the main queue
+    assert_eq!(queue(&cache, false), vec!["a", "b", "c", "d"]);
+    cache.insert("e", "e");
+    assert_eq!(cache.peek(&"c"), None);
+    assert_eq!(queue(&cache, false), vec!["d", "e"]);
+    assert_eq!(queue(&cache, true), vec!["a", "b"]);
+
+    // Once the small queue is below its share, the main queue evicts and reinserts accessed elements
+    cache.get(&"a");
+    cache.get(&"d");
+    cache.insert("f", "f");
+    assert_eq!(cache.peek(&"b"), None);
+    assert_eq!(queue(&cache, false), vec!["e", "f"]);
+    assert_eq!(queue(&cache, true), vec!["d", "a"]);
+
+    // A key evicted from the small queue recently skips it
+    cache.insert("c", "c");
+    assert_eq!(cache.peek(&"e"), None);
+    assert_eq!(queue(&cache, false), vec!["f"]);
+    assert_eq!(queue(&cache, true), vec!["d", "a", "c"]);
+    assert_eq!(cache.validate(), Ok(()));
+}
+
+
+#[test]
+fn test_eviction_history() {
+    use crate::clock::MockClock;
+
+    let clock = MockClock::at(0);
+    let mut cache = Cache::new(2).clock(clock.clone()).eviction_history(2);
+    cache.insert("a", 1);
+    clock.advance(Duration::from_millis(5));
+    cache.get(&"a");
+    cache.insert("b", 2);
+    cache.insert("c", 3);
+    assert!(cache.was_recently_evicted(&"a"));
+    assert!(!cache.was_recently_evicted(&"b"));
+    let record = cache.eviction_record(&"a").unwrap();
+    assert_eq!((record.age(), record.hits(), record.weight()), (Duration::from_millis(5), 1, 1));
+    assert_eq!(record.evicted_at(), SystemTime::UNIX_EPOCH + Duration::from_millis(5));
+
+    // Misses on evicted keys count as re-misses until the key is inserted again
+    cache.get(&"a");
+    cache.get(&"x");
+    assert_eq!((cache.statistics().get_misses(), cache.statistics().get_re_misses()), (2, 1));
+    cache.insert("a", 1);
+    assert!(!cache.was_recently_evicted(&"a"));
+    assert!(cache.was_recently_evicted(&"b"));
+
+    // Explicit removals are not recorded and the history forgets the oldest keys
+    cache.remove(&"a");
+    cache.insert("d", 4);
+    cache.insert("e", 5);
+    cache.insert("f", 6);
+    assert!(!cache.was_recently_evicted(&"a"));
+    assert!(!cache.was_recently_evicted(&"b"));
+    assert!(cache.was_recently_evicted(&"c") && cache.was_recently_evicted(&"d"));
+}
+
+
+#[test]
+fn test_lirs() {
+    let mut cache = Cache::new(3).policy(Policy::Lirs { hir_ratio: 0.3 });
+    let set = |cache: &Cache<&'static str, &str>, lir: bool| {
+        let lists = if lir { &cache.protected } else { &cache.usage };
+        list_keys(cache, &lists[Priority::Normal as usize])
+    };
+
+    // The first elements fill the LIR set, the rest are resident HIR elements evicted first
+    for key in ["a", "b", "c", "d"] {
+        cache.insert(key, key);
+    }
+    assert_eq!(cache.peek(&"c"), None);
+    assert_eq!((set(&cache, true), set(&cache, false)), (vec!["a", "b"], vec!["d"]));
+
+    // "c" comes back while it is still in the stack, so it displaces the bottom LIR element
+    cache.insert("c", "c");
+    assert_eq!(cache.peek(&"d"), None);
+    assert_eq!((set(&cache, true), set(&cache, false)), (vec!["b", "c"], vec!["a"]));
+
+    // A HIR element pruned from the stack has to be accessed twice to become LIR
+    cache.get(&"b");
+    cache.get(&"a");
+    assert_eq!((set(&cache, true), set(&cache, false)), (vec!["c", "b"], vec!["a"]));
+    cache.get(&"a");
+    assert_eq!((set(&cache, true), set(&cache, false)), (vec!["b", "a"], vec!["c"]));
+    assert_eq!(cache.validate(), Ok(()));
+
+    // New keys accessed once only replace HIR elements
+    for key in ["e", "f", "g"] {
+        cache.insert(key, key);
+    }
+    assert_eq!((set(&cache, true), set(&cache, false)), (vec!["b", "a"], vec!["g"]));
+    cache.flush();
+    cache.insert("a", "a");
+    assert_eq!(cache.validate(), Ok(()));
+}
+
+
+#[test]
+fn test_fifo_and_random() {
+    let mut cache = Cache::new(3).policy(Policy::Fifo);
+    for key in ["a", "b", "c"] {
+        cache.insert(key, key);
+    }
+    // Neither reads nor writes reorder the elements
+    cache.get(&"a");
+    cache.insert("a", "A");
+    cache.insert("d", "d");
+    assert_eq!(cache.peek(&"a"), None);
+    assert_eq!(list_keys(&cache, &cache.usage[Priority::Normal as usize]), vec!["b", "c", "d"]);
+
+    let evictions = |seed| {
+        let mut cache = Cache::new(8).policy(Policy::Random { seed });
+        cache.insert_with_priority(100, 100, Priority::High);
+        for key in 0..8 {
+            cache.insert(key, key);
+        }
+        cache.get(&0);
+        let evicted: Vec<u32> = (8..40).filter_map(|key| cache.insert_returning(key, key).map(|(key, _)| key)).collect();
+        assert!(cache.contains_key(&100));
+        assert_eq!(cache.validate(), Ok(()));
+        (evicted, cache.keys().copied().collect::<Vec<_>>())
+    };
+    // The victims depend on the seed only and are not in insertion order
+    assert_eq!(evictions(7), evictions(7));
+    assert_ne!(evictions(7), evictions(8));
+    let (_, keys) = evictions(7);
+    assert_ne!(keys[..7], [33, 34, 35, 36, 37, 38, 39]);
+}
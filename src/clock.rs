@@ -0,0 +1,129 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+
+/// The source of every timestamp a cache reads, e.g. for recency, expiration and statistics
+///
+/// Caches use the `SystemClock` unless another clock is injected with `Cache::clock` or
+/// `CacheBuilder::clock`.
+pub trait Clock: Send + Sync {
+    /// The current time as a UTC UNIX timestamp in us
+    fn now_micros(&self) -> i64;
+}
+
+/// The wall clock of the system
+///
+/// Adjustments of the system time, e.g. by NTP, are passed on to the cache and may reorder the
+/// recency of elements or expire them early. `MonotonicClock` avoids this.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_micros(&self) -> i64 {
+        match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(since) => micros(since),
+            Err(error) => -micros(error.duration()),
+        }
+    }
+}
+
+/// A clock that never jumps, measuring the time elapsed since its creation with `Instant`
+///
+/// Its timestamps start at the system time when the clock was created, so they can still be
+/// converted to wall time but do not follow later adjustments of the system time.
+#[derive(Debug, Clone, Copy)]
+pub struct MonotonicClock {
+    /// The instant the clock was created
+    origin: Instant,
+    /// The system time the clock was created as a UTC UNIX timestamp in us
+    origin_micros: i64,
+}
+
+impl MonotonicClock {
+    /// Create a clock starting at the current system time
+    pub fn new() -> Self {
+        MonotonicClock {
+            origin: Instant::now(),
+            origin_micros: SystemClock.now_micros(),
+        }
+    }
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        MonotonicClock::new()
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now_micros(&self) -> i64 {
+        self.origin_micros.saturating_add(micros(self.origin.elapsed()))
+    }
+}
+
+/// A clock that only moves when told to, for testing time-dependent behavior deterministically
+///
+/// Clones share the same time, so a test can keep a clone to advance the clock of a cache.
+///
+/// # Examples
+/// ```rust
+/// use std::time::Duration;
+/// use slabcache::{Cache, MockClock};
+///
+/// let clock = MockClock::new();
+/// let mut cache = Cache::new(16).clock(clock.clone());
+/// cache.insert_with_ttl("foo", "bar", Duration::from_secs(60));
+///
+/// clock.advance(Duration::from_secs(59));
+/// assert_eq!(cache.get(&"foo"), Some(&"bar"));
+/// clock.advance(Duration::from_secs(1));
+/// assert_eq!(cache.get(&"foo"), None);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MockClock {
+    /// The current time as a UTC UNIX timestamp in us
+    now: Arc<AtomicI64>,
+}
+
+impl MockClock {
+    /// Create a clock standing at the current system time
+    pub fn new() -> Self {
+        MockClock::at(SystemClock.now_micros())
+    }
+
+    /// Create a clock standing at the UTC UNIX timestamp `micros` in us
+    pub fn at(micros: i64) -> Self {
+        MockClock { now: Arc::new(AtomicI64::new(micros)) }
+    }
+
+    /// Move the clock forward by `duration`
+    pub fn advance(&self, duration: Duration) {
+        self.now.fetch_add(micros(duration), Ordering::SeqCst);
+    }
+}
+
+impl Clock for MockClock {
+    fn now_micros(&self) -> i64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
+
+/// A duration in us, saturating at the largest timestamp
+fn micros(duration: Duration) -> i64 {
+    i64::try_from(duration.as_micros()).unwrap_or(i64::MAX)
+}
+
+
+#[cfg(test)]
+#[test]
+fn test_clocks() {
+    let system = SystemClock.now_micros();
+    let monotonic = MonotonicClock::new();
+    assert!((monotonic.now_micros() - system).abs() < 1_000_000);
+    assert!(monotonic.now_micros() <= monotonic.now_micros());
+
+    let mock = MockClock::at(0);
+    mock.clone().advance(Duration::from_millis(5));
+    assert_eq!(mock.now_micros(), 5_000);
+}
@@ -0,0 +1,218 @@
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+
+use crate::cache::{Cache, RemovalCause};
+use crate::policy::Priority;
+
+
+/// A view into a single element of the cache, which may either be occupied or vacant.
+///
+/// Constructed by `Cache::entry`.
+pub enum Entry<'a, K, V, S = RandomState> {
+    /// The key is present in the cache
+    Occupied(OccupiedEntry<'a, K, V, S>),
+    /// The key is not present in the cache
+    Vacant(VacantEntry<'a, K, V, S>),
+}
+
+/// A view into an element that is present in the cache
+pub struct OccupiedEntry<'a, K, V, S = RandomState> {
+    cache: &'a mut Cache<K, V, S>,
+    /// The index of the element in the slab
+    index: usize,
+}
+
+/// A view into a key that is not present in the cache
+pub struct VacantEntry<'a, K, V, S = RandomState> {
+    cache: &'a mut Cache<K, V, S>,
+    key: K,
+}
+
+/// What `Cache::compute` does with an element after its closure has seen the current value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op<V> {
+    /// Keep the element as it is, including changes made through the mutable reference, or leave
+    /// the key vacant
+    Keep,
+    /// Store a new value, inserting the element if the key is vacant
+    Replace(V),
+    /// Remove the element if it is present
+    Remove,
+}
+
+/// The error returned by `Cache::try_insert` when the key is already present
+///
+/// Contains the occupied entry and the value that was not inserted.
+pub struct OccupiedError<'a, K, V, S = RandomState> {
+    /// The entry of the present key
+    pub entry: OccupiedEntry<'a, K, V, S>,
+    /// The value that was not inserted
+    pub value: V,
+}
+
+impl<'a, K: Hash + Eq + Clone, V, S: BuildHasher> Entry<'a, K, V, S> {
+    /// Return the value of the entry, inserting `default` if it is vacant
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Return the value of the entry, inserting the result of `default` if it is vacant
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let started = entry.cache.now();
+                let value = default();
+                entry.insert_loaded(value, started)
+            }
+        }
+    }
+
+    /// Return the value of the entry, inserting the result of the fallible `default` if it is
+    /// vacant and leaving it vacant if `default` fails
+    pub(crate) fn or_try_insert_with<E, F>(self, default: F) -> Result<&'a mut V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+    {
+        match self {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let started = entry.cache.now();
+                let value = default()?;
+                Ok(entry.insert_loaded(value, started))
+            }
+        }
+    }
+
+    /// Modify the value of an occupied entry in place before any potential insert
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+
+    /// The key of the entry
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+}
+
+impl<'a, K: Hash + Eq + Clone, V, S: BuildHasher> OccupiedEntry<'a, K, V, S> {
+    pub(crate) fn new(cache: &'a mut Cache<K, V, S>, index: usize) -> Self {
+        OccupiedEntry { cache, index }
+    }
+
+    /// The key of the entry
+    pub fn key(&self) -> &K {
+        self.cache.key_at(self.index)
+    }
+
+    /// The value of the entry
+    pub fn get(&self) -> &V {
+        self.cache.value_at(self.index)
+    }
+
+    /// A mutable reference to the value of the entry
+    pub fn get_mut(&mut self) -> &mut V {
+        self.cache.value_at_mut(self.index)
+    }
+
+    /// Convert the entry into a mutable reference to its value bound to the cache's lifetime
+    pub fn into_mut(self) -> &'a mut V {
+        self.cache.value_at_mut(self.index)
+    }
+
+    /// Replace the value of the entry and return the previous one
+    pub fn insert(&mut self, value: V) -> V {
+        self.cache.replace_index(self.index, value, None)
+    }
+
+    /// Remove the entry from the cache and return its value
+    pub fn remove(self) -> V {
+        let (_, value) = self
+            .cache
+            .remove_index(self.index, RemovalCause::Explicit)
+            .expect("occupied entry must be present in the cache");
+        value
+    }
+}
+
+impl<'a, K: Hash + Eq + Clone, V, S: BuildHasher> VacantEntry<'a, K, V, S> {
+    pub(crate) fn new(cache: &'a mut Cache<K, V, S>, key: K) -> Self {
+        VacantEntry { cache, key }
+    }
+
+    /// The key that would be used when inserting through this entry
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Take ownership of the key
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Insert a value for the vacant key and return a mutable reference to it
+    pub fn insert(self, value: V) -> &'a mut V {
+        let index = self.cache.insert_new(self.key, value, None, Priority::Normal);
+        self.cache.value_at_mut(index)
+    }
+
+    /// Insert a value that a loader started to load at timestamp `started`, see `Cache::record_load`
+    fn insert_loaded(self, value: V, started: i64) -> &'a mut V {
+        let index = self.cache.insert_new(self.key, value, None, Priority::Normal);
+        self.cache.record_load(index, started);
+        self.cache.value_at_mut(index)
+    }
+}
+
+impl<K, V, S> fmt::Debug for OccupiedError<'_, K, V, S>
+where
+    K: Hash + Eq + Clone + fmt::Debug,
+    V: fmt::Debug,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OccupiedError")
+            .field("key", self.entry.key())
+            .field("old_value", self.entry.get())
+            .field("new_value", &self.value)
+            .finish()
+    }
+}
+
+impl<K, V, S> fmt::Display for OccupiedError<'_, K, V, S>
+where
+    K: Hash + Eq + Clone + fmt::Debug,
+    V: fmt::Debug,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "failed to insert {:?}, key {:?} already exists with value {:?}",
+            self.value,
+            self.entry.key(),
+            self.entry.get(),
+        )
+    }
+}
+
+impl<K, V, S> std::error::Error for OccupiedError<'_, K, V, S>
+where
+    K: Hash + Eq + Clone + fmt::Debug,
+    V: fmt::Debug,
+    S: BuildHasher,
+{
+}
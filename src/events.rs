@@ -0,0 +1,76 @@
+use std::hash::{BuildHasher, Hash};
+use std::sync::mpsc::{self, Receiver};
+
+use crate::cache::Cache;
+
+
+/// Something that happened in a cache, sent to the receiver returned by `Cache::subscribe`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheEvent<K> {
+    /// A new key was stored, replacing the value of a present key is not reported
+    Insert(K),
+    /// A lookup found the key
+    Hit(K),
+    /// A lookup by key did not find it, carrying the hash of the key since the cache does not own
+    /// a copy of it
+    Miss(u64),
+    /// The element was evicted to make room for another one
+    Evict(K),
+    /// The element was removed because it was past its expiration time
+    Expire(K),
+}
+
+impl<K: Hash + Eq + Clone, V, S: BuildHasher> Cache<K, V, S> {
+    /// Start sending the events of the cache to the returned receiver, replacing any previous
+    /// subscription
+    ///
+    /// The channel holds up to `bound` events. The cache never blocks on it: events that do not
+    /// fit are dropped, so a slow observer misses events instead of slowing the cache down. The
+    /// subscription ends when the receiver is dropped. Lookups by token do not report misses.
+    pub fn subscribe(&mut self, bound: usize) -> Receiver<CacheEvent<K>> {
+        let (sender, receiver) = mpsc::sync_channel(bound);
+        self.set_event_sender(Some(sender));
+        receiver
+    }
+}
+
+
+#[cfg(test)]
+#[test]
+fn test_subscribe() {
+    use std::time::Duration;
+
+    let mut cache = Cache::new(2);
+    let events = cache.subscribe(16);
+    cache.insert("key1", 1);
+    cache.get(&"key1");
+    cache.get(&"key2");
+    cache.insert_with_ttl("key2", 2, Duration::ZERO);
+    cache.insert("key3", 3);
+    cache.insert("key4", 4);
+
+    // The observer runs on another thread without access to the cache
+    let observer = std::thread::spawn(move || events.try_iter().collect::<Vec<_>>());
+    let hash = cache.hash_key(&"key2");
+    assert_eq!(
+        observer.join().unwrap(),
+        [
+            CacheEvent::Insert("key1"),
+            CacheEvent::Hit("key1"),
+            CacheEvent::Miss(hash),
+            CacheEvent::Insert("key2"),
+            CacheEvent::Expire("key2"),
+            CacheEvent::Insert("key3"),
+            CacheEvent::Evict("key1"),
+            CacheEvent::Insert("key4"),
+        ]
+    );
+
+    // A full channel drops events instead of blocking
+    let events = cache.subscribe(1);
+    cache.insert("key5", 5);
+    cache.insert("key6", 6);
+    assert_eq!(events.try_iter().count(), 1);
+    drop(events);
+    cache.insert("key7", 7);
+}
@@ -0,0 +1,122 @@
+use std::hash::{BuildHasher, Hash};
+
+use crate::cache::Cache;
+
+
+impl<K: Hash + Eq + Clone, V, S: BuildHasher> Cache<K, V, S> {
+    /// Publish the current statistics to the installed `metrics` recorder, if enabled with
+    /// `metrics`
+    ///
+    /// Exports the counters `slabcache_hits_total`, `slabcache_misses_total`,
+    /// `slabcache_evictions_total` and `slabcache_expirations_total` and the gauges
+    /// `slabcache_size` and `slabcache_weight`. Counters are set to their absolute value, so
+    /// exporting more often does not inflate them. `reset_statistics` makes them restart from 0.
+    pub fn export_metrics(&self) {
+        let Some(name) = self.metrics_name() else {
+            return;
+        };
+        let statistics = self.statistics();
+        let counters = [
+            ("slabcache_hits_total", statistics.get_hits()),
+            ("slabcache_misses_total", statistics.get_misses()),
+            ("slabcache_evictions_total", statistics.get_evictions()),
+            ("slabcache_expirations_total", statistics.get_expirations()),
+        ];
+        for (metric, value) in counters {
+            metrics::counter!(metric, "cache" => name.clone()).absolute(value as u64);
+        }
+        metrics::gauge!("slabcache_size", "cache" => name.clone()).set(self.len() as f64);
+        metrics::gauge!("slabcache_weight", "cache" => name.clone()).set(self.weight() as f64);
+    }
+}
+
+
+#[cfg(test)]
+#[test]
+fn test_export_metrics() {
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    use metrics::{Counter, CounterFn, Gauge, GaugeFn, Histogram, Key, KeyName, Metadata, Recorder, SharedString, Unit};
+
+    /// Records the last value of every metric by its name and labels
+    #[derive(Default)]
+    struct Values(Mutex<BTreeMap<String, f64>>);
+
+    struct Handle(Arc<Values>, String);
+
+    impl CounterFn for Handle {
+        fn increment(&self, value: u64) {
+            *self.0 .0.lock().unwrap().entry(self.1.clone()).or_default() += value as f64;
+        }
+
+        fn absolute(&self, value: u64) {
+            self.0 .0.lock().unwrap().insert(self.1.clone(), value as f64);
+        }
+    }
+
+    impl GaugeFn for Handle {
+        fn increment(&self, value: f64) {
+            *self.0 .0.lock().unwrap().entry(self.1.clone()).or_default() += value;
+        }
+
+        fn decrement(&self, value: f64) {
+            *self.0 .0.lock().unwrap().entry(self.1.clone()).or_default() -= value;
+        }
+
+        fn set(&self, value: f64) {
+            self.0 .0.lock().unwrap().insert(self.1.clone(), value);
+        }
+    }
+
+    struct TestRecorder(Arc<Values>);
+
+    impl TestRecorder {
+        fn handle(&self, key: &Key) -> Arc<Handle> {
+            let labels: Vec<String> = key.labels().map(|label| format!("{}={}", label.key(), label.value())).collect();
+            Arc::new(Handle(self.0.clone(), format!("{}{{{}}}", key.name(), labels.join(","))))
+        }
+    }
+
+    impl Recorder for TestRecorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.handle(key))
+        }
+
+        fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::from_arc(self.handle(key))
+        }
+
+        fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::noop()
+        }
+    }
+
+    let values = Arc::new(Values::default());
+    let recorder = TestRecorder(values.clone());
+    let mut cache = Cache::new(2).metrics("sessions");
+    cache.insert("key1", 1);
+    cache.insert("key2", 2);
+    cache.insert("key3", 3);
+    cache.get(&"key3");
+    cache.get(&"key1");
+
+    metrics::with_local_recorder(&recorder, || {
+        Cache::<&str, i32>::new(1).export_metrics();
+        cache.maintain();
+        cache.maintain();
+    });
+    let values = values.0.lock().unwrap();
+    assert_eq!(values.len(), 6);
+    assert_eq!(values["slabcache_hits_total{cache=sessions}"], 1.0);
+    assert_eq!(values["slabcache_misses_total{cache=sessions}"], 1.0);
+    assert_eq!(values["slabcache_evictions_total{cache=sessions}"], 1.0);
+    assert_eq!(values["slabcache_size{cache=sessions}"], 2.0);
+    assert_eq!(values["slabcache_weight{cache=sessions}"], 2.0);
+}
@@ -0,0 +1,220 @@
+use std::borrow::Borrow;
+
+use crate::cache::Metadata;
+use crate::clock::{Clock, SystemClock};
+use crate::list::{Links, List};
+use crate::policy::Priority;
+
+
+/// An LRU cache of at most `N` elements that keeps all of its storage inline and never allocates.
+///
+/// Slots are preallocated in arrays, so inserting, evicting and removing elements never touches
+/// the heap, which suits targets where even the growth of a slab is unacceptable. The elements
+/// are ordered by the same intrusive list and carry the same `Metadata` as in `Cache`. Keys are
+/// found by a linear scan instead of a hash map, so the cache is meant for small `N` and keys
+/// only need to implement `Eq`.
+///
+/// Elements are always evicted in LRU order, the `Policy` of a `Cache` does not apply. Timestamps
+/// are read from the clock `C`, the system clock by default, see `clock`. A cache without slots
+/// could not hold any element, so `N` must not be zero:
+///
+/// ```rust,compile_fail
+/// let cache: slabcache::FixedCache<&str, u32, 0> = slabcache::FixedCache::new();
+/// ```
+///
+/// # Examples
+/// ```rust
+/// use slabcache::FixedCache;
+///
+/// let mut cache: FixedCache<&str, u32, 2> = FixedCache::new();
+/// cache.insert("foo", 1);
+/// cache.insert("bar", 2);
+/// cache.get(&"foo");
+/// cache.insert("baz", 3);
+///
+/// assert_eq!(cache.peek(&"bar"), None);
+/// assert_eq!(cache.peek(&"foo"), Some(&1));
+/// ```
+pub struct FixedCache<K, V, const N: usize, C = SystemClock> {
+    /// The elements with their metadata, `None` for free slots
+    slots: [Option<(V, Metadata<K>)>; N],
+    /// The links of every slot, each slot is linked into either `usage` or `free`
+    links: [Links; N],
+    /// The occupied slots from the least to the most recently used one
+    usage: List,
+    /// The free slots
+    free: List,
+    /// The clock the timestamps of the metadata are read from
+    clock: C,
+}
+
+impl<K: Eq, V, const N: usize> FixedCache<K, V, N> {
+    /// Create an empty cache holding up to `N` elements
+    pub fn new() -> Self {
+        const { assert!(N > 0, "a FixedCache needs at least one slot") };
+        let mut links = [Links::default(); N];
+        let mut free = List::new();
+        for index in 0..N {
+            free.push_back(&mut links, index);
+        }
+        FixedCache {
+            slots: std::array::from_fn(|_| None),
+            links,
+            usage: List::new(),
+            free,
+            clock: SystemClock,
+        }
+    }
+}
+
+impl<K: Eq, V, const N: usize, C: Clock> FixedCache<K, V, N, C> {
+    /// Read every timestamp from `clock` instead of the system clock, e.g. a `MockClock` in tests
+    pub fn clock<D: Clock>(self, clock: D) -> FixedCache<K, V, N, D> {
+        FixedCache {
+            slots: self.slots,
+            links: self.links,
+            usage: self.usage,
+            free: self.free,
+            clock,
+        }
+    }
+
+    /// The number of elements in the cache
+    pub fn len(&self) -> usize {
+        self.usage.len()
+    }
+
+    /// Whether the cache holds no elements
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The maximum number of elements the cache can hold
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Insert a value into the cache, evicting the least recently used element if it is full
+    ///
+    /// If the key is already present its value is replaced in place, the element becomes the most
+    /// recently used one and the previous value is returned.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let now = self.clock.now_micros();
+        if let Some(index) = self.position(&key) {
+            let (old, metadata) = self.slots[index].as_mut()?;
+            metadata.record_write(now);
+            self.usage.move_to_back(&mut self.links, index);
+            return Some(std::mem::replace(old, value));
+        }
+        let index = match self.free.pop_front(&mut self.links) {
+            Some(index) => index,
+            None => self.usage.pop_front(&mut self.links)?,
+        };
+        self.slots[index] = Some((value, Metadata::new(key, now, 1, Priority::Normal)));
+        self.usage.push_back(&mut self.links, index);
+        None
+    }
+
+    /// Get a value from the cache and update its access time and frequency
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let index = self.position(key)?;
+        self.usage.move_to_back(&mut self.links, index);
+        let (value, metadata) = self.slots[index].as_mut()?;
+        metadata.record_hit(self.clock.now_micros(), true);
+        Some(value)
+    }
+
+    /// Get a value from the cache without updating its access time, frequency or recency
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let index = self.position(key)?;
+        self.slots[index].as_ref().map(|(value, _)| value)
+    }
+
+    /// Remove an element from the cache and return its value
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let index = self.position(key)?;
+        self.usage.unlink(&mut self.links, index);
+        self.free.push_back(&mut self.links, index);
+        self.slots[index].take().map(|(value, _)| value)
+    }
+
+    /// Returns an iterator over the cache from the least to the most recently used element
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&K, &V, &Metadata<K>)> + '_ {
+        self.usage.iter(&self.links).filter_map(|index| {
+            let (value, metadata) = self.slots[index].as_ref()?;
+            Some((metadata.user_key(), value, metadata))
+        })
+    }
+
+    /// The slot holding `key`
+    fn position<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        self.slots.iter().position(|slot| {
+            slot.as_ref().is_some_and(|(_, metadata)| metadata.user_key().borrow() == key)
+        })
+    }
+}
+
+impl<K: Eq, V, const N: usize> Default for FixedCache<K, V, N> {
+    fn default() -> Self {
+        FixedCache::new()
+    }
+}
+
+
+#[cfg(test)]
+#[test]
+fn test_fixed_cache() {
+    let mut cache: FixedCache<&str, u32, 3> = FixedCache::new();
+    assert_eq!(cache.capacity(), 3);
+    assert!(cache.is_empty());
+
+    cache.insert("key1", 1);
+    cache.insert("key2", 2);
+    cache.insert("key3", 3);
+    assert_eq!(cache.get(&"key1"), Some(&1));
+    assert_eq!(cache.insert("key2", 20), Some(2));
+    cache.insert("key4", 4);
+
+    assert_eq!(cache.len(), 3);
+    assert_eq!(cache.peek(&"key3"), None);
+    let keys: Vec<_> = cache.iter().map(|(key, _, _)| *key).collect();
+    assert_eq!(keys, vec!["key1", "key2", "key4"]);
+    assert_eq!(cache.iter().next().unwrap().2.hits(), 1);
+
+    // Removed slots are reused without evicting
+    assert_eq!(cache.remove(&"key2"), Some(20));
+    cache.insert("key5", 5);
+    assert_eq!(cache.peek(&"key1"), Some(&1));
+    assert_eq!(cache.len(), 3);
+}
+
+#[test]
+fn test_fixed_cache_clock() {
+    use std::time::{Duration, SystemTime};
+
+    let clock = crate::clock::MockClock::at(1_000_000);
+    let mut cache = FixedCache::<&str, u32, 2>::new().clock(clock.clone());
+    cache.insert("key1", 1);
+    clock.advance(Duration::from_secs(10));
+    cache.get(&"key1");
+
+    let metadata = cache.iter().next().unwrap().2;
+    assert_eq!(metadata.created_at(), SystemTime::UNIX_EPOCH + Duration::from_secs(1));
+    assert_eq!(metadata.last_accessed(), SystemTime::UNIX_EPOCH + Duration::from_secs(11));
+}
@@ -0,0 +1,134 @@
+use std::collections::{HashMap, VecDeque};
+
+
+/// A bounded FIFO queue of the hashes of recently evicted keys, remembering which keys left the
+/// cache without keeping their values, optionally with a small record per key
+///
+/// Once the queue is full, recording another hash forgets the oldest one. Hash collisions make a
+/// key look recently evicted when another key with the same hash was, which only costs accuracy.
+#[derive(Clone)]
+pub(crate) struct GhostQueue<T = ()> {
+    /// The hashes and their sequence numbers in the order they were recorded, including stale
+    /// entries of hashes that were removed or recorded again since
+    order: VecDeque<(u64, u64)>,
+    /// The hashes currently in the queue with the sequence number and the record they were last
+    /// recorded with
+    members: HashMap<u64, (u64, T)>,
+    /// The sequence number given to the next recorded hash
+    next_sequence: u64,
+    /// The maximum number of hashes kept
+    capacity: usize,
+}
+
+impl<T> Default for GhostQueue<T> {
+    fn default() -> Self {
+        GhostQueue::new(0)
+    }
+}
+
+impl<T> GhostQueue<T> {
+    /// Create a queue remembering up to `capacity` hashes
+    pub(crate) fn new(capacity: usize) -> Self {
+        GhostQueue {
+            order: VecDeque::new(),
+            members: HashMap::new(),
+            next_sequence: 0,
+            capacity,
+        }
+    }
+
+    /// The record of `hash` if it is in the queue
+    pub(crate) fn get(&self, hash: u64) -> Option<&T> {
+        self.members.get(&hash).map(|(_, record)| record)
+    }
+
+    /// Record `hash` as the youngest entry of the queue, forgetting the oldest hashes beyond the
+    /// capacity
+    pub(crate) fn insert(&mut self, hash: u64, record: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.members.insert(hash, (self.next_sequence, record));
+        self.order.push_back((hash, self.next_sequence));
+        self.next_sequence += 1;
+        self.trim();
+        self.compact();
+    }
+
+    /// Take `hash` out of the queue, returning whether it was in it
+    pub(crate) fn remove(&mut self, hash: u64) -> bool {
+        let removed = self.members.remove(&hash).is_some();
+        self.compact();
+        removed
+    }
+
+    /// Change the maximum number of hashes kept, forgetting the oldest ones beyond it
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.trim();
+    }
+
+    /// Forget the oldest hashes until the queue fits its capacity
+    fn trim(&mut self) {
+        while self.members.len() > self.capacity {
+            let Some((oldest, sequence)) = self.order.pop_front() else {
+                break;
+            };
+            if self.members.get(&oldest).is_some_and(|&(current, _)| current == sequence) {
+                self.members.remove(&oldest);
+            }
+        }
+    }
+
+    /// Drop the stale entries from the order once they dominate it
+    fn compact(&mut self) {
+        if self.order.len() > 2 * self.members.len() + 16 {
+            let members = &self.members;
+            self.order
+                .retain(|(hash, sequence)| members.get(hash).is_some_and(|(current, _)| current == sequence));
+        }
+    }
+}
+
+
+#[cfg(test)]
+#[test]
+fn test_ghost_queue() {
+    let mut ghosts = GhostQueue::default();
+    // A queue without capacity remembers nothing
+    ghosts.insert(1, ());
+    assert!(!ghosts.remove(1));
+
+    ghosts.set_capacity(2);
+    ghosts.insert(1, ());
+    ghosts.insert(2, ());
+    // The oldest hash is forgotten first
+    ghosts.insert(3, ());
+    assert!(!ghosts.remove(1));
+    assert!(ghosts.remove(2));
+    assert!(!ghosts.remove(2));
+
+    // A hash recorded again is as young as its last record
+    ghosts.insert(4, ());
+    ghosts.insert(3, ());
+    ghosts.insert(5, ());
+    assert!(!ghosts.remove(4));
+
+    ghosts.set_capacity(1);
+    assert!(!ghosts.remove(3));
+    assert!(ghosts.remove(5));
+}
+
+#[test]
+fn test_ghost_records() {
+    let mut ghosts = GhostQueue::new(100);
+    for record in 0..1000 {
+        ghosts.insert(record % 150, record);
+    }
+    assert_eq!(ghosts.members.len(), 100);
+    assert_eq!(ghosts.get(0), Some(&900));
+    assert_eq!(ghosts.get(99), Some(&999));
+    assert_eq!(ghosts.get(100), None);
+    // Stale entries do not pile up
+    assert!(ghosts.order.len() <= 2 * ghosts.members.len() + 16);
+}
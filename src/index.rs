@@ -0,0 +1,171 @@
+use std::collections::hash_map::{Entry, HashMap};
+use std::collections::TryReserveError;
+use std::hash::{BuildHasher, BuildHasherDefault, Hash, Hasher};
+
+
+/// A hasher passing the precomputed hashes used as keys of a `KeyIndex` through unchanged
+#[derive(Default)]
+struct IdentityHasher(u64);
+
+impl Hasher for IdentityHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = self.0.rotate_left(8) ^ u64::from(byte);
+        }
+    }
+
+    fn write_u64(&mut self, hash: u64) {
+        self.0 = hash;
+    }
+}
+
+/// The slab indices of the elements whose keys share a hash
+#[derive(Clone)]
+enum Bucket {
+    One(usize),
+    /// Only needed on a hash collision
+    Many(Vec<usize>),
+}
+
+/// A map from the hash of a key to the slab index of its element
+///
+/// The index does not store the keys themselves, they only live in the metadata of the elements
+/// and callers resolve hash collisions by comparing them. So storing an element takes ownership of
+/// its key without cloning it.
+#[derive(Clone)]
+pub(crate) struct KeyIndex<S> {
+    /// The hasher of the keys
+    hasher: S,
+    /// The slab indices of the elements by the hash of their key
+    buckets: HashMap<u64, Bucket, BuildHasherDefault<IdentityHasher>>,
+}
+
+impl<S: BuildHasher> KeyIndex<S> {
+    pub(crate) fn with_capacity_and_hasher(capacity: usize, hasher: S) -> Self {
+        KeyIndex {
+            hasher,
+            buckets: HashMap::with_capacity_and_hasher(capacity, BuildHasherDefault::default()),
+        }
+    }
+
+    /// The hash of `key`, which is the same for every borrowed form of a key
+    pub(crate) fn hash<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        self.hasher.hash_one(key)
+    }
+
+    /// The first index with the given hash for which `is_match` returns true
+    pub(crate) fn find<F: FnMut(usize) -> bool>(&self, hash: u64, mut is_match: F) -> Option<usize> {
+        match self.buckets.get(&hash)? {
+            Bucket::One(index) => Some(*index).filter(|&index| is_match(index)),
+            Bucket::Many(indices) => indices.iter().copied().find(|&index| is_match(index)),
+        }
+    }
+
+    /// Add the index of an element whose key has the given hash
+    pub(crate) fn insert(&mut self, hash: u64, index: usize) {
+        match self.buckets.entry(hash) {
+            Entry::Vacant(entry) => {
+                entry.insert(Bucket::One(index));
+            }
+            Entry::Occupied(mut entry) => {
+                let bucket = entry.get_mut();
+                match bucket {
+                    Bucket::One(other) => *bucket = Bucket::Many(vec![*other, index]),
+                    Bucket::Many(indices) => indices.push(index),
+                }
+            }
+        }
+    }
+
+    /// Remove the index of an element whose key has the given hash
+    pub(crate) fn remove(&mut self, hash: u64, index: usize) {
+        let Entry::Occupied(mut entry) = self.buckets.entry(hash) else {
+            return;
+        };
+        let bucket = entry.get_mut();
+        match bucket {
+            Bucket::One(other) if *other == index => {
+                entry.remove();
+            }
+            Bucket::One(_) => {}
+            Bucket::Many(indices) => {
+                indices.retain(|&other| other != index);
+                if let [last] = indices[..] {
+                    *bucket = Bucket::One(last);
+                }
+            }
+        }
+    }
+
+    /// Iterate over every hash together with the index of an element whose key has it
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (u64, usize)> + '_ {
+        self.buckets.iter().flat_map(|(&hash, bucket)| {
+            let indices = match bucket {
+                Bucket::One(index) => std::slice::from_ref(index),
+                Bucket::Many(indices) => &indices[..],
+            };
+            indices.iter().map(move |&index| (hash, index))
+        })
+    }
+
+    /// Reserve room for at least `additional` more keys
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        self.buckets.reserve(additional);
+    }
+
+    /// Reserve room for at least `additional` more keys, failing instead of aborting when the
+    /// memory cannot be allocated
+    pub(crate) fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        self.buckets.try_reserve(additional)
+    }
+
+    /// Shrink the allocated memory as much as possible
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.buckets.shrink_to_fit();
+    }
+
+    /// The number of bytes the index has allocated on the heap
+    pub(crate) fn allocated_bytes(&self) -> usize {
+        let collisions: usize = self
+            .buckets
+            .values()
+            .map(|bucket| match bucket {
+                Bucket::One(_) => 0,
+                Bucket::Many(indices) => indices.capacity() * std::mem::size_of::<usize>(),
+            })
+            .sum();
+        // Every slot of the table carries one control byte next to the entry
+        self.buckets.capacity() * (std::mem::size_of::<(u64, Bucket)>() + 1) + collisions
+    }
+
+    /// Remove every index
+    pub(crate) fn clear(&mut self) {
+        self.buckets.clear();
+    }
+}
+
+
+#[cfg(test)]
+#[test]
+fn test_key_index_collisions() {
+    let mut index = KeyIndex::with_capacity_and_hasher(4, std::collections::hash_map::RandomState::new());
+    let keys = ["key0", "key1", "key2"];
+    // Every key gets the same hash to exercise the collision buckets
+    for slot in 0..keys.len() {
+        index.insert(7, slot);
+    }
+    assert_eq!(index.find(7, |slot| keys[slot] == "key1"), Some(1));
+    assert_eq!(index.find(8, |_| true), None);
+
+    index.remove(7, 1);
+    assert_eq!(index.find(7, |slot| keys[slot] == "key1"), None);
+    index.remove(7, 0);
+    assert_eq!(index.find(7, |slot| keys[slot] == "key2"), Some(2));
+    index.remove(7, 2);
+    assert!(index.buckets.is_empty());
+    assert_eq!(index.hash("key0"), index.hash(&"key0".to_string()));
+}
@@ -1,4 +1,53 @@
+#[cfg(feature = "tokio")]
+mod async_cache;
+mod builder;
 mod cache;
+mod clock;
+mod entry;
+#[cfg(feature = "events")]
+mod events;
+#[cfg(feature = "metrics")]
+mod exporter;
+mod fixed;
+mod ghost;
+mod index;
+mod lirs;
+mod list;
+#[cfg(feature = "background")]
+mod maintenance;
+#[cfg(feature = "arbitrary")]
+pub mod model;
+mod namespace;
+mod policy;
+mod sharded;
+pub mod simulate;
+mod sink;
+mod sketch;
+pub mod snapshot;
 mod statistics;
+mod tiered;
+mod trace;
 
-pub use cache::{Cache, SortOrder};
+#[cfg(feature = "tokio")]
+pub use async_cache::AsyncCache;
+pub use builder::{BuildError, CacheBuilder};
+pub use cache::{
+    Cache, CacheError, CacheIter, CacheIterMut, Drain, EntrySnapshot, EntryStats, EvictionRecord, IntoIter,
+    InvariantViolation, Keys, Metadata, RemovalCause, SortKey, SortOrder, SortedIter, Token, Values,
+};
+pub use clock::{Clock, MockClock, MonotonicClock, SystemClock};
+pub use entry::{Entry, OccupiedEntry, OccupiedError, Op, VacantEntry};
+#[cfg(feature = "events")]
+pub use events::CacheEvent;
+pub use fixed::FixedCache;
+#[cfg(feature = "background")]
+pub use maintenance::MaintenanceHandle;
+pub use namespace::Namespace;
+pub use policy::{Policy, Priority};
+pub use sharded::{ShardedCache, SyncCache};
+pub use sink::{CacheMetric, StatsSink, StatsdSink};
+#[cfg(feature = "snapshot")]
+pub use snapshot::SnapshotError;
+pub use statistics::{Statistics, StatsSnapshot};
+pub use tiered::{SecondaryStore, TieredCache, WritePolicy};
+pub use trace::{read_trace, AccessKind, TraceRecord, TraceRecorder};
@@ -0,0 +1,90 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+
+/// The recency stack of the LIRS policy
+///
+/// The stack orders the keys by their last access, keyed by the hash of the key. Resident keys
+/// know the slab index of their element, non-resident keys were evicted but still carry their
+/// reuse distance: a non-resident key accessed again while in the stack becomes LIR right away.
+/// The number of non-resident keys is bounded, the oldest are forgotten first.
+#[derive(Clone, Default)]
+pub(crate) struct LirsStack {
+    /// The hash of each key and the slab index of its element, if resident, by the sequence number
+    /// of its last access, from the least to the most recently accessed key
+    entries: BTreeMap<u64, (u64, Option<usize>)>,
+    /// The sequence number of the last access of each key in the stack by its hash
+    positions: HashMap<u64, u64>,
+    /// The sequence numbers of the non-resident keys
+    non_resident: BTreeSet<u64>,
+    /// The sequence number given to the next access
+    next_sequence: u64,
+}
+
+impl LirsStack {
+    /// Move the key with the given hash, stored at `index` in the slab, to the top of the stack,
+    /// returning whether it was in the stack before
+    pub(crate) fn touch(&mut self, hash: u64, index: usize) -> bool {
+        let present = self.remove(hash);
+        self.positions.insert(hash, self.next_sequence);
+        self.entries.insert(self.next_sequence, (hash, Some(index)));
+        self.next_sequence += 1;
+        present
+    }
+
+    /// The hash of the least recently accessed key and the slab index of its element, if resident
+    pub(crate) fn bottom(&self) -> Option<(u64, Option<usize>)> {
+        self.entries.first_key_value().map(|(_, &entry)| entry)
+    }
+
+    /// Take the key with the given hash out of the stack, returning whether it was in it
+    pub(crate) fn remove(&mut self, hash: u64) -> bool {
+        let Some(sequence) = self.positions.remove(&hash) else {
+            return false;
+        };
+        self.entries.remove(&sequence);
+        self.non_resident.remove(&sequence);
+        true
+    }
+
+    /// Mark the key with the given hash as evicted, forgetting the oldest non-resident keys once
+    /// there are more than `max_non_resident`
+    pub(crate) fn evict(&mut self, hash: u64, max_non_resident: usize) {
+        let Some(&sequence) = self.positions.get(&hash) else {
+            return;
+        };
+        self.entries.insert(sequence, (hash, None));
+        self.non_resident.insert(sequence);
+        while self.non_resident.len() > max_non_resident {
+            let Some(oldest) = self.non_resident.pop_first() else {
+                break;
+            };
+            if let Some((hash, _)) = self.entries.remove(&oldest) {
+                self.positions.remove(&hash);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+#[test]
+fn test_lirs_stack() {
+    let mut stack = LirsStack::default();
+    assert!(!stack.touch(1, 10));
+    assert!(!stack.touch(2, 20));
+    assert!(!stack.touch(3, 30));
+    assert!(stack.touch(1, 10));
+    assert_eq!(stack.bottom(), Some((2, Some(20))));
+
+    // Evicted keys stay in the stack without an index, up to the given number
+    stack.evict(2, 1);
+    assert_eq!(stack.bottom(), Some((2, None)));
+    assert!(stack.positions.contains_key(&2));
+    stack.evict(3, 1);
+    assert!(!stack.positions.contains_key(&2));
+    assert_eq!(stack.bottom(), Some((3, None)));
+
+    assert!(stack.remove(3));
+    assert!(!stack.remove(3));
+    assert_eq!(stack.bottom(), Some((1, Some(10))));
+}
@@ -0,0 +1,226 @@
+use std::ops::{Index, IndexMut};
+
+#[cfg(test)]
+use slab::Slab;
+
+
+/// The links of an element in an intrusive doubly linked list
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Links {
+    /// The index of the previous element in the slab, towards the front of the list
+    prev: Option<usize>,
+    /// The index of the next element in the slab, towards the back of the list
+    next: Option<usize>,
+}
+
+/// A slab entry that can be linked into a `List`
+pub(crate) trait Linked {
+    fn links(&self) -> &Links;
+    fn links_mut(&mut self) -> &mut Links;
+}
+
+/// A doubly linked list of slab indices whose links are stored inside the slab entries.
+///
+/// Pushing, popping and unlinking an element are O(1) and never allocate, since the list only
+/// stores its two ends and every element carries its own links. An element must be linked into at
+/// most one list at a time and always has to be unlinked before it is removed from the slab.
+///
+/// Any storage indexed by `usize` works in place of a slab, e.g. a fixed-size array of links.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct List {
+    /// The index of the first element in the slab
+    head: Option<usize>,
+    /// The index of the last element in the slab
+    tail: Option<usize>,
+    /// The number of linked elements
+    len: usize,
+}
+
+impl List {
+    pub(crate) fn new() -> Self {
+        List::default()
+    }
+
+    /// The index of the first element
+    pub(crate) fn front(&self) -> Option<usize> {
+        self.head
+    }
+
+    /// The number of linked elements
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Link the element at `index` to the back of the list
+    pub(crate) fn push_back<S>(&mut self, slab: &mut S, index: usize)
+    where
+        S: IndexMut<usize, Output: Linked> + ?Sized,
+    {
+        *slab[index].links_mut() = Links { prev: self.tail, next: None };
+        match self.tail {
+            Some(tail) => slab[tail].links_mut().next = Some(index),
+            None => self.head = Some(index),
+        }
+        self.tail = Some(index);
+        self.len += 1;
+    }
+
+    /// Unlink and return the first element
+    pub(crate) fn pop_front<S>(&mut self, slab: &mut S) -> Option<usize>
+    where
+        S: IndexMut<usize, Output: Linked> + ?Sized,
+    {
+        let head = self.head?;
+        self.unlink(slab, head);
+        Some(head)
+    }
+
+    /// Unlink the element at `index`, which has to be linked into this list
+    pub(crate) fn unlink<S>(&mut self, slab: &mut S, index: usize)
+    where
+        S: IndexMut<usize, Output: Linked> + ?Sized,
+    {
+        let Links { prev, next } = std::mem::take(slab[index].links_mut());
+        match prev {
+            Some(prev) => slab[prev].links_mut().next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => slab[next].links_mut().prev = prev,
+            None => self.tail = prev,
+        }
+        self.len -= 1;
+    }
+
+    /// Move the element at `index`, which has to be linked into this list, to the back
+    pub(crate) fn move_to_back<S>(&mut self, slab: &mut S, index: usize)
+    where
+        S: IndexMut<usize, Output: Linked> + ?Sized,
+    {
+        if self.tail != Some(index) {
+            self.unlink(slab, index);
+            self.push_back(slab, index);
+        }
+    }
+
+    /// The indices of the elements from front to back, or `None` if the links are inconsistent
+    ///
+    /// Unlike `iter`, a link to an element that `get` cannot find is reported instead of panicking,
+    /// as is a chain of links that does not match the ends or the length of the list.
+    pub(crate) fn checked_indices<'a, T, F>(&self, get: F) -> Option<Vec<usize>>
+    where
+        T: Linked + 'a,
+        F: Fn(usize) -> Option<&'a T>,
+    {
+        let mut indices = Vec::with_capacity(self.len);
+        let mut prev = None;
+        let mut current = self.head;
+        while let Some(index) = current {
+            // Following more links than the length means the chain is too long or cyclic
+            if indices.len() == self.len {
+                return None;
+            }
+            let links = get(index)?.links();
+            if links.prev != prev {
+                return None;
+            }
+            indices.push(index);
+            prev = Some(index);
+            current = links.next;
+        }
+        (indices.len() == self.len && self.tail == prev).then_some(indices)
+    }
+
+    /// Forget every element without touching their links, for use after the slab was cleared
+    pub(crate) fn clear(&mut self) {
+        *self = List::new();
+    }
+
+    /// Iterate over the indices of the elements from front to back
+    pub(crate) fn iter<'a, S>(&self, slab: &'a S) -> ListIter<'a, S>
+    where
+        S: Index<usize, Output: Linked> + ?Sized,
+    {
+        ListIter {
+            slab,
+            front: self.head,
+            back: self.tail,
+            remaining: self.len,
+        }
+    }
+}
+
+/// An iterator over the indices of the elements in a `List`
+pub(crate) struct ListIter<'a, S: ?Sized> {
+    slab: &'a S,
+    front: Option<usize>,
+    back: Option<usize>,
+    remaining: usize,
+}
+
+impl<S: Index<usize, Output: Linked> + ?Sized> Iterator for ListIter<'_, S> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let index = self.front?;
+        self.front = self.slab[index].links().next;
+        self.remaining -= 1;
+        Some(index)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<S: Index<usize, Output: Linked> + ?Sized> DoubleEndedIterator for ListIter<'_, S> {
+    fn next_back(&mut self) -> Option<usize> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let index = self.back?;
+        self.back = self.slab[index].links().prev;
+        self.remaining -= 1;
+        Some(index)
+    }
+}
+
+impl<S: Index<usize, Output: Linked> + ?Sized> ExactSizeIterator for ListIter<'_, S> {}
+
+
+impl Linked for Links {
+    fn links(&self) -> &Links {
+        self
+    }
+
+    fn links_mut(&mut self) -> &mut Links {
+        self
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_list_operations() {
+    let mut slab = Slab::new();
+    let mut list = List::new();
+    let indices: Vec<usize> = (0..4).map(|_| slab.insert(Links::default())).collect();
+    for &index in &indices {
+        list.push_back(&mut slab, index);
+    }
+
+    list.unlink(&mut slab, indices[1]);
+    list.move_to_back(&mut slab, indices[0]);
+    assert_eq!(list.iter(&slab).collect::<Vec<_>>(), vec![indices[2], indices[3], indices[0]]);
+    assert_eq!(list.iter(&slab).rev().collect::<Vec<_>>(), vec![indices[0], indices[3], indices[2]]);
+    assert_eq!(list.len(), 3);
+
+    assert_eq!(list.pop_front(&mut slab), Some(indices[2]));
+    assert_eq!(list.front(), Some(indices[3]));
+    list.unlink(&mut slab, indices[0]);
+    list.unlink(&mut slab, indices[3]);
+    assert_eq!(list, List::new());
+    assert_eq!(list.pop_front(&mut slab), None);
+}
@@ -0,0 +1,111 @@
+use std::hash::Hash;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Weak};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::sharded::ShardedCache;
+
+
+/// A handle to a maintenance thread started by `SyncCache::spawn_maintenance`, see `ShardedCache`
+///
+/// Dropping the handle stops the thread and waits for its current pass to finish.
+pub struct MaintenanceHandle {
+    /// Dropping the sender wakes the thread up and tells it to stop
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MaintenanceHandle {
+    /// Stop the thread and wait for it to exit
+    pub fn stop(self) {
+        drop(self);
+    }
+}
+
+impl Drop for MaintenanceHandle {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            // A panic in the thread was caused by user code, e.g. a listener, and already reported
+            let _ = thread.join();
+        }
+    }
+}
+
+impl<K, V> ShardedCache<K, V>
+where
+    K: Hash + Eq + Clone + Send + 'static,
+    V: Send + Sync + 'static,
+{
+    /// Start a thread that runs `maintain` on every shard each `interval` and
+    /// `decay_frequencies` each `decay_interval`, if given
+    ///
+    /// `maintain` removes expired elements and trims every shard to its low watermark. The thread
+    /// only holds a weak reference, so it exits on its own once the cache is dropped.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// use slabcache::SyncCache;
+    ///
+    /// let cache = Arc::new(SyncCache::new(1024, 8));
+    /// cache.insert_with_ttl("foo", "bar", Duration::ZERO);
+    ///
+    /// let handle = cache.spawn_maintenance(Duration::from_secs(1), None);
+    /// handle.stop();
+    /// ```
+    pub fn spawn_maintenance(
+        self: &Arc<Self>,
+        interval: Duration,
+        decay_interval: Option<Duration>,
+    ) -> MaintenanceHandle {
+        let cache = Arc::downgrade(self);
+        let (stop, stopped) = mpsc::channel();
+        let thread = std::thread::spawn(move || {
+            let mut next_decay = decay_interval.map(|decay_interval| Instant::now() + decay_interval);
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let Some(cache) = Weak::upgrade(&cache) else {
+                    return;
+                };
+                cache.maintain();
+                if let (Some(decay_interval), Some(at)) = (decay_interval, next_decay) {
+                    if Instant::now() >= at {
+                        cache.decay_frequencies();
+                        next_decay = Some(at + decay_interval);
+                    }
+                }
+            }
+        });
+        MaintenanceHandle {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+
+#[cfg(test)]
+#[test]
+fn test_spawn_maintenance() {
+    use crate::cache::Cache;
+
+    let cache = Arc::new(ShardedCache::from_shards(vec![Cache::new(4).low_watermark(1)]));
+    for i in 0..4 {
+        cache.insert(i, i);
+    }
+    cache.insert_with_ttl(4, 4, Duration::ZERO);
+
+    let handle = cache.spawn_maintenance(Duration::from_millis(5), Some(Duration::from_millis(5)));
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while cache.statistics().get_current_size() > 1 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    handle.stop();
+
+    assert_eq!(cache.statistics().get_current_size(), 1);
+    assert_eq!(cache.get(&3), Some(3));
+    assert_eq!(cache.statistics().get_expirations(), 1);
+}
@@ -0,0 +1,195 @@
+//! Operation sequences applied to a cache and to a naive reference model side by side, to find
+//! consistency bugs with property-based tests or fuzzers generating the operations through
+//! `arbitrary`
+
+use std::collections::VecDeque;
+use std::hash::Hash;
+
+use crate::cache::{Cache, InvariantViolation};
+
+
+/// An operation on a cache, see `run`
+#[derive(Debug, Clone, PartialEq, Eq, arbitrary::Arbitrary)]
+pub enum Operation<K, V> {
+    /// `Cache::insert`, returning the previous value
+    Insert(K, V),
+    /// `Cache::get`, returning the value
+    Get(K),
+    /// `Cache::remove`, returning the value
+    Remove(K),
+    /// `Cache::flush`
+    Flush,
+    /// `Cache::set_capacity`
+    Resize(usize),
+}
+
+/// Where a cache diverged from the reference model while running a sequence of operations
+#[derive(Debug, Clone, PartialEq)]
+pub enum Divergence<K, V> {
+    /// The operation at `step` returned another value than the model
+    Output { step: usize, op: Operation<K, V>, expected: Option<V>, actual: Option<V> },
+    /// After the operation at `step` the cache holds other elements than the model, listed in
+    /// eviction order
+    Contents { step: usize, op: Operation<K, V>, expected: Vec<(K, V)>, actual: Vec<(K, V)> },
+    /// After the operation at `step` the internal structures of the cache are inconsistent
+    Invariant { step: usize, op: Operation<K, V>, violation: InvariantViolation },
+}
+
+/// A deliberately simple LRU cache that the real one is checked against
+///
+/// Elements are kept in a list from the least to the most recently used one, so every operation
+/// is a linear scan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Model<K, V> {
+    capacity: usize,
+    elements: VecDeque<(K, V)>,
+}
+
+impl<K: Eq, V: Clone> Model<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Model { capacity, elements: VecDeque::new() }
+    }
+
+    /// Apply `op`, returning what the cache is expected to return
+    pub fn apply(&mut self, op: Operation<K, V>) -> Option<V> {
+        match op {
+            Operation::Insert(key, value) => {
+                let previous = self.take(&key).map(|(_, previous)| previous);
+                if previous.is_none() && self.elements.len() >= self.capacity {
+                    self.elements.pop_front();
+                }
+                if self.capacity > 0 {
+                    self.elements.push_back((key, value));
+                }
+                previous
+            }
+            Operation::Get(key) => {
+                let element = self.take(&key)?;
+                let value = element.1.clone();
+                self.elements.push_back(element);
+                Some(value)
+            }
+            Operation::Remove(key) => self.take(&key).map(|(_, value)| value),
+            Operation::Flush => {
+                self.elements.clear();
+                None
+            }
+            Operation::Resize(capacity) => {
+                self.capacity = capacity;
+                while self.elements.len() > capacity {
+                    self.elements.pop_front();
+                }
+                None
+            }
+        }
+    }
+
+    /// The elements from the least to the most recently used one
+    pub fn elements(&self) -> impl Iterator<Item = &(K, V)> {
+        self.elements.iter()
+    }
+
+    fn take(&mut self, key: &K) -> Option<(K, V)> {
+        let position = self.elements.iter().position(|(other, _)| other == key)?;
+        self.elements.remove(position)
+    }
+}
+
+/// Apply `op` to `cache`, returning the value the cache returns
+pub fn apply<K: Hash + Eq + Clone, V: Clone>(cache: &mut Cache<K, V>, op: Operation<K, V>) -> Option<V> {
+    match op {
+        Operation::Insert(key, value) => cache.insert(key, value),
+        Operation::Get(key) => cache.get(&key).cloned(),
+        Operation::Remove(key) => cache.remove(&key),
+        Operation::Flush => {
+            cache.flush();
+            None
+        }
+        Operation::Resize(capacity) => {
+            cache.set_capacity(capacity);
+            None
+        }
+    }
+}
+
+/// Run `ops` against an LRU cache and a `Model`, both of the given capacity, returning the first
+/// divergence between them
+///
+/// After every operation the outputs, the contents in eviction order and the invariants checked
+/// by `Cache::validate` are compared.
+///
+/// # Examples
+/// ```rust
+/// use slabcache::model::{self, Operation};
+///
+/// use Operation::{Get, Insert, Resize};
+///
+/// let ops = vec![Insert(1, 'a'), Insert(2, 'b'), Get(1), Insert(3, 'c'), Resize(1)];
+/// assert_eq!(model::run(2, ops), Ok(()));
+/// ```
+pub fn run<K, V, I>(capacity: usize, ops: I) -> Result<(), Divergence<K, V>>
+where
+    K: Hash + Eq + Clone,
+    V: Clone + PartialEq,
+    I: IntoIterator<Item = Operation<K, V>>,
+{
+    let mut cache = Cache::new(capacity);
+    let mut model = Model::new(capacity);
+    for (step, op) in ops.into_iter().enumerate() {
+        let expected = model.apply(op.clone());
+        let actual = apply(&mut cache, op.clone());
+        if actual != expected {
+            return Err(Divergence::Output { step, op, expected, actual });
+        }
+        if let Err(violation) = cache.validate() {
+            return Err(Divergence::Invariant { step, op, violation });
+        }
+        let expected: Vec<(K, V)> = model.elements().cloned().collect();
+        let actual: Vec<(K, V)> = cache.iter().map(|(key, value, _)| (key.clone(), value.clone())).collect();
+        if actual != expected {
+            return Err(Divergence::Contents { step, op, expected, actual });
+        }
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+#[test]
+fn test_run() {
+    use arbitrary::{Arbitrary, Unstructured};
+
+    let ops = vec![
+        Operation::Insert(1, 1),
+        Operation::Insert(2, 2),
+        Operation::Get(1),
+        Operation::Insert(3, 3),
+        Operation::Remove(1),
+        Operation::Flush,
+    ];
+    assert_eq!(run(2, ops), Ok(()));
+
+    // Random operations on a few keys, so that they hit, replace and evict each other
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    for _ in 0..500 {
+        let bytes: Vec<u8> = (0..256)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        let mut input = Unstructured::new(&bytes);
+        let ops = std::iter::from_fn(|| (!input.is_empty()).then(|| Operation::arbitrary(&mut input).unwrap()));
+        let ops: Vec<Operation<u8, u8>> = ops.collect();
+        let ops = ops.into_iter().map(|op| match op {
+            Operation::Insert(key, value) => Operation::Insert(key % 8, value),
+            Operation::Get(key) => Operation::Get(key % 8),
+            Operation::Remove(key) => Operation::Remove(key % 8),
+            Operation::Resize(capacity) => Operation::Resize(capacity % 6 + 1),
+            Operation::Flush => Operation::Flush,
+        });
+        assert_eq!(run(4, ops), Ok(()));
+    }
+}
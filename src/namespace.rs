@@ -0,0 +1,151 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::time::Duration;
+
+use crate::cache::Cache;
+use crate::statistics::StatsSnapshot;
+
+
+/// A handle to a namespace of a cache, whose keys are kept apart from the keys of every other
+/// namespace while sharing the capacity and eviction order of the cache
+///
+/// Constructed by `Cache::namespace`.
+///
+/// # Examples
+/// ```rust
+/// use slabcache::Cache;
+///
+/// let mut cache = Cache::new(16);
+/// cache.namespace("users").insert(1, "alice");
+/// cache.namespace("groups").insert(1, "admins");
+///
+/// assert_eq!(cache.namespace("users").get(&1), Some(&"alice"));
+/// assert_eq!(cache.namespace("groups").invalidate(), 1);
+/// assert_eq!(cache.len(), 1);
+/// ```
+pub struct Namespace<'a, K, V, S = RandomState> {
+    cache: &'a mut Cache<K, V, S>,
+    /// The id of the namespace in the cache
+    id: u32,
+}
+
+impl<'a, K: Hash + Eq + Clone, V, S: BuildHasher> Namespace<'a, K, V, S> {
+    pub(crate) fn new(cache: &'a mut Cache<K, V, S>, id: u32) -> Self {
+        Namespace { cache, id }
+    }
+
+    /// The name of the namespace
+    pub fn name(&self) -> &str {
+        self.cache.namespace_name(self.id)
+    }
+
+    /// Insert a value into the namespace like `Cache::insert` and return the previous value of
+    /// the key in the namespace
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.cache.insert_expiring(key, value, None, None, Some(self.id))
+    }
+
+    /// Insert a value into the namespace that expires after `ttl`
+    pub fn insert_with_ttl(&mut self, key: K, value: V, ttl: Duration) -> Option<V> {
+        self.cache.insert_expiring(key, value, Some(ttl), None, Some(self.id))
+    }
+
+    /// Get a value from the namespace and update its access time and frequency like `Cache::get`
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.get_in(self.id, key)
+    }
+
+    /// Get a value from the namespace without updating its recency, metadata or the statistics
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.peek_in(Some(self.id), key)
+    }
+
+    /// Whether a live element is stored under `key` in the namespace
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.peek(key).is_some()
+    }
+
+    /// Remove an element from the namespace and return its value
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.remove_in(Some(self.id), key)
+    }
+
+    /// Remove every element of the namespace and return how many were removed
+    ///
+    /// The eviction listener is notified about every element like for `Cache::retain`. The
+    /// namespace stays registered and keeps its statistics.
+    pub fn invalidate(&mut self) -> usize {
+        self.cache.invalidate_namespace(self.id)
+    }
+
+    /// The number of elements in the namespace, including expired ones that were not removed yet
+    pub fn len(&self) -> usize {
+        self.cache.namespace_statistics(self.id).get_current_size()
+    }
+
+    /// Whether the namespace holds no elements
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// A snapshot of the statistics of the namespace
+    ///
+    /// Its accesses, inserts and removals are also counted in the statistics of the cache.
+    pub fn statistics(&self) -> StatsSnapshot {
+        self.cache.namespace_statistics(self.id).snapshot()
+    }
+}
+
+
+#[cfg(test)]
+#[test]
+fn test_namespaces() {
+    let mut cache = Cache::new(4);
+    cache.insert("key", 0);
+    cache.namespace("a").insert("key", 1);
+    cache.namespace("b").insert("key", 2);
+    cache.namespace("b").insert("other", 3);
+
+    // The same key lives independently in every namespace and outside of them
+    assert_eq!(cache.get(&"key"), Some(&0));
+    assert_eq!(cache.namespace("a").get(&"key"), Some(&1));
+    assert_eq!(cache.namespace("b").peek(&"key"), Some(&2));
+    assert_eq!(cache.namespace("a").get(&"other"), None);
+    assert!(!cache.contains_key(&"other"));
+    assert_eq!(cache.namespace("b").name(), "b");
+
+    let stats = cache.namespace("a").statistics();
+    assert_eq!((stats.get_hits(), stats.get_misses(), stats.get_inserts()), (1, 1, 1));
+    assert_eq!(cache.namespace("b").len(), 2);
+    assert_eq!(cache.statistics().get_hits(), 2);
+
+    // The namespaces share the capacity and LRU order of the cache
+    cache.insert("new", 4);
+    assert_eq!(cache.namespace("b").peek(&"key"), None);
+    assert_eq!(cache.namespace("b").statistics().get_evictions(), 1);
+    assert_eq!(cache.namespace("b").len(), 1);
+
+    assert_eq!(cache.namespace("b").invalidate(), 1);
+    assert!(cache.namespace("b").is_empty());
+    assert_eq!(cache.namespace("b").statistics().get_removals(), 1);
+    assert_eq!(cache.len(), 3);
+    assert_eq!(cache.namespace("a").remove(&"key"), Some(1));
+    assert_eq!(cache.remove(&"key"), Some(0));
+}
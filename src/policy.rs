@@ -0,0 +1,68 @@
+/// The policy deciding which element is evicted when the cache is full
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Policy {
+    /// Evict the least recently used element
+    #[default]
+    Lru,
+    /// Segmented LRU: new elements land in a probationary segment and are promoted to a protected
+    /// segment on their second access. Elements are evicted from the probationary segment first,
+    /// so a scan over many keys that are accessed only once cannot flush the hot elements.
+    ///
+    /// `protected_ratio` is the share of the capacity reserved for the protected segment. When
+    /// the protected segment is full, its least recently used element is demoted back to the most
+    /// recently used end of the probationary segment.
+    Slru { protected_ratio: f64 },
+    /// CLOCK: an access only marks the element as referenced instead of reordering the usage
+    /// list. On eviction a hand sweeps the elements in insertion order, giving referenced elements
+    /// a second chance by clearing their mark, and evicts the first unreferenced one. This
+    /// approximates LRU while making hits much cheaper.
+    Clock,
+    /// S3-FIFO: new elements enter a small FIFO queue, elements evicted from it without having
+    /// been accessed leave their key hash in a ghost queue, and elements that were accessed move
+    /// to a main FIFO queue instead. New elements whose key is still in the ghost queue are
+    /// inserted into the main queue directly. The main queue reinserts accessed elements at its
+    /// back, up to three times per element, so like CLOCK a hit never reorders a queue.
+    ///
+    /// `small_ratio` is the share of the capacity for the small queue, 0.1 works well for most
+    /// workloads. The ghost queue remembers as many keys as the main queue holds elements.
+    S3Fifo { small_ratio: f64 },
+    /// LIRS: elements are ranked by their reuse distance, the number of other keys accessed
+    /// between two accesses to a key, instead of their recency. Elements with a short reuse
+    /// distance form the LIR set that is only evicted from as a last resort, the others are
+    /// resident HIR elements evicted in FIFO order. A recency stack remembers recently evicted
+    /// keys as well, so a key that comes back soon enough joins the LIR set directly.
+    ///
+    /// `hir_ratio` is the share of the capacity for resident HIR elements, about 0.01 is typical.
+    /// At least one element is always HIR.
+    Lirs { hir_ratio: f64 },
+    /// Evict the element that was inserted first, accesses and writes never reorder elements
+    Fifo,
+    /// Evict an element picked at random, which is cheap since accesses never reorder elements
+    /// and often close to LRU in hit ratio. The generator is seeded with `seed`, so the victims of
+    /// a cache replaying the same operations are reproducible.
+    Random { seed: u64 },
+}
+
+/// The number of priority levels
+pub(crate) const PRIORITIES: usize = 3;
+
+/// How important it is to keep an element in the cache
+///
+/// Elements of a lower priority are always evicted before elements of a higher priority,
+/// regardless of their recency. The eviction policy only orders elements of the same priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Priority {
+    /// Cheap to recompute, evicted first
+    Low,
+    /// The priority of elements inserted without one
+    #[default]
+    Normal,
+    /// Expensive to recompute, evicted last
+    High,
+}
+
+impl Priority {
+    /// Every priority from the lowest to the highest, indexed by its position in the lists
+    pub(crate) const ALL: [Priority; PRIORITIES] = [Priority::Low, Priority::Normal, Priority::High];
+}
@@ -0,0 +1,280 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::time::Duration;
+
+use crate::cache::Cache;
+use crate::statistics::{Statistics, StatsSnapshot};
+
+
+/// A load in flight with the timestamp at which its loader started
+type PendingLoad<V> = Arc<OnceLock<(V, i64)>>;
+
+/// The loads in flight for the keys of one shard
+type PendingLoads<K, V> = Mutex<HashMap<K, PendingLoad<V>>>;
+
+/// A thread-safe cache that spreads its keys over several independently locked `Cache` shards.
+///
+/// This is the synchronized cache of the crate, also available as `SyncCache`.
+///
+/// Each key is hashed to exactly one shard, so threads working on different shards never contend
+/// for the same lock. The LRU order and the capacity are maintained per shard.
+///
+/// # Examples
+/// ```rust
+/// use slabcache::ShardedCache;
+///
+/// let cache = ShardedCache::new(1024, 8);
+///
+/// std::thread::scope(|scope| {
+///     scope.spawn(|| cache.insert("foo", "bar"));
+///     scope.spawn(|| cache.insert("baz", "bar"));
+/// });
+///
+/// assert_eq!(cache.get(&"foo"), Some("bar"));
+/// assert_eq!(cache.statistics().get_hits(), 1);
+/// ```
+pub struct ShardedCache<K, V> {
+    /// The shards, each guarded by its own lock
+    shards: Box<[Mutex<Cache<K, V>>]>,
+    /// The hasher used to assign keys to shards
+    hasher: RandomState,
+    /// The loads currently in flight for each shard, shared by every thread waiting for the same key
+    pending: Box<[PendingLoads<K, V>]>,
+}
+
+/// The thread-safe cache shared between threads, see `ShardedCache`
+pub type SyncCache<K, V> = ShardedCache<K, V>;
+
+impl<K: Hash + Eq + Clone, V> ShardedCache<K, V> {
+    /// Create a cache holding at least `capacity` elements split evenly over `shards` shards
+    ///
+    /// # Panics
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize, shards: usize) -> Self {
+        let shards = shards.max(1);
+        let per_shard = capacity.div_ceil(shards);
+        Self::from_shards((0..shards).map(|_| Cache::new(per_shard)).collect())
+    }
+
+    /// Create a cache from preconfigured shards, e.g. to set expiration policies or listeners
+    ///
+    /// # Panics
+    /// Panics if `shards` is empty.
+    pub fn from_shards(shards: Vec<Cache<K, V>>) -> Self {
+        assert!(!shards.is_empty(), "a sharded cache needs at least one shard");
+        let pending = shards.iter().map(|_| Mutex::new(HashMap::new())).collect();
+        ShardedCache {
+            shards: shards.into_iter().map(Mutex::new).collect(),
+            hasher: RandomState::new(),
+            pending,
+        }
+    }
+
+    /// Get a copy of a value from the cache and update its access time and frequency
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        self.shard(key).get(key).cloned()
+    }
+
+    /// Return the cached value for `key`, loading it with `loader` on a miss
+    ///
+    /// Only one thread runs a loader per missing key at a time, every other thread asking for the
+    /// same key blocks until the value is loaded. This keeps a popular key that just expired from
+    /// causing a stampede on the backend. If the loading thread panics, one of the waiting threads
+    /// takes over with its own loader.
+    pub fn get_or_load<F>(&self, key: K, loader: F) -> V
+    where
+        F: FnOnce() -> V,
+        V: Clone,
+    {
+        if let Some(value) = self.get(&key) {
+            return value;
+        }
+        let index = self.shard_index(&key);
+        let cell = {
+            let mut pending = lock(&self.pending[index]);
+            // Another thread may have completed the load since the miss above
+            if let Some(value) = lock(&self.shards[index]).peek(&key) {
+                return value.clone();
+            }
+            pending.entry(key.clone()).or_default().clone()
+        };
+        let (value, started) = cell
+            .get_or_init(|| {
+                let started = lock(&self.shards[index]).now();
+                (loader(), started)
+            })
+            .clone();
+
+        // The first thread to get here publishes the value, the others find the load already gone
+        let mut pending = lock(&self.pending[index]);
+        if pending.get(&key).is_some_and(|current| Arc::ptr_eq(current, &cell)) {
+            pending.remove(&key);
+            lock(&self.shards[index]).insert_loaded(key, value.clone(), started);
+        }
+        value
+    }
+
+    /// Insert a value into the cache, returning the previous value of the key
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        self.shard(&key).insert(key, value)
+    }
+
+    /// Insert a value into the cache that expires once `ttl` has elapsed
+    pub fn insert_with_ttl(&self, key: K, value: V, ttl: Duration) -> Option<V> {
+        self.shard(&key).insert_with_ttl(key, value, ttl)
+    }
+
+    /// Remove an element from the cache and return its value
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).remove(key)
+    }
+
+    /// Remove every element that is past its expiration time and return how many were removed
+    pub fn purge_expired(&self) -> usize {
+        self.shards.iter().map(|shard| lock(shard).purge_expired()).sum()
+    }
+
+    /// Run `Cache::maintain` on every shard and return how many elements were removed
+    pub fn maintain(&self) -> usize {
+        self.shards.iter().map(|shard| lock(shard).maintain()).sum()
+    }
+
+    /// Halve the access frequency of every element in every shard
+    pub fn decay_frequencies(&self) {
+        for shard in self.shards.iter() {
+            lock(shard).decay_frequencies();
+        }
+    }
+
+    /// Remove all elements from every shard but preserve allocated memory
+    pub fn flush(&self) {
+        for shard in self.shards.iter() {
+            lock(shard).flush();
+        }
+    }
+
+    /// The statistics of all shards added together
+    pub fn statistics(&self) -> StatsSnapshot {
+        let mut statistics = Statistics::new();
+        for shard in self.shards.iter() {
+            statistics.merge(lock(shard).raw_statistics());
+        }
+        statistics.snapshot()
+    }
+
+    /// Zero the statistics counters of every shard
+    pub fn reset_statistics(&self) {
+        for shard in self.shards.iter() {
+            lock(shard).reset_statistics();
+        }
+    }
+
+    /// The number of shards
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Lock the shard responsible for `key`
+    ///
+    /// A borrowed key hashes like its owned form, so both select the same shard.
+    fn shard<Q: Hash + ?Sized>(&self, key: &Q) -> MutexGuard<'_, Cache<K, V>> {
+        lock(&self.shards[self.shard_index(key)])
+    }
+
+
+    /// The index of the shard responsible for `key`, which also owns its pending loads
+    fn shard_index<Q: Hash + ?Sized>(&self, key: &Q) -> usize {
+        self.hasher.hash_one(key) as usize % self.shards.len()
+    }
+}
+
+/// Lock a shard or the pending loads, recovering them if another thread panicked while holding
+/// the lock
+///
+/// Every cache operation leaves the shard consistent before it can panic in user code, and the
+/// pending loads are only touched outside of user code, so a poisoned lock is still safe to use.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+
+#[cfg(test)]
+#[test]
+fn test_sharded_basic() {
+    let cache = ShardedCache::new(16, 4);
+
+    assert_eq!(cache.shard_count(), 4);
+    assert_eq!(cache.insert("key1", "value1"), None);
+    assert_eq!(cache.insert("key1", "updated"), Some("value1"));
+    assert_eq!(cache.get(&"key1"), Some("updated"));
+    assert_eq!(cache.get(&"key2"), None);
+    assert_eq!(cache.remove(&"key1"), Some("updated"));
+    assert_eq!(cache.get(&"key1"), None);
+
+    let statistics = cache.statistics();
+    assert_eq!(statistics.get_hits(), 1);
+    assert_eq!(statistics.get_misses(), 2);
+    assert_eq!(statistics.get_current_size(), 0);
+}
+
+#[test]
+fn test_sharded_concurrent() {
+    let cache = ShardedCache::new(1000, 8);
+
+    std::thread::scope(|scope| {
+        for thread in 0..4 {
+            let cache = &cache;
+            scope.spawn(move || {
+                for i in 0..100 {
+                    cache.insert(thread * 100 + i, i);
+                    assert_eq!(cache.get(&(thread * 100 + i)), Some(i));
+                }
+            });
+        }
+    });
+
+    let statistics = cache.statistics();
+    assert_eq!(statistics.get_hits(), 400);
+    assert_eq!(statistics.get_current_size(), 400);
+}
+
+#[test]
+fn test_sharded_get_or_load() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    let cache = ShardedCache::new(16, 4);
+    let loads = AtomicUsize::new(0);
+
+    std::thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                let value = cache.get_or_load("key", || {
+                    loads.fetch_add(1, Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(50));
+                    42
+                });
+                assert_eq!(value, 42);
+            });
+        }
+    });
+
+    assert_eq!(loads.load(Ordering::SeqCst), 1);
+    let load_duration = cache.shard(&"key").iter().next().and_then(|(_, _, metadata)| metadata.load_duration());
+    assert!(load_duration.is_some_and(|duration| duration >= Duration::from_millis(50)));
+    assert_eq!(cache.get(&"key"), Some(42));
+    assert_eq!(cache.get_or_load("key", || 0), 42);
+    assert!(cache.pending.iter().all(|pending| lock(pending).is_empty()));
+}
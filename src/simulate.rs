@@ -0,0 +1,154 @@
+//! Replays of access traces recorded by a `TraceRecorder` against other cache configurations,
+//! to compare their hit ratios without deploying them
+
+use std::time::Duration;
+
+use crate::cache::Cache;
+use crate::clock::{Clock, MockClock};
+use crate::policy::Policy;
+use crate::trace::{AccessKind, TraceRecord};
+
+
+/// A cache configuration to replay a trace against, see `replay`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimulationConfig {
+    pub capacity: usize,
+    pub policy: Policy,
+    /// Whether to enable the TinyLFU admission filter, see `Cache::tiny_lfu`
+    pub tiny_lfu: bool,
+}
+
+impl SimulationConfig {
+    /// A configuration evicting with `policy` at `capacity`, without admission filter
+    pub fn new(capacity: usize, policy: Policy) -> Self {
+        SimulationConfig { capacity, policy, tiny_lfu: false }
+    }
+
+    /// Enable the TinyLFU admission filter
+    pub fn tiny_lfu(mut self) -> Self {
+        self.tiny_lfu = true;
+        self
+    }
+}
+
+/// The outcome of replaying a trace against one configuration
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimulationReport {
+    pub config: SimulationConfig,
+    /// The number of lookups that found their key
+    pub hits: usize,
+    /// The number of lookups that did not find their key
+    pub misses: usize,
+    /// The number of elements evicted to make room for others
+    pub evictions: usize,
+}
+
+impl SimulationReport {
+    /// The share of lookups that found their key, 0 without lookups
+    pub fn hit_ratio(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+/// Replay `trace` against every configuration in `configs`, returning a report for each in the
+/// same order
+///
+/// Every recorded hit or miss is replayed as a lookup and every insert as an insert, so the
+/// replayed lookups hit or miss depending on the configuration and not on the recording. The
+/// keys are the recorded key hashes and the clock follows the recorded timestamps.
+///
+/// # Examples
+/// ```rust
+/// use slabcache::simulate::{self, SimulationConfig};
+/// use slabcache::{Cache, Policy, TraceRecorder};
+///
+/// let recorder = TraceRecorder::ring(1024);
+/// let mut cache = Cache::new(2);
+/// cache.record_trace(recorder.clone());
+/// for key in [1, 2, 3, 1, 2, 3] {
+///     if cache.get(&key).is_none() {
+///         cache.insert(key, key);
+///     }
+/// }
+///
+/// let configs = [SimulationConfig::new(2, Policy::Lru), SimulationConfig::new(4, Policy::Lru)];
+/// let reports = simulate::replay(recorder.records(), &configs);
+/// assert_eq!(reports[0].hit_ratio(), 0.0);
+/// assert_eq!(reports[1].hit_ratio(), 0.5);
+/// ```
+pub fn replay<I>(trace: I, configs: &[SimulationConfig]) -> Vec<SimulationReport>
+where
+    I: IntoIterator<Item = TraceRecord>,
+{
+    let trace: Vec<TraceRecord> = trace.into_iter().collect();
+    configs.iter().map(|config| replay_one(&trace, *config)).collect()
+}
+
+/// Replay `trace` against a single configuration
+fn replay_one(trace: &[TraceRecord], config: SimulationConfig) -> SimulationReport {
+    let clock = MockClock::at(trace.first().map_or(0, |record| record.timestamp));
+    let mut cache = Cache::new(config.capacity).policy(config.policy).clock(clock.clone());
+    if config.tiny_lfu {
+        cache = cache.tiny_lfu();
+    }
+    for record in trace {
+        // Timestamps recorded from a wall clock may go backwards, the mock clock only moves forward
+        let elapsed = record.timestamp - clock.now_micros();
+        if elapsed > 0 {
+            clock.advance(Duration::from_micros(elapsed as u64));
+        }
+        match record.kind {
+            AccessKind::Hit | AccessKind::Miss => {
+                cache.get(&record.key_hash);
+            }
+            AccessKind::Insert => {
+                cache.insert(record.key_hash, ());
+            }
+        }
+    }
+    let statistics = cache.statistics();
+    SimulationReport {
+        config,
+        hits: statistics.get_hits(),
+        misses: statistics.get_misses(),
+        evictions: statistics.get_evictions(),
+    }
+}
+
+
+#[cfg(test)]
+#[test]
+fn test_replay() {
+    let record = |key_hash, timestamp, kind| TraceRecord { key_hash, timestamp, kind };
+    let mut trace = Vec::new();
+    // A hot key interleaved with a scan over cold keys
+    for (i, key) in (100..110).enumerate() {
+        let timestamp = i as i64 * 10;
+        trace.push(record(1, timestamp, AccessKind::Hit));
+        trace.push(record(1, timestamp, AccessKind::Insert));
+        trace.push(record(key, timestamp + 5, AccessKind::Miss));
+        trace.push(record(key, timestamp + 5, AccessKind::Insert));
+    }
+    let configs = [
+        SimulationConfig::new(1, Policy::Lru),
+        SimulationConfig::new(2, Policy::Lru),
+        SimulationConfig::new(2, Policy::Slru { protected_ratio: 0.5 }).tiny_lfu(),
+        SimulationConfig::new(2, Policy::Fifo),
+    ];
+    let reports = replay(trace, &configs);
+    assert_eq!(reports.len(), 4);
+    assert_eq!(reports[0].config, configs[0]);
+    assert_eq!((reports[0].hits, reports[0].misses), (0, 20));
+    assert_eq!(reports[0].evictions, 19);
+    assert_eq!((reports[1].hits, reports[1].misses), (9, 11));
+    assert_eq!(reports[1].hit_ratio(), 0.45);
+    assert_eq!(reports[2].hits, 9);
+    // FIFO evicts the hot key every other round
+    assert_eq!(reports[3].hits, 5);
+    assert_eq!(replay(Vec::new(), &configs[..1])[0].hit_ratio(), 0.0);
+}
@@ -0,0 +1,153 @@
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::cache::RemovalCause;
+
+
+/// A significant event in a cache, reported to its `StatsSink`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheMetric {
+    /// A lookup found its key
+    Hit,
+    /// A lookup did not find its key
+    Miss,
+    /// A new key was stored
+    Insert,
+    /// An element left the cache for the given reason
+    Removal(RemovalCause),
+}
+
+/// A receiver of the metrics of a cache, attached with `Cache::stats_sink`
+///
+/// `record` is called while the cache is being accessed, so it should be cheap, e.g. by only
+/// counting and sending the counts in batches like `StatsdSink`.
+pub trait StatsSink: Send + Sync {
+    fn record(&self, metric: CacheMetric);
+}
+
+impl<T: StatsSink + ?Sized> StatsSink for Arc<T> {
+    fn record(&self, metric: CacheMetric) {
+        (**self).record(metric);
+    }
+}
+
+/// The statsd buckets counted by a `StatsdSink`
+const BUCKETS: [&str; 7] = ["hits", "misses", "inserts", "evictions", "expirations", "removals", "replacements"];
+
+/// A `StatsSink` counting metrics and sending the counts to a statsd server over UDP in batches
+///
+/// Every `batch_size` metrics the counts since the last send go out as statsd counters in a single
+/// datagram, e.g. `myapp.cache.hits:42|c`. `flush` sends the remaining counts, e.g. on shutdown.
+/// Sending is best effort: the counts of a datagram that could not be sent are lost.
+pub struct StatsdSink {
+    socket: UdpSocket,
+    /// The prefix of every bucket, e.g. `myapp.cache`
+    prefix: String,
+    batch_size: usize,
+    /// The counts of every bucket since the last send
+    counts: [AtomicU64; BUCKETS.len()],
+    /// The number of metrics recorded since the last send
+    pending: AtomicUsize,
+}
+
+impl StatsdSink {
+    /// Send the counts to the statsd server at `address` under buckets starting with `prefix`
+    pub fn new<A: ToSocketAddrs>(address: A, prefix: impl Into<String>) -> io::Result<Self> {
+        let address = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address to send to"))?;
+        let local = match address {
+            SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(StatsdSink {
+            socket,
+            prefix: prefix.into(),
+            batch_size: 1000,
+            counts: Default::default(),
+            pending: AtomicUsize::new(0),
+        })
+    }
+
+    /// Send the counts every `batch_size` metrics instead of every 1000
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Send the counts recorded since the last send
+    pub fn flush(&self) -> io::Result<()> {
+        self.pending.store(0, Ordering::Relaxed);
+        let lines: Vec<String> = BUCKETS
+            .iter()
+            .zip(&self.counts)
+            .filter_map(|(bucket, count)| match count.swap(0, Ordering::Relaxed) {
+                0 => None,
+                count => Some(format!("{}.{bucket}:{count}|c", self.prefix)),
+            })
+            .collect();
+        if !lines.is_empty() {
+            self.socket.send(lines.join("\n").as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl StatsSink for StatsdSink {
+    fn record(&self, metric: CacheMetric) {
+        let bucket = match metric {
+            CacheMetric::Hit => 0,
+            CacheMetric::Miss => 1,
+            CacheMetric::Insert => 2,
+            CacheMetric::Removal(RemovalCause::Capacity) => 3,
+            CacheMetric::Removal(RemovalCause::Expired) => 4,
+            CacheMetric::Removal(RemovalCause::Explicit) => 5,
+            CacheMetric::Removal(RemovalCause::Replaced) => 6,
+        };
+        self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+        if self.pending.fetch_add(1, Ordering::Relaxed) + 1 >= self.batch_size {
+            // The hot path cannot report errors, the next batch tries again
+            let _ = self.flush();
+        }
+    }
+}
+
+
+#[cfg(test)]
+#[test]
+fn test_statsd_sink() {
+    use std::time::Duration;
+
+    use crate::cache::Cache;
+
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    server.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let sink = Arc::new(StatsdSink::new(server.local_addr().unwrap(), "app.cache").unwrap().batch_size(6));
+    let mut cache = Cache::new(1).stats_sink(sink.clone());
+    cache.insert("key1", 1);
+    cache.get(&"key1");
+    cache.get(&"key2");
+    cache.insert("key1", 10);
+    cache.insert("key2", 2);
+
+    // The sixth metric completes the batch
+    let mut buffer = [0; 512];
+    let received = server.recv(&mut buffer).unwrap();
+    let datagram = std::str::from_utf8(&buffer[..received]).unwrap();
+    assert_eq!(
+        datagram,
+        "app.cache.hits:1|c\napp.cache.misses:1|c\napp.cache.inserts:2|c\napp.cache.evictions:1|c\n\
+         app.cache.replacements:1|c"
+    );
+
+    cache.remove(&"key2");
+    sink.flush().unwrap();
+    let received = server.recv(&mut buffer).unwrap();
+    assert_eq!(&buffer[..received], b"app.cache.removals:1|c");
+}
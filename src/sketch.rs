@@ -0,0 +1,127 @@
+/// The number of rows, i.e. independent hash functions, of the sketch
+const DEPTH: usize = 4;
+
+/// The largest value a counter can hold, small counters are enough to tell hot from cold keys
+const MAX_COUNT: u8 = 15;
+
+/// Odd multipliers deriving one index per row from a single 64 bit hash
+const SEEDS: [u64; DEPTH] = [
+    0x9E37_79B9_7F4A_7C15,
+    0xC2B2_AE3D_27D4_EB4F,
+    0x1656_67B1_9E37_79F9,
+    0x27D4_EB2F_1656_67C5,
+];
+
+
+/// A count-min sketch estimating how often keys were seen in a fixed amount of memory.
+///
+/// Estimates never undercount but may overcount due to hash collisions. All counters are halved
+/// once the number of recorded keys reaches the sample size, so the estimates reflect recent
+/// popularity rather than all-time popularity.
+#[derive(Clone)]
+pub(crate) struct CountMinSketch {
+    /// The counters of all rows laid out one row after the other
+    counters: Vec<u8>,
+    /// A mask selecting a column from a hash, the number of columns is a power of two
+    mask: usize,
+    /// The number of keys recorded since the counters were last halved
+    additions: usize,
+    /// The number of recorded keys after which the counters are halved
+    sample_size: usize,
+}
+
+impl CountMinSketch {
+    /// Create a sketch sized for a cache holding `capacity` elements
+    pub(crate) fn new(capacity: usize) -> Self {
+        let width = capacity.max(16).next_power_of_two();
+        CountMinSketch {
+            counters: vec![0; width * DEPTH],
+            mask: width - 1,
+            additions: 0,
+            sample_size: width.saturating_mul(10),
+        }
+    }
+
+    /// Record one occurrence of the key with the given hash, returning whether the counters were
+    /// halved because the sample size was reached
+    pub(crate) fn increment(&mut self, hash: u64) -> bool {
+        for row in 0..DEPTH {
+            let slot = self.slot(row, hash);
+            let counter = &mut self.counters[slot];
+            *counter = (*counter + 1).min(MAX_COUNT);
+        }
+        self.additions += 1;
+        if self.additions < self.sample_size {
+            return false;
+        }
+        self.reset();
+        true
+    }
+
+    /// Estimate how often the key with the given hash was recorded
+    pub(crate) fn estimate(&self, hash: u64) -> u8 {
+        (0..DEPTH)
+            .map(|row| self.counters[self.slot(row, hash)])
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// The number of bytes the sketch has allocated on the heap
+    pub(crate) fn allocated_bytes(&self) -> usize {
+        self.counters.capacity()
+    }
+
+    /// Halve every counter to age out old occurrences
+    pub(crate) fn reset(&mut self) {
+        for counter in self.counters.iter_mut() {
+            *counter /= 2;
+        }
+        self.additions /= 2;
+    }
+
+    /// The position of the counter for `hash` in `row`
+    fn slot(&self, row: usize, hash: u64) -> usize {
+        let mixed = hash.wrapping_mul(SEEDS[row]);
+        let column = (mixed ^ (mixed >> 32)) as usize & self.mask;
+        row * (self.mask + 1) + column
+    }
+}
+
+
+#[cfg(test)]
+#[test]
+fn test_sketch_estimate() {
+    let mut sketch = CountMinSketch::new(16);
+
+    for _ in 0..5 {
+        sketch.increment(42);
+    }
+    sketch.increment(7);
+
+    assert!(sketch.estimate(42) >= 5);
+    assert!(sketch.estimate(7) >= 1);
+    assert!(sketch.estimate(42) > sketch.estimate(7));
+
+    // Counters saturate instead of overflowing
+    for _ in 0..100 {
+        sketch.increment(42);
+    }
+    assert_eq!(sketch.estimate(42), MAX_COUNT);
+}
+
+#[test]
+fn test_sketch_aging() {
+    let mut sketch = CountMinSketch::new(16);
+
+    for _ in 0..8 {
+        sketch.increment(42);
+    }
+    sketch.reset();
+    assert_eq!(sketch.estimate(42), 4);
+
+    // Recording the last key of a sample halves every counter
+    sketch.additions = sketch.sample_size - 1;
+    sketch.increment(42);
+    assert_eq!(sketch.estimate(42), 2);
+    assert_eq!(sketch.additions, sketch.sample_size / 2);
+}
@@ -0,0 +1,100 @@
+//! Copies of the cache contents taken by `Cache::snapshot` and saved to disk by `Cache::save_to`
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::cache::EntrySnapshot;
+
+#[cfg(feature = "snapshot")]
+mod file;
+
+#[cfg(feature = "snapshot")]
+pub use file::SnapshotError;
+
+
+/// The changes between two snapshots taken by `Cache::snapshot`, see `diff`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotDiff<K> {
+    /// The keys only present in the newer snapshot, in its eviction order
+    pub added: Vec<K>,
+    /// The keys only present in the older snapshot, in its eviction order
+    pub removed: Vec<K>,
+    /// The keys present in both snapshots whose value changed, in the eviction order of the newer
+    pub changed: Vec<K>,
+    /// The change of the access frequency of every key present in both snapshots whose frequency
+    /// changed, in the eviction order of the newer
+    pub frequency_deltas: Vec<(K, i64)>,
+}
+
+impl<K> SnapshotDiff<K> {
+    /// Whether the snapshots hold the same keys with the same values and frequencies
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+            && self.frequency_deltas.is_empty()
+    }
+}
+
+/// Compare the snapshot `old` with the later snapshot `new`
+///
+/// Elements of different namespaces with the same key are not told apart.
+pub fn diff<K, V>(old: &[(K, V, EntrySnapshot)], new: &[(K, V, EntrySnapshot)]) -> SnapshotDiff<K>
+where
+    K: Hash + Eq + Clone,
+    V: PartialEq,
+{
+    let before: HashMap<&K, (&V, &EntrySnapshot)> =
+        old.iter().map(|(key, value, entry)| (key, (value, entry))).collect();
+    let after: HashMap<&K, &V> = new.iter().map(|(key, value, _)| (key, value)).collect();
+    let mut diff = SnapshotDiff {
+        added: Vec::new(),
+        removed: old
+            .iter()
+            .filter(|(key, _, _)| !after.contains_key(key))
+            .map(|(key, _, _)| key.clone())
+            .collect(),
+        changed: Vec::new(),
+        frequency_deltas: Vec::new(),
+    };
+    for (key, value, entry) in new {
+        let Some((previous, previous_entry)) = before.get(key) else {
+            diff.added.push(key.clone());
+            continue;
+        };
+        if *previous != value {
+            diff.changed.push(key.clone());
+        }
+        let delta = entry.frequency() as i64 - previous_entry.frequency() as i64;
+        if delta != 0 {
+            diff.frequency_deltas.push((key.clone(), delta));
+        }
+    }
+    diff
+}
+
+
+#[cfg(test)]
+#[test]
+fn test_diff() {
+    use crate::cache::Cache;
+
+    let mut cache = Cache::new(3);
+    cache.insert("key1", 1);
+    cache.insert("key2", 2);
+    cache.insert("key3", 3);
+    let old = cache.snapshot();
+    assert!(diff(&old, &old).is_empty());
+
+    cache.get(&"key1");
+    cache.get(&"key1");
+    cache.insert("key3", 30);
+    cache.insert("key4", 4);
+    let new = cache.snapshot();
+
+    let changes = diff(&old, &new);
+    assert_eq!(changes.added, ["key4"]);
+    assert_eq!(changes.removed, ["key2"]);
+    assert_eq!(changes.changed, ["key3"]);
+    assert_eq!(changes.frequency_deltas, [("key1", 2)]);
+}
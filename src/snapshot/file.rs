@@ -0,0 +1,143 @@
+use std::fmt;
+use std::fs::File;
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::cache::Cache;
+use crate::statistics::Statistics;
+
+
+/// The bytes every snapshot file starts with
+const MAGIC: [u8; 8] = *b"SLABCACH";
+
+/// The version of the snapshot format, bumped whenever the encoding of the payload changes
+const VERSION: u16 = 1;
+
+/// The reason a snapshot could not be saved or loaded
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// Reading or writing the snapshot file failed
+    Io(io::Error),
+    /// The file does not start with the snapshot header
+    NotASnapshot,
+    /// The snapshot was written in a format version this build cannot read
+    UnsupportedVersion(u16),
+    /// The payload could not be encoded or decoded
+    Encoding(postcard::Error),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "failed to access the snapshot: {error}"),
+            SnapshotError::NotASnapshot => write!(f, "the file is not a cache snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {version}, expected {VERSION}")
+            }
+            SnapshotError::Encoding(error) => write!(f, "malformed snapshot: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SnapshotError::Io(error) => Some(error),
+            SnapshotError::Encoding(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(error: io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+impl From<postcard::Error> for SnapshotError {
+    fn from(error: postcard::Error) -> Self {
+        SnapshotError::Encoding(error)
+    }
+}
+
+impl<K, V, S> Cache<K, V, S>
+where
+    K: Hash + Eq + Clone + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+    S: BuildHasher + Default,
+{
+    /// Write the elements in eviction order, their metadata and the statistics to `path`
+    ///
+    /// The snapshot uses a compact binary encoding behind a versioned header, so loading a
+    /// snapshot written by an incompatible version fails with an error instead of misreading it.
+    /// Like the serde form, the configuration of the cache is not saved.
+    pub fn save_to(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        let writer = postcard::to_io(&(self.raw_statistics(), self), writer)?;
+        writer.into_inner().map_err(io::IntoInnerError::into_error)?.sync_all()?;
+        Ok(())
+    }
+
+    /// Restore a cache with the default configuration from a snapshot written by `save_to`
+    pub fn load_from(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+        let payload = bytes.strip_prefix(&MAGIC).ok_or(SnapshotError::NotASnapshot)?;
+        let (version, payload) = payload.split_first_chunk::<2>().ok_or(SnapshotError::NotASnapshot)?;
+        let version = u16::from_le_bytes(*version);
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let (statistics, mut cache): (Statistics, Self) = postcard::from_bytes(payload)?;
+        cache.restore_statistics(statistics);
+        Ok(cache)
+    }
+}
+
+
+#[cfg(test)]
+#[test]
+fn test_snapshot_roundtrip() {
+    use crate::policy::Priority;
+
+    let path = std::env::temp_dir().join(format!("slabcache-roundtrip-{}", std::process::id()));
+    let mut cache = Cache::new(3);
+    cache.insert_with_priority("key1".to_string(), 1, Priority::High);
+    cache.insert("key2".to_string(), 2);
+    cache.insert("key3".to_string(), 3);
+    cache.get("key2");
+    cache.get("key4");
+
+    cache.save_to(&path).unwrap();
+    let mut restored: Cache<String, i32> = Cache::load_from(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(restored.keys().collect::<Vec<_>>(), cache.keys().collect::<Vec<_>>());
+    assert_eq!(restored.statistics(), cache.statistics());
+    restored.insert("key5".to_string(), 5);
+    assert_eq!(restored.peek("key3"), None);
+    assert_eq!(restored.peek("key1"), Some(&1));
+}
+
+#[test]
+fn test_snapshot_version() {
+    let path = std::env::temp_dir().join(format!("slabcache-version-{}", std::process::id()));
+
+    std::fs::write(&path, b"not a snapshot").unwrap();
+    let result: Result<Cache<String, i32>, _> = Cache::load_from(&path);
+    assert!(matches!(result, Err(SnapshotError::NotASnapshot)));
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&(VERSION + 1).to_le_bytes());
+    std::fs::write(&path, bytes).unwrap();
+    let result: Result<Cache<String, i32>, _> = Cache::load_from(&path);
+    assert!(matches!(result, Err(SnapshotError::UnsupportedVersion(version)) if version == VERSION + 1));
+    std::fs::remove_file(&path).unwrap();
+}
@@ -1,42 +1,493 @@
+use std::time::Duration;
+
+use crate::cache::RemovalCause;
+use crate::clock::{Clock, SystemClock};
+
+
+/// Counters describing how effective a cache is
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Statistics {
     hits: usize,
+    /// Hits on keys recorded as missing upstream, these are counted as hits as well
+    negative_hits: usize,
     misses: usize,
     current_size: usize,
+    /// Elements evicted or rejected to stay within the capacity or the maximum weight
+    evictions: usize,
+    /// Elements removed because they were past their expiration time
+    expirations: usize,
+    /// Elements removed explicitly by the user
+    removals: usize,
+    /// New elements stored in the cache
+    inserts: usize,
+    /// Values overwritten by a new value for the same key
+    replacements: usize,
+    /// The time evicted elements spent in the cache
+    eviction_ages: Histogram,
+    /// The time the loader APIs took to load values
+    #[cfg_attr(feature = "serde", serde(default))]
+    load_times: Histogram,
+    /// Misses on keys in the eviction history, see `Cache::eviction_history`
+    #[cfg_attr(feature = "serde", serde(default))]
+    re_misses: usize,
+    /// The hits and misses of the most recent intervals, if enabled
+    #[cfg_attr(feature = "serde", serde(skip))]
+    window: Option<Window>,
 }
 
 impl Statistics {
     pub fn new() -> Self {
         Statistics {
             hits: 0,
+            negative_hits: 0,
             misses: 0,
             current_size: 0,
+            evictions: 0,
+            expirations: 0,
+            removals: 0,
+            inserts: 0,
+            replacements: 0,
+            eviction_ages: Histogram::default(),
+            load_times: Histogram::default(),
+            re_misses: 0,
+            window: None,
+        }
+    }
+
+    /// Create statistics that additionally keep the hits and misses of the last `intervals`
+    /// intervals of length `interval`
+    pub fn with_window(interval: Duration, intervals: usize) -> Self {
+        Statistics {
+            window: Some(Window::new(interval, intervals)),
+            ..Statistics::new()
         }
     }
 
     pub fn hit(&mut self) {
+        self.hit_at(SystemClock.now_micros());
+    }
+
+    /// Count a hit at the UTC UNIX timestamp `now` in us
+    pub(crate) fn hit_at(&mut self, now: i64) {
         self.hits += 1;
+        if let Some(window) = self.window.as_mut() {
+            window.record(now, true);
+        }
+    }
+
+    /// Count a hit on a key recorded as missing upstream, in addition to the hit itself
+    pub fn negative_hit(&mut self) {
+        self.negative_hits += 1;
     }
 
     pub fn miss(&mut self) {
+        self.miss_at(SystemClock.now_micros());
+    }
+
+    /// Count a miss at the UTC UNIX timestamp `now` in us
+    pub(crate) fn miss_at(&mut self, now: i64) {
         self.misses += 1;
+        if let Some(window) = self.window.as_mut() {
+            window.record(now, false);
+        }
+    }
+
+    /// Count a miss on a key that was evicted for capacity recently, in addition to the miss itself
+    pub fn re_missed(&mut self) {
+        self.re_misses += 1;
     }
 
     pub fn update_size(&mut self, size: usize) {
         self.current_size = size;
     }
 
-    #[allow(dead_code)]
+    pub fn inserted(&mut self) {
+        self.inserts += 1;
+    }
+
+    /// Count `count` elements that left the cache or had their value overwritten for `cause`
+    pub fn removed(&mut self, cause: RemovalCause, count: usize) {
+        let counter = match cause {
+            RemovalCause::Capacity => &mut self.evictions,
+            RemovalCause::Expired => &mut self.expirations,
+            RemovalCause::Explicit => &mut self.removals,
+            RemovalCause::Replaced => &mut self.replacements,
+        };
+        *counter += count;
+    }
+
+    /// Record the age of an element evicted to stay within the capacity or the maximum weight
+    pub fn evicted_at_age(&mut self, age: Duration) {
+        self.eviction_ages.record(age);
+    }
+
+    /// Record the time a loader took to load a value
+    pub fn loaded(&mut self, duration: Duration) {
+        self.load_times.record(duration);
+    }
+
+    /// Zero every counter, the size keeps tracking the cache
+    pub fn reset(&mut self) {
+        let mut window = self.window.take();
+        if let Some(window) = window.as_mut() {
+            window.clear();
+        }
+        *self = Statistics {
+            current_size: self.current_size,
+            window,
+            ..Statistics::new()
+        };
+    }
+
+    /// A copy of the current counters
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            hits: self.hits,
+            negative_hits: self.negative_hits,
+            misses: self.misses,
+            current_size: self.current_size,
+            evictions: self.evictions,
+            re_misses: self.re_misses,
+            expirations: self.expirations,
+            removals: self.removals,
+            inserts: self.inserts,
+            replacements: self.replacements,
+        }
+    }
+
+    /// Add the counters of `other`, e.g. to aggregate the statistics of several caches
+    pub fn merge(&mut self, other: &Statistics) {
+        self.hits += other.hits;
+        self.negative_hits += other.negative_hits;
+        self.misses += other.misses;
+        self.current_size += other.current_size;
+        self.evictions += other.evictions;
+        self.re_misses += other.re_misses;
+        self.expirations += other.expirations;
+        self.removals += other.removals;
+        self.inserts += other.inserts;
+        self.replacements += other.replacements;
+        self.eviction_ages.merge(&other.eviction_ages);
+        self.load_times.merge(&other.load_times);
+    }
+
+    pub fn get_hits(&self) -> usize {
+        self.hits
+    }
+
+    pub fn get_negative_hits(&self) -> usize {
+        self.negative_hits
+    }
+
+    pub fn get_misses(&self) -> usize {
+        self.misses
+    }
+
+    pub fn get_current_size(&self) -> usize {
+        self.current_size
+    }
+
+    pub fn get_evictions(&self) -> usize {
+        self.evictions
+    }
+
+    pub fn get_re_misses(&self) -> usize {
+        self.re_misses
+    }
+
+    pub fn get_expirations(&self) -> usize {
+        self.expirations
+    }
+
+    pub fn get_removals(&self) -> usize {
+        self.removals
+    }
+
+    pub fn get_inserts(&self) -> usize {
+        self.inserts
+    }
+
+    pub fn get_replacements(&self) -> usize {
+        self.replacements
+    }
+
+    /// The number of lookups, i.e. hits and misses
+    pub fn requests(&self) -> usize {
+        self.hits + self.misses
+    }
+
+    /// The share of lookups that were hits, 0 if there were no lookups
+    pub fn hit_ratio(&self) -> f64 {
+        ratio(self.hits, self.requests())
+    }
+
+    /// The share of lookups that were misses, 0 if there were no lookups
+    pub fn miss_ratio(&self) -> f64 {
+        ratio(self.misses, self.requests())
+    }
+
+    /// The age below which `percentile` percent of the evicted elements were evicted
+    ///
+    /// Ages are bucketed by powers of two, so the result is the upper bound of a bucket and may
+    /// overestimate the actual age by up to a factor of two. Returns `None` if nothing was evicted.
+    pub fn eviction_age_percentile(&self, percentile: f64) -> Option<Duration> {
+        self.eviction_ages.percentile(percentile)
+    }
+
+    /// The time below which `percentile` percent of the loads took
+    ///
+    /// Bucketed like `eviction_age_percentile`. Returns `None` if nothing was loaded.
+    pub fn load_time_percentile(&self, percentile: f64) -> Option<Duration> {
+        self.load_times.percentile(percentile)
+    }
+
+    /// The share of lookups within the last `duration` that were hits, 0 if there were none
+    ///
+    /// The duration is rounded up to whole intervals and capped at the length of the window.
+    /// Returns `None` unless the statistics were created with a window.
+    pub fn hit_ratio_last(&self, duration: Duration) -> Option<f64> {
+        self.hit_ratio_last_at(SystemClock.now_micros(), duration)
+    }
+
+    /// The hit ratio within `duration` before the UTC UNIX timestamp `now` in us
+    pub(crate) fn hit_ratio_last_at(&self, now: i64, duration: Duration) -> Option<f64> {
+        let window = self.window.as_ref()?;
+        let (hits, misses) = window.totals(now, duration);
+        Some(ratio(hits, hits + misses))
+    }
+}
+
+/// A histogram of durations with one bucket per power of two microseconds
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Histogram {
+    /// The number of durations in each bucket, bucket `i` holds durations below 2^i us
+    buckets: Vec<usize>,
+    /// The total number of recorded durations
+    count: usize,
+}
+
+impl Histogram {
+    /// Count one duration
+    fn record(&mut self, duration: Duration) {
+        let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        let bucket = (u64::BITS - micros.leading_zeros()) as usize;
+        if self.buckets.len() <= bucket {
+            self.buckets.resize(bucket + 1, 0);
+        }
+        self.buckets[bucket] += 1;
+        self.count += 1;
+    }
+
+    /// Add the counts of `other`
+    fn merge(&mut self, other: &Histogram) {
+        if self.buckets.len() < other.buckets.len() {
+            self.buckets.resize(other.buckets.len(), 0);
+        }
+        for (bucket, count) in self.buckets.iter_mut().zip(&other.buckets) {
+            *bucket += count;
+        }
+        self.count += other.count;
+    }
+
+    /// The upper bound of the bucket containing the `percentile`th percentile
+    fn percentile(&self, percentile: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((percentile.clamp(0.0, 100.0) / 100.0 * self.count as f64).ceil() as usize).max(1);
+        let mut seen = 0;
+        let bucket = self.buckets.iter().position(|count| {
+            seen += count;
+            seen >= rank
+        })?;
+        Some(Duration::from_micros(1u64.checked_shl(bucket as u32).unwrap_or(u64::MAX)))
+    }
+}
+
+/// A ring buffer of hit and miss counts per fixed-length interval
+#[derive(Debug, Clone)]
+struct Window {
+    /// The length of an interval in us
+    interval: i64,
+    /// The counts of the most recent intervals, indexed by interval number modulo their count
+    buckets: Vec<Bucket>,
+}
+
+/// The lookups recorded during one interval
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    /// The number of the interval since the UNIX epoch the counts belong to
+    epoch: i64,
+    hits: usize,
+    misses: usize,
+}
+
+impl Window {
+    fn new(interval: Duration, intervals: usize) -> Self {
+        let interval = i64::try_from(interval.as_micros()).unwrap_or(i64::MAX).max(1);
+        Window {
+            interval,
+            buckets: vec![Bucket { epoch: -1, ..Bucket::default() }; intervals.max(1)],
+        }
+    }
+
+    /// Count a lookup at timestamp `now`
+    fn record(&mut self, now: i64, hit: bool) {
+        let epoch = now.div_euclid(self.interval);
+        let slot = epoch.rem_euclid(self.buckets.len() as i64) as usize;
+        let bucket = &mut self.buckets[slot];
+        if bucket.epoch != epoch {
+            *bucket = Bucket { epoch, ..Bucket::default() };
+        }
+        if hit {
+            bucket.hits += 1;
+        } else {
+            bucket.misses += 1;
+        }
+    }
+
+    /// The hits and misses of the intervals covering the `duration` before `now`
+    fn totals(&self, now: i64, duration: Duration) -> (usize, usize) {
+        let current = now.div_euclid(self.interval);
+        let span = i64::try_from(duration.as_micros()).unwrap_or(i64::MAX);
+        let intervals = (span / self.interval + 1).min(self.buckets.len() as i64);
+        self.buckets
+            .iter()
+            .filter(|bucket| bucket.epoch <= current && bucket.epoch > current - intervals)
+            .fold((0, 0), |(hits, misses), bucket| (hits + bucket.hits, misses + bucket.misses))
+    }
+
+    /// Forget every recorded lookup
+    fn clear(&mut self) {
+        for bucket in self.buckets.iter_mut() {
+            bucket.epoch = -1;
+        }
+    }
+}
+
+/// A point-in-time copy of the statistics of a cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StatsSnapshot {
+    hits: usize,
+    negative_hits: usize,
+    misses: usize,
+    current_size: usize,
+    evictions: usize,
+    re_misses: usize,
+    expirations: usize,
+    removals: usize,
+    inserts: usize,
+    replacements: usize,
+}
+
+impl StatsSnapshot {
     pub fn get_hits(&self) -> usize {
         self.hits
     }
 
-    #[allow(dead_code)]
+    pub fn get_negative_hits(&self) -> usize {
+        self.negative_hits
+    }
+
     pub fn get_misses(&self) -> usize {
         self.misses
     }
 
-    #[allow(dead_code)]
     pub fn get_current_size(&self) -> usize {
         self.current_size
     }
-}
\ No newline at end of file
+
+    pub fn get_evictions(&self) -> usize {
+        self.evictions
+    }
+
+    pub fn get_re_misses(&self) -> usize {
+        self.re_misses
+    }
+
+    pub fn get_expirations(&self) -> usize {
+        self.expirations
+    }
+
+    pub fn get_removals(&self) -> usize {
+        self.removals
+    }
+
+    pub fn get_inserts(&self) -> usize {
+        self.inserts
+    }
+
+    pub fn get_replacements(&self) -> usize {
+        self.replacements
+    }
+
+    /// The number of lookups, i.e. hits and misses
+    pub fn requests(&self) -> usize {
+        self.hits + self.misses
+    }
+
+    /// The share of lookups that were hits, 0 if there were no lookups
+    pub fn hit_ratio(&self) -> f64 {
+        ratio(self.hits, self.requests())
+    }
+
+    /// The share of lookups that were misses, 0 if there were no lookups
+    pub fn miss_ratio(&self) -> f64 {
+        ratio(self.misses, self.requests())
+    }
+}
+
+/// `part` as a share of `total`, 0 if `total` is 0
+fn ratio(part: usize, total: usize) -> f64 {
+    match total {
+        0 => 0.0,
+        total => part as f64 / total as f64,
+    }
+}
+
+
+#[cfg(test)]
+#[test]
+fn test_window() {
+    let second = 1_000_000;
+    let mut window = Window::new(Duration::from_secs(1), 3);
+    window.record(10 * second, true);
+    window.record(11 * second, false);
+    window.record(12 * second, true);
+    window.record(12 * second + 1, true);
+
+    assert_eq!(window.totals(12 * second, Duration::ZERO), (2, 0));
+    assert_eq!(window.totals(12 * second, Duration::from_secs(1)), (2, 1));
+    assert_eq!(window.totals(12 * second, Duration::from_secs(60)), (3, 1));
+
+    // Intervals older than the window are overwritten
+    window.record(13 * second, false);
+    assert_eq!(window.totals(13 * second, Duration::from_secs(60)), (2, 2));
+    assert_eq!(window.totals(20 * second, Duration::from_secs(60)), (0, 0));
+
+    window.clear();
+    assert_eq!(window.totals(13 * second, Duration::from_secs(60)), (0, 0));
+}
+
+#[test]
+fn test_histogram() {
+    let mut histogram = Histogram::default();
+    assert_eq!(histogram.percentile(50.0), None);
+
+    for millis in [1, 2, 3, 100] {
+        histogram.record(Duration::from_millis(millis));
+    }
+    assert_eq!(histogram.percentile(0.0), Some(Duration::from_micros(1024)));
+    assert_eq!(histogram.percentile(50.0), Some(Duration::from_micros(2048)));
+    assert_eq!(histogram.percentile(75.0), Some(Duration::from_micros(4096)));
+    assert_eq!(histogram.percentile(100.0), Some(Duration::from_micros(131_072)));
+
+    let mut other = Histogram::default();
+    other.record(Duration::ZERO);
+    other.merge(&histogram);
+    assert_eq!(other.count, 5);
+    assert_eq!(other.percentile(0.0), Some(Duration::from_micros(1)));
+}
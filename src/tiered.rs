@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+
+use crate::cache::Cache;
+
+
+/// A slower but larger store backing a `TieredCache`, e.g. a directory on disk or redis
+pub trait SecondaryStore<K, V> {
+    /// A copy of the value stored under `key`
+    fn get(&mut self, key: &K) -> Option<V>;
+
+    /// Store a value, replacing any previous value of the key
+    fn put(&mut self, key: K, value: V);
+
+    /// Remove the value stored under `key` and return it
+    fn remove(&mut self, key: &K) -> Option<V>;
+}
+
+impl<K: Hash + Eq, V: Clone, S: BuildHasher> SecondaryStore<K, V> for HashMap<K, V, S> {
+    fn get(&mut self, key: &K) -> Option<V> {
+        HashMap::get(self, key).cloned()
+    }
+
+    fn put(&mut self, key: K, value: V) {
+        self.insert(key, value);
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        HashMap::remove(self, key)
+    }
+}
+
+/// When a `TieredCache` writes values to its secondary store
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WritePolicy {
+    /// Every key lives in at most one tier: elements evicted from memory are moved to the
+    /// secondary store and values found there are moved back into memory
+    #[default]
+    Exclusive,
+    /// Every insert is written to the secondary store immediately, so it always holds every value
+    WriteThrough,
+    /// Inserts only mark the element dirty, dirty values are written to the secondary store when
+    /// they are evicted or expire, or by `flush_dirty`
+    WriteBack,
+}
+
+/// A two-tier cache that spills elements evicted from the in-memory `Cache` into a secondary
+/// store instead of dropping them.
+///
+/// A miss in memory consults the secondary store before it is reported as a miss, and a value
+/// found there is loaded back into memory. How the tiers are kept in sync is decided by the
+/// `WritePolicy`. Expired elements are dropped unless they are dirty under write-back, and the
+/// secondary store does not know about TTLs.
+///
+/// # Examples
+/// ```rust
+/// use std::collections::HashMap;
+/// use slabcache::TieredCache;
+///
+/// let mut cache = TieredCache::new(1, HashMap::new());
+/// cache.insert("foo", "bar");
+/// cache.insert("baz", "qux");
+///
+/// assert_eq!(cache.store().get("foo"), Some(&"bar"));
+/// assert_eq!(cache.get(&"foo"), Some(&"bar"));
+/// assert_eq!(cache.store().get("baz"), Some(&"qux"));
+/// ```
+pub struct TieredCache<K, V, S: SecondaryStore<K, V>> {
+    /// The in-memory tier
+    cache: Cache<K, V>,
+    /// The secondary tier receiving the elements evicted from memory
+    store: S,
+    /// When values are written to the secondary tier
+    write_policy: WritePolicy,
+}
+
+impl<K: Hash + Eq + Clone, V, S: SecondaryStore<K, V>> TieredCache<K, V, S> {
+    /// Create a cache holding `capacity` elements in memory and spilling the rest into `store`
+    ///
+    /// # Panics
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize, store: S) -> Self {
+        Self::from_cache(Cache::new(capacity), store)
+    }
+
+    /// Wrap a preconfigured in-memory cache
+    ///
+    /// Elements evicted for capacity go to `store` instead of the eviction listener of `cache`,
+    /// the listener is still notified about every other removal.
+    pub fn from_cache(mut cache: Cache<K, V>, store: S) -> Self {
+        cache.enable_spill(false);
+        TieredCache {
+            cache,
+            store,
+            write_policy: WritePolicy::default(),
+        }
+    }
+
+    /// Keep the tiers in sync according to `write_policy`, only meant to be set before the
+    /// first insert
+    pub fn write_policy(mut self, write_policy: WritePolicy) -> Self {
+        self.cache.enable_spill(write_policy == WritePolicy::WriteBack);
+        self.write_policy = write_policy;
+        self
+    }
+
+    /// Get a value from memory or, on a miss, load it from the secondary store into memory
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        if self.cache.get(key).is_none() {
+            if self.write_policy == WritePolicy::Exclusive {
+                let value = self.store.remove(key)?;
+                self.cache.insert(key.clone(), value);
+            } else {
+                let value = self.store.get(key)?;
+                self.cache.insert(key.clone(), value);
+                self.cache.mark_clean(key);
+            }
+            self.spill();
+        }
+        self.cache.peek(key)
+    }
+
+    /// Insert a value into memory and write it to the secondary store as the write policy demands
+    ///
+    /// Returns the previous value of the key. Under the exclusive policy it may come from either
+    /// tier, otherwise only the value in memory is returned.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V>
+    where
+        V: Clone,
+    {
+        let previous = match self.write_policy {
+            WritePolicy::Exclusive if self.cache.peek(&key).is_none() => {
+                let previous = self.store.remove(&key);
+                self.cache.insert(key, value);
+                previous
+            }
+            WritePolicy::Exclusive | WritePolicy::WriteBack => self.cache.insert(key, value),
+            WritePolicy::WriteThrough => {
+                self.store.put(key.clone(), value.clone());
+                let previous = self.cache.insert(key.clone(), value);
+                self.cache.mark_clean(&key);
+                previous
+            }
+        };
+        self.spill();
+        previous
+    }
+
+    /// Write every dirty value in memory to the secondary store and return how many were written
+    pub fn flush_dirty(&mut self) -> usize
+    where
+        V: Clone,
+    {
+        let store = &mut self.store;
+        self.cache.clean_dirty(|key, value| store.put(key.clone(), value.clone()))
+    }
+
+    /// Remove an element from both tiers and return its value
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let value = self.cache.remove(key);
+        if value.is_some() && self.write_policy == WritePolicy::Exclusive {
+            return value;
+        }
+        let stored = self.store.remove(key);
+        value.or(stored)
+    }
+
+    /// The in-memory tier
+    pub fn cache(&self) -> &Cache<K, V> {
+        &self.cache
+    }
+
+    /// The secondary tier
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// A mutable reference to the secondary tier
+    pub fn store_mut(&mut self) -> &mut S {
+        &mut self.store
+    }
+
+    /// Move the elements evicted from memory into the secondary store
+    fn spill(&mut self) {
+        for (key, value) in self.cache.take_spilled() {
+            self.store.put(key, value);
+        }
+    }
+}
+
+
+#[cfg(test)]
+#[test]
+fn test_tiered_spill_and_promote() {
+    let mut cache = TieredCache::new(2, HashMap::new());
+    cache.insert("key1", 1);
+    cache.insert("key2", 2);
+    cache.insert("key3", 3);
+    assert_eq!(cache.store().get("key1"), Some(&1));
+    assert_eq!(cache.cache().len(), 2);
+
+    // A miss in memory promotes the value and spills the next LRU element
+    assert_eq!(cache.get(&"key1"), Some(&1));
+    assert_eq!(cache.store().get("key1"), None);
+    assert_eq!(cache.store().get("key2"), Some(&2));
+    assert_eq!(cache.get(&"key4"), None);
+
+    // Inserting a spilled key returns its old value and moves it out of the secondary store, the
+    // tiers never hold the same key
+    assert_eq!(cache.insert("key2", 20), Some(2));
+    assert_eq!(cache.store().get("key2"), None);
+    assert_eq!(cache.remove(&"key3"), Some(3));
+    assert_eq!(cache.remove(&"key3"), None);
+}
+
+#[test]
+fn test_tiered_write_through() {
+    let mut cache = TieredCache::new(1, HashMap::new()).write_policy(WritePolicy::WriteThrough);
+    cache.insert("key1", 1);
+    assert_eq!(cache.store().get("key1"), Some(&1));
+
+    // Values stay in the secondary store when they are loaded into memory
+    cache.insert("key2", 2);
+    assert_eq!(cache.get(&"key1"), Some(&1));
+    assert_eq!(cache.store().len(), 2);
+    assert_eq!(cache.flush_dirty(), 0);
+
+    assert_eq!(cache.remove(&"key1"), Some(1));
+    assert_eq!(cache.store().get("key1"), None);
+}
+
+#[test]
+fn test_tiered_write_back() {
+    use std::time::Duration;
+
+    let mut cache = TieredCache::new(2, HashMap::new()).write_policy(WritePolicy::WriteBack);
+    cache.insert("key1", 1);
+    cache.insert("key2", 2);
+    assert!(cache.store().is_empty());
+    assert!(cache.cache().iter().all(|(_, _, metadata)| metadata.is_dirty()));
+
+    assert_eq!(cache.flush_dirty(), 2);
+    assert_eq!(cache.store().len(), 2);
+    assert_eq!(cache.flush_dirty(), 0);
+
+    // Clean values are dropped on eviction, dirty ones are written back even when they expire
+    cache.store_mut().insert("key1", 10);
+    cache.insert("key3", 3);
+    assert_eq!(cache.store().get("key1"), Some(&10));
+    cache.cache.insert_with_ttl("key4", 4, Duration::ZERO);
+    cache.insert("key5", 5);
+    assert_eq!(cache.store().get("key4"), Some(&4));
+}
+
+#[test]
+fn test_tiered_drops_expired() {
+    use std::time::Duration;
+
+    let evicted = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = evicted.clone();
+    let inner = Cache::new(1).eviction_listener(move |key, _, cause| sink.lock().unwrap().push((key, cause)));
+    let mut cache = TieredCache::from_cache(inner, HashMap::new());
+    cache.cache.insert_with_ttl("key1", 1, Duration::ZERO);
+    cache.insert("key2", 2);
+
+    assert!(cache.store().is_empty());
+    assert_eq!(*evicted.lock().unwrap(), vec![("key1", crate::cache::RemovalCause::Expired)]);
+}
@@ -0,0 +1,203 @@
+use std::collections::VecDeque;
+use std::io::{self, BufWriter, Read, Write};
+use std::sync::{Arc, Mutex};
+
+
+/// The kind of access recorded in a `TraceRecord`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccessKind {
+    /// A lookup found its key
+    Hit,
+    /// A lookup by key did not find it
+    Miss,
+    /// A value was written, either for a new key or replacing the value of a present one
+    Insert,
+}
+
+/// An access to a cache captured by a `TraceRecorder`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TraceRecord {
+    /// The hash of the key, see `Cache::hash_key`
+    pub key_hash: u64,
+    /// The time of the access as a UTC UNIX timestamp in us
+    pub timestamp: i64,
+    pub kind: AccessKind,
+}
+
+impl TraceRecord {
+    /// The size of a record in the binary format
+    pub const ENCODED_LEN: usize = 17;
+
+    /// Encode the record as the little endian key hash and timestamp followed by a byte for the
+    /// kind of access
+    pub fn encode(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut bytes = [0; Self::ENCODED_LEN];
+        bytes[..8].copy_from_slice(&self.key_hash.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.timestamp.to_le_bytes());
+        bytes[16] = match self.kind {
+            AccessKind::Hit => 0,
+            AccessKind::Miss => 1,
+            AccessKind::Insert => 2,
+        };
+        bytes
+    }
+
+    /// Decode a record written by `encode`, returning `None` for an unknown kind of access
+    pub fn decode(bytes: &[u8; Self::ENCODED_LEN]) -> Option<Self> {
+        let kind = match bytes[16] {
+            0 => AccessKind::Hit,
+            1 => AccessKind::Miss,
+            2 => AccessKind::Insert,
+            _ => return None,
+        };
+        let (hash, timestamp) = bytes[..16].split_at(8);
+        Some(TraceRecord {
+            key_hash: u64::from_le_bytes(hash.try_into().ok()?),
+            timestamp: i64::from_le_bytes(timestamp.try_into().ok()?),
+            kind,
+        })
+    }
+}
+
+/// Read the records written by a `TraceRecorder` from `reader` until it ends
+pub fn read_trace<R: Read>(mut reader: R) -> impl Iterator<Item = io::Result<TraceRecord>> {
+    std::iter::from_fn(move || {
+        let mut bytes = [0; TraceRecord::ENCODED_LEN];
+        match reader.read_exact(&mut bytes) {
+            Ok(()) => Some(
+                TraceRecord::decode(&bytes)
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unknown kind of access")),
+            ),
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => None,
+            Err(error) => Some(Err(error)),
+        }
+    })
+}
+
+/// Where a `TraceRecorder` keeps its records
+enum Destination {
+    /// The most recent records, up to the capacity of the ring
+    Ring(VecDeque<TraceRecord>, usize),
+    /// The records are encoded and written, the first error stops the recording
+    Writer(BufWriter<Box<dyn Write + Send>>, Option<io::Error>),
+}
+
+/// Records every get and insert of the cache it is attached to with `Cache::record_trace`, e.g.
+/// to replay production traffic offline with `simulate`
+///
+/// Clones share the same records, so a clone can be kept to read them while the cache holds the
+/// other one.
+///
+/// # Examples
+/// ```rust
+/// use slabcache::{AccessKind, Cache, TraceRecorder};
+///
+/// let recorder = TraceRecorder::ring(1024);
+/// let mut cache = Cache::new(16);
+/// cache.record_trace(recorder.clone());
+/// cache.insert("foo", "bar");
+/// cache.get(&"foo");
+///
+/// let kinds: Vec<AccessKind> = recorder.records().iter().map(|record| record.kind).collect();
+/// assert_eq!(kinds, [AccessKind::Insert, AccessKind::Hit]);
+/// ```
+#[derive(Clone)]
+pub struct TraceRecorder {
+    destination: Arc<Mutex<Destination>>,
+}
+
+impl TraceRecorder {
+    /// Keep the last `capacity` records in memory
+    pub fn ring(capacity: usize) -> Self {
+        TraceRecorder::new(Destination::Ring(VecDeque::with_capacity(capacity), capacity))
+    }
+
+    /// Write the records to `writer` in the binary format of `TraceRecord::encode`
+    ///
+    /// Writes are buffered, `flush` or dropping the last clone of the recorder writes the rest.
+    pub fn writer<W: Write + Send + 'static>(writer: W) -> Self {
+        TraceRecorder::new(Destination::Writer(BufWriter::new(Box::new(writer)), None))
+    }
+
+    fn new(destination: Destination) -> Self {
+        TraceRecorder { destination: Arc::new(Mutex::new(destination)) }
+    }
+
+    /// Record an access
+    pub fn record(&self, record: TraceRecord) {
+        match &mut *self.lock() {
+            Destination::Ring(records, capacity) => {
+                if records.len() == *capacity {
+                    records.pop_front();
+                }
+                if *capacity > 0 {
+                    records.push_back(record);
+                }
+            }
+            Destination::Writer(writer, error @ None) => {
+                if let Err(write_error) = writer.write_all(&record.encode()) {
+                    *error = Some(write_error);
+                }
+            }
+            Destination::Writer(_, Some(_)) => {}
+        }
+    }
+
+    /// The records kept in memory from the oldest to the newest, always empty for a writer
+    pub fn records(&self) -> Vec<TraceRecord> {
+        match &*self.lock() {
+            Destination::Ring(records, _) => records.iter().copied().collect(),
+            Destination::Writer(..) => Vec::new(),
+        }
+    }
+
+    /// Write the buffered records, returning the error that stopped the recording if any
+    pub fn flush(&self) -> io::Result<()> {
+        match &mut *self.lock() {
+            Destination::Ring(..) => Ok(()),
+            Destination::Writer(_, Some(error)) => Err(io::Error::new(error.kind(), error.to_string())),
+            Destination::Writer(writer, None) => writer.flush(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Destination> {
+        // A panic while recording leaves the records usable
+        self.destination.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+
+#[cfg(test)]
+#[test]
+fn test_trace_recorder() {
+    use crate::cache::Cache;
+    use crate::clock::MockClock;
+
+    let ring = TraceRecorder::ring(3);
+    let mut cache = Cache::new(2).clock(MockClock::at(7));
+    cache.record_trace(ring.clone());
+    cache.insert("key1", 1);
+    cache.get(&"key1");
+    cache.get(&"key2");
+    cache.insert("key1", 10);
+    let records = ring.records();
+    let kinds: Vec<AccessKind> = records.iter().map(|record| record.kind).collect();
+    assert_eq!(kinds, [AccessKind::Hit, AccessKind::Miss, AccessKind::Insert]);
+    assert_eq!(records[0], TraceRecord { key_hash: cache.hash_key("key1"), timestamp: 7, kind: AccessKind::Hit });
+    assert_eq!(records[1].key_hash, cache.hash_key("key2"));
+
+    // The binary format round trips through a writer
+    let path = std::env::temp_dir().join(format!("slabcache-trace-{}", std::process::id()));
+    let writer = TraceRecorder::writer(std::fs::File::create(&path).unwrap());
+    cache.record_trace(writer.clone());
+    cache.get(&"key1");
+    cache.insert("key3", 3);
+    assert!(cache.stop_trace().is_some());
+    cache.get(&"key3");
+    writer.flush().unwrap();
+    let read: Vec<TraceRecord> = read_trace(std::fs::File::open(&path).unwrap()).map(Result::unwrap).collect();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(read.iter().map(|record| record.kind).collect::<Vec<_>>(), [AccessKind::Hit, AccessKind::Insert]);
+    assert_eq!(ring.records().len(), 3);
+    assert!(TraceRecord::decode(&[0xff; TraceRecord::ENCODED_LEN]).is_none());
+}